@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS source_song;
+DROP TABLE IF EXISTS song;
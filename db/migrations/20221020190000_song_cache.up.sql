@@ -0,0 +1,18 @@
+-- Cached song metadata, so sources don't need to be refetched on every startup
+CREATE TABLE IF NOT EXISTS song (
+    id INTEGER PRIMARY KEY NOT NULL,
+    path TEXT UNIQUE NOT NULL,    -- url or local file path
+    title TEXT NOT NULL,
+    artist TEXT NOT NULL,
+    thumbnail_url TEXT,
+    duration INTEGER NOT NULL,
+    available INTEGER NOT NULL    -- boolean, false if the song is no longer in any source
+);
+
+-- Which songs were in a source as of the last refresh
+CREATE TABLE IF NOT EXISTS source_song (
+    id INTEGER PRIMARY KEY NOT NULL,
+    source_id INTEGER NOT NULL REFERENCES source(id) ON DELETE CASCADE,
+    song_id INTEGER NOT NULL REFERENCES song(id) ON DELETE CASCADE,
+    UNIQUE(source_id, song_id)
+);
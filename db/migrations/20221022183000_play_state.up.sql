@@ -0,0 +1,12 @@
+-- Snapshot of the now playing track, queue and history, so they survive a restart
+CREATE TABLE IF NOT EXISTS play_state (
+    id INTEGER PRIMARY KEY NOT NULL,
+    list INTEGER NOT NULL,        -- enum, now playing/queue/history
+    position INTEGER NOT NULL,    -- order within the list
+    song_id INTEGER NOT NULL REFERENCES song(id) ON DELETE CASCADE,
+    -- Requester is stored as-is, not every requester has a user row (e.g. anonymous web requests)
+    requester_id INTEGER NOT NULL,
+    requester_name TEXT NOT NULL,
+    requester_icon TEXT NOT NULL,
+    progress INTEGER NOT NULL     -- seconds into the song, only meaningful for now playing
+);
@@ -0,0 +1,7 @@
+-- Autoplay fairness scores (seconds of music played), so a restart doesn't reset everyone
+-- No foreign key on user_id, anonymous requesters can end up with a score too
+CREATE TABLE IF NOT EXISTS autoplay_score (
+    user_id INTEGER PRIMARY KEY NOT NULL,
+    score INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL   -- unix timestamp, used to decay old scores
+);
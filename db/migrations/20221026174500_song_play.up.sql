@@ -0,0 +1,6 @@
+-- How often and how recently each song was played, used to weight autoplay shuffling
+CREATE TABLE IF NOT EXISTS song_play (
+    song_id INTEGER PRIMARY KEY NOT NULL REFERENCES song(id) ON DELETE CASCADE,
+    play_count INTEGER NOT NULL,
+    last_played INTEGER NOT NULL  -- unix timestamp
+);
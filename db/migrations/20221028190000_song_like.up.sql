@@ -0,0 +1,8 @@
+-- Songs a user has liked, these can be used as an autoplay source
+CREATE TABLE IF NOT EXISTS song_like (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL REFERENCES user(id) ON DELETE CASCADE,
+    song_id INTEGER NOT NULL REFERENCES song(id) ON DELETE CASCADE,
+    liked_at INTEGER NOT NULL,    -- unix timestamp
+    UNIQUE(user_id, song_id)
+);
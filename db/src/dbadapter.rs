@@ -1,10 +1,16 @@
 use std::collections::{
+    BTreeMap,
     HashMap,
-    hash_map::Entry,
+    HashSet,
+};
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
 };
 
 use minstrelmodel::MinstrelUserId;
 use sqlx::SqlitePool;
+use sqlx::sqlite::SqlitePoolOptions;
 use crate::model::*;
 
 pub async fn init_db() -> DbAdapter {
@@ -18,6 +24,54 @@ pub async fn init_db() -> DbAdapter {
     DbAdapter::new(db)
 }
 
+/// Throwaway in-memory database, mostly for tests.
+// Every connection to :memory: gets its own database, so only allow one
+pub async fn init_memory_db() -> DbAdapter {
+    let db = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+
+    sqlx::migrate!().run(&db).await.unwrap();
+
+    DbAdapter::new(db)
+}
+
+/// Convert a source row into the model type, skipping (and complaining about) unknown source types
+fn convert_source(row: Source) -> Option<minstrelmodel::Source> {
+    let id = row.id;
+
+    match minstrelmodel::Source::try_from(row) {
+        Ok(src) => Some(src),
+        Err(srctype) => {
+            log::warn!("source {} has an unknown source_type {}, ignoring", id, srctype);
+            None
+        }
+    }
+}
+
+/// Insert or refresh a song's metadata, shared between the standalone and transaction paths
+async fn upsert_song<'e, E>(executor: E, song: &minstrelmodel::Song) -> Result<i64, sqlx::Error>
+    where E: sqlx::Executor<'e, Database = sqlx::Sqlite>
+{
+    let thumbnail = (!song.thumbnail.is_empty()).then(|| &song.thumbnail);
+
+    let row = sqlx::query!(r#"INSERT INTO song (path, title, artist, thumbnail_url, duration, available)
+        VALUES (?, ?, ?, ?, ?, TRUE)
+        ON CONFLICT(path) DO UPDATE SET
+            title = excluded.title,
+            artist = excluded.artist,
+            thumbnail_url = excluded.thumbnail_url,
+            duration = excluded.duration,
+            available = TRUE
+        RETURNING id"#,
+        song.url, song.title, song.artist, thumbnail, song.duration)
+        .fetch_one(executor).await?;
+
+    Ok(row.id)
+}
+
 pub type UserId = i64;
 pub type DiscordId = String;
 pub type SourceId = i64;
@@ -46,18 +100,22 @@ impl DbAdapter {
 
     /// Get all userids and their associated sources
     /// TODO: eventually probably don't use this, this is mostly for autoplay refactoring
-    pub async fn get_active_sources(&self) -> Result<HashMap<MinstrelUserId, Vec<minstrelmodel::Source>>, ()> {
-        let resp = sqlx::query_as!(Source, r#"SELECT * FROM source WHERE active = TRUE"#)
+    /// Every user's active sources, in a stable order (by user, then by when the source was added)
+    pub async fn get_active_sources(&self) -> Result<BTreeMap<MinstrelUserId, Vec<minstrelmodel::Source>>, ()> {
+        let resp = sqlx::query_as!(Source, r#"SELECT * FROM source WHERE active = TRUE ORDER BY user_id, id"#)
             .fetch_all(&self.db).await;
 
         let resp = resp.unwrap();
 
-        let mut ret: HashMap<i64, Vec<minstrelmodel::Source>> = HashMap::new();
+        let mut ret: BTreeMap<i64, Vec<minstrelmodel::Source>> = BTreeMap::new();
         for row in resp {
-            match ret.entry(row.user_id) {
-                Entry::Occupied(mut e) => { e.get_mut().push(row.into()); },
-                Entry::Vacant(e)   => { e.insert(vec![row.into()]); },
-            }
+            let user_id = row.user_id;
+            let src = match convert_source(row) {
+                Some(src) => src,
+                None => continue,
+            };
+
+            ret.entry(user_id).or_default().push(src);
         }
 
         Ok(ret)
@@ -72,15 +130,13 @@ impl DbAdapter {
         };
 
         let mut resp = resp.unwrap();
-        let resp = resp.drain(..).map(|e| e.into()).collect();
+        let resp = resp.drain(..).filter_map(convert_source).collect();
 
         Ok(resp)
     }
 
     pub async fn create_source(&self, user_id: MinstrelUserId, srctype: &minstrelmodel::SourceType, active: bool) -> Result<(),()> {
-        let (path, srctype) = match srctype {
-            minstrelmodel::SourceType::YoutubePlaylist(path) => (path, 1), // TODO: actually implement a source enum
-        };
+        let (path, srctype) = source_type_to_row(srctype);
 
         let resp = sqlx::query!("INSERT INTO source (path, active, source_type, user_id) VALUES (?, ?, ?, ?)",
             path, active, srctype, user_id).execute(&self.db).await;
@@ -107,7 +163,7 @@ impl DbAdapter {
         let resp = sqlx::query!("SELECT * FROM user WHERE user.id = ?", muid)
             .fetch_one(&self.db).await;
 
-        let resp = resp.unwrap();
+        let resp = resp.map_err(|_| ())?;
 
         Ok(minstrelmodel::Requester {
             displayname: resp.displayname,
@@ -247,4 +303,282 @@ impl DbAdapter {
             Err(_) => Err(()),
         }
     }
+
+    /// Insert a song into the cache, or refresh the metadata of the song with the same path
+    pub async fn create_song(&self, song: &minstrelmodel::Song) -> Result<i64, ()> {
+        let resp = upsert_song(&self.db, song).await;
+
+        match resp {
+            Ok(id) => Ok(id),
+            Err(e) => {
+                log::error!("failed to cache song {}: {:?}", song.url, e);
+                Err(())
+            }
+        }
+    }
+
+    /// Look up a cached song by its url/path, unavailable songs are not returned
+    pub async fn get_song_by_path(&self, path: &str) -> Result<Option<minstrelmodel::Song>, ()> {
+        let resp = sqlx::query_as!(Song, "SELECT * FROM song WHERE path = ? AND available = TRUE", path)
+            .fetch_optional(&self.db).await;
+
+        match resp {
+            Ok(row) => Ok(row.map(|s| s.into())),
+            Err(_) => Err(()),
+        }
+    }
+
+    /// Get the cached songs for a source, as of the last time it was refreshed
+    pub async fn get_songs_from_source(&self, source_id: SourceId) -> Result<Vec<minstrelmodel::Song>, ()> {
+        let resp = sqlx::query_as!(Song, r#"SELECT song.* FROM song
+            INNER JOIN source_song ON source_song.song_id = song.id
+            WHERE source_song.source_id = ? AND song.available = TRUE"#, source_id)
+            .fetch_all(&self.db).await;
+
+        match resp {
+            Ok(rows) => Ok(rows.into_iter().map(|s| s.into()).collect()),
+            Err(_) => Err(()),
+        }
+    }
+
+    /// Replace the cached contents of a source with a freshly fetched list of songs.
+    /// Only new or changed songs get written, returns the number of songs (added, removed).
+    pub async fn update_source_songs(&self, source_id: SourceId, songs: &[minstrelmodel::Song]) -> Result<(usize, usize), ()> {
+        let mut tx = self.db.begin().await.map_err(|_| ())?;
+
+        let existing = sqlx::query_as!(Song, r#"SELECT song.* FROM song
+            INNER JOIN source_song ON source_song.song_id = song.id
+            WHERE source_song.source_id = ?"#, source_id)
+            .fetch_all(&mut tx).await.map_err(|_| ())?;
+        let existing: HashMap<String, minstrelmodel::Song> = existing.into_iter()
+            .map(|s| (s.path.clone(), s.into()))
+            .collect();
+        let current: HashSet<&String> = songs.iter().map(|s| &s.url).collect();
+
+        let mut added = 0;
+        for song in songs {
+            match existing.get(&song.url) {
+                // Already in the source and nothing changed, nothing to write
+                Some(cached) if cached == song => (),
+                // Metadata can change (renamed videos, retagged files)
+                Some(_) => {
+                    upsert_song(&mut tx, song).await.map_err(|_| ())?;
+                },
+                None => {
+                    let song_id = upsert_song(&mut tx, song).await.map_err(|_| ())?;
+                    sqlx::query!("INSERT OR IGNORE INTO source_song (source_id, song_id) VALUES (?, ?)", source_id, song_id)
+                        .execute(&mut tx).await.map_err(|_| ())?;
+                    added += 1;
+                },
+            }
+        }
+
+        let mut removed = 0;
+        for path in existing.keys().filter(|p| !current.contains(p)) {
+            sqlx::query!(r#"DELETE FROM source_song
+                WHERE source_id = ? AND song_id = (SELECT id FROM song WHERE path = ?)"#, source_id, path)
+                .execute(&mut tx).await.map_err(|_| ())?;
+
+            // Keep the metadata around, but don't hand out songs that aren't in any source anymore.
+            // Liked songs stay available, as they're still in their liked songs
+            sqlx::query!(r#"UPDATE song SET available = FALSE WHERE path = ?
+                AND NOT EXISTS (SELECT 1 FROM source_song WHERE source_song.song_id = song.id)
+                AND NOT EXISTS (SELECT 1 FROM song_like WHERE song_like.song_id = song.id)"#, path)
+                .execute(&mut tx).await.map_err(|_| ())?;
+            removed += 1;
+        }
+
+        tx.commit().await.map_err(|_| ())?;
+
+        Ok((added, removed))
+    }
+
+    /// Replace the saved now playing track and how far into it we are
+    pub async fn update_play_state_current(&self, current: Option<(&minstrelmodel::SongRequest, u64)>) -> Result<(), ()> {
+        self.replace_play_state_list(PLAY_STATE_CURRENT, current).await
+    }
+
+    /// Replace the saved queue, leaving the now playing track and history alone
+    pub async fn update_play_state_queue<'a, Q>(&self, queue: Q) -> Result<(), ()>
+        where Q: IntoIterator<Item = &'a minstrelmodel::SongRequest>
+    {
+        self.replace_play_state_list(PLAY_STATE_QUEUE, queue.into_iter().map(|s| (s, 0))).await
+    }
+
+    /// Replace the saved history, leaving the now playing track and queue alone
+    pub async fn update_play_state_history<'a, H>(&self, history: H) -> Result<(), ()>
+        where H: IntoIterator<Item = &'a minstrelmodel::SongRequest>
+    {
+        self.replace_play_state_list(PLAY_STATE_HISTORY, history.into_iter().map(|s| (s, 0))).await
+    }
+
+    // Rewrite just the rows of one list, as (request, progress) in order
+    async fn replace_play_state_list<'a, L>(&self, list: i64, requests: L) -> Result<(), ()>
+        where L: IntoIterator<Item = (&'a minstrelmodel::SongRequest, u64)>
+    {
+        let mut tx = self.db.begin().await.map_err(|_| ())?;
+
+        sqlx::query!("DELETE FROM play_state WHERE list = ?", list).execute(&mut tx).await.map_err(|_| ())?;
+
+        for (position, (req, progress)) in requests.into_iter().enumerate() {
+            let song_id = upsert_song(&mut tx, &req.song).await.map_err(|_| ())?;
+            let position = position as i64;
+            let progress = progress as i64;
+
+            sqlx::query!(r#"INSERT INTO play_state (list, position, song_id, requester_id, requester_name, requester_icon, progress)
+                VALUES (?, ?, ?, ?, ?, ?, ?)"#,
+                list, position, song_id, req.requested_by.id, req.requested_by.displayname, req.requested_by.icon, progress)
+                .execute(&mut tx).await.map_err(|_| ())?;
+        }
+
+        tx.commit().await.map_err(|_| ())
+    }
+
+    /// Only update how far into the now playing track we are
+    pub async fn update_play_state_progress(&self, progress: u64) -> Result<(), ()> {
+        let progress = progress as i64;
+
+        sqlx::query!("UPDATE play_state SET progress = ? WHERE list = ?", progress, PLAY_STATE_CURRENT)
+            .execute(&self.db).await.map_err(|_| ())?;
+
+        Ok(())
+    }
+
+    /// Get the saved (now playing + progress, queue, history)
+    #[allow(clippy::type_complexity)]
+    pub async fn get_play_state(&self) -> Result<(Option<(minstrelmodel::SongRequest, u64)>, Vec<minstrelmodel::SongRequest>, Vec<minstrelmodel::SongRequest>), ()> {
+        let rows = sqlx::query!(r#"SELECT play_state.list, play_state.requester_id, play_state.requester_name,
+                play_state.requester_icon, play_state.progress,
+                song.path, song.title, song.artist, song.thumbnail_url, song.duration
+            FROM play_state
+            INNER JOIN song ON song.id = play_state.song_id
+            ORDER BY play_state.list, play_state.position"#)
+            .fetch_all(&self.db).await.map_err(|_| ())?;
+
+        let mut current = None;
+        let mut queue = Vec::new();
+        let mut history = Vec::new();
+
+        for row in rows {
+            let req = minstrelmodel::SongRequest::new(
+                minstrelmodel::Song {
+                    title: row.title,
+                    artist: row.artist,
+                    url: row.path,
+                    thumbnail: row.thumbnail_url.unwrap_or_else(|| "".into()),
+                    duration: row.duration,
+                },
+                minstrelmodel::Requester {
+                    displayname: row.requester_name,
+                    icon: row.requester_icon,
+                    id: row.requester_id,
+                },
+            );
+
+            match row.list {
+                PLAY_STATE_CURRENT => current = Some((req, row.progress as u64)),
+                PLAY_STATE_QUEUE => queue.push(req),
+                PLAY_STATE_HISTORY => history.push(req),
+                unknown => log::warn!("play_state row has an unknown list {}, ignoring", unknown),
+            }
+        }
+
+        Ok((current, queue, history))
+    }
+
+    /// Get every saved autoplay score, as (user, score, unix time it was saved)
+    pub async fn get_autoplay_scores(&self) -> Result<Vec<(MinstrelUserId, i64, i64)>, ()> {
+        let rows = sqlx::query!("SELECT user_id, score, updated_at FROM autoplay_score")
+            .fetch_all(&self.db).await.map_err(|_| ())?;
+
+        Ok(rows.into_iter().map(|r| (r.user_id, r.score, r.updated_at)).collect())
+    }
+
+    /// Save the current autoplay scores, stamped with the current time
+    pub async fn update_autoplay_scores(&self, scores: &HashMap<MinstrelUserId, i64>) -> Result<(), ()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|_| ())?.as_secs() as i64;
+        let mut tx = self.db.begin().await.map_err(|_| ())?;
+
+        for (user_id, score) in scores {
+            sqlx::query!(r#"INSERT INTO autoplay_score (user_id, score, updated_at) VALUES (?, ?, ?)
+                ON CONFLICT(user_id) DO UPDATE SET score = excluded.score, updated_at = excluded.updated_at"#,
+                user_id, score, now)
+                .execute(&mut tx).await.map_err(|_| ())?;
+        }
+
+        tx.commit().await.map_err(|_| ())
+    }
+
+    /// Count a play of a song, songs that aren't in the song cache are not tracked
+    pub async fn record_song_play(&self, path: &str) -> Result<(), ()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|_| ())?.as_secs() as i64;
+
+        let resp = sqlx::query!(r#"INSERT INTO song_play (song_id, play_count, last_played)
+            SELECT id, 1, ? FROM song WHERE path = ?
+            ON CONFLICT(song_id) DO UPDATE SET
+                play_count = play_count + 1,
+                last_played = excluded.last_played"#,
+            now, path)
+            .execute(&self.db).await;
+
+        match resp {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                log::error!("failed to record play of {}: {:?}", path, e);
+                Err(())
+            }
+        }
+    }
+
+    /// Get the play count and last played time of every song that has been played, keyed by path
+    pub async fn get_song_plays(&self) -> Result<HashMap<String, (i64, i64)>, ()> {
+        let rows = sqlx::query!(r#"SELECT song.path, song_play.play_count, song_play.last_played
+            FROM song_play
+            INNER JOIN song ON song.id = song_play.song_id"#)
+            .fetch_all(&self.db).await.map_err(|_| ())?;
+
+        Ok(rows.into_iter().map(|r| (r.path, (r.play_count, r.last_played))).collect())
+    }
+
+    /// Like a song for a user, caching the song if needed. Returns false if it was already liked
+    pub async fn create_song_like(&self, user_id: MinstrelUserId, song: &minstrelmodel::Song) -> Result<bool, ()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_err(|_| ())?.as_secs() as i64;
+        let mut tx = self.db.begin().await.map_err(|_| ())?;
+
+        let song_id = upsert_song(&mut tx, song).await.map_err(|_| ())?;
+
+        let resp = sqlx::query!("INSERT OR IGNORE INTO song_like (user_id, song_id, liked_at) VALUES (?, ?, ?)",
+            user_id, song_id, now)
+            .execute(&mut tx).await.map_err(|_| ())?;
+
+        tx.commit().await.map_err(|_| ())?;
+
+        Ok(resp.rows_affected() > 0)
+    }
+
+    /// Unlike a song for a user, returns false if it wasn't liked
+    pub async fn delete_song_like(&self, user_id: MinstrelUserId, path: &str) -> Result<bool, ()> {
+        let resp = sqlx::query!(r#"DELETE FROM song_like
+            WHERE user_id = ? AND song_id = (SELECT id FROM song WHERE path = ?)"#, user_id, path)
+            .execute(&self.db).await;
+
+        match resp {
+            Ok(r) => Ok(r.rows_affected() > 0),
+            Err(_) => Err(()),
+        }
+    }
+
+    /// Get every song a user has liked, oldest like first
+    pub async fn get_liked_songs(&self, user_id: MinstrelUserId) -> Result<Vec<minstrelmodel::Song>, ()> {
+        let resp = sqlx::query_as!(Song, r#"SELECT song.* FROM song
+            INNER JOIN song_like ON song_like.song_id = song.id
+            WHERE song_like.user_id = ?
+            ORDER BY song_like.liked_at, song_like.id"#, user_id)
+            .fetch_all(&self.db).await;
+
+        match resp {
+            Ok(rows) => Ok(rows.into_iter().map(|s| s.into()).collect()),
+            Err(_) => Err(()),
+        }
+    }
  }
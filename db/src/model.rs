@@ -32,13 +32,40 @@ pub struct Source {
     pub user_id: i64,     // Points to User
 }
 
-impl From<Source> for minstrelmodel::Source {
-    fn from(src: Source) -> Self {
-        // TODO: match on row.source_type
-        minstrelmodel::Source {
+// Values stored in source.source_type
+pub const SOURCE_YOUTUBE_PLAYLIST: i64 = 1;
+pub const SOURCE_LOCAL_DIRECTORY: i64 = 2;
+pub const SOURCE_PLAYLIST_FILE: i64 = 3;
+pub const SOURCE_LIKED_SONGS: i64 = 4;
+
+/// Split a SourceType into the path and source_type columns
+pub fn source_type_to_row(srctype: &minstrelmodel::SourceType) -> (&str, i64) {
+    match srctype {
+        minstrelmodel::SourceType::YoutubePlaylist(path) => (path, SOURCE_YOUTUBE_PLAYLIST),
+        minstrelmodel::SourceType::LocalDirectory(path) => (path, SOURCE_LOCAL_DIRECTORY),
+        minstrelmodel::SourceType::PlaylistFile(path) => (path, SOURCE_PLAYLIST_FILE),
+        // Liked songs come from the owner's likes, there's no path to store
+        minstrelmodel::SourceType::LikedSongs => ("", SOURCE_LIKED_SONGS),
+    }
+}
+
+/// Fails with the unknown source_type value if the row can't be mapped
+impl TryFrom<Source> for minstrelmodel::Source {
+    type Error = i64;
+
+    fn try_from(src: Source) -> Result<Self, Self::Error> {
+        let path = match src.source_type {
+            SOURCE_YOUTUBE_PLAYLIST => minstrelmodel::SourceType::YoutubePlaylist(src.path),
+            SOURCE_LOCAL_DIRECTORY => minstrelmodel::SourceType::LocalDirectory(src.path),
+            SOURCE_PLAYLIST_FILE => minstrelmodel::SourceType::PlaylistFile(src.path),
+            SOURCE_LIKED_SONGS => minstrelmodel::SourceType::LikedSongs,
+            unknown => return Err(unknown),
+        };
+
+        Ok(minstrelmodel::Source {
             id: src.id,
-            path: minstrelmodel::SourceType::YoutubePlaylist(src.path)
-        }
+            path,
+        })
     }
 }
 
@@ -66,7 +93,12 @@ impl From<Song> for minstrelmodel::Song {
     }
 }
 
-// TODO: PlaylistCache, ThumbnailCache
+// Values stored in play_state.list
+pub const PLAY_STATE_CURRENT: i64 = 0;
+pub const PLAY_STATE_QUEUE: i64 = 1;
+pub const PLAY_STATE_HISTORY: i64 = 2;
+
+// TODO: ThumbnailCache
 
 
 #[cfg(test)]
@@ -86,6 +118,7 @@ mod tests {
         sqlx::query_as!(UserAuth, "SELECT * FROM user_auth").fetch_optional(db).await.unwrap();
         sqlx::query_as!(DiscordUser, "SELECT * FROM discord_user").fetch_optional(db).await.unwrap();
         sqlx::query_as!(Source, "SELECT * FROM source").fetch_optional(db).await.unwrap();
+        sqlx::query_as!(Song, "SELECT * FROM song").fetch_optional(db).await.unwrap();
 
     }
 }
\ No newline at end of file
@@ -21,7 +21,10 @@ use serenity::{
     async_trait,
     client::ClientBuilder,
     model::{
-        channel::Message,
+        channel::{
+            Message,
+            Reaction,
+        },
         gateway::Ready,
         id::GuildId,
         voice::VoiceState,
@@ -97,6 +100,14 @@ impl EventHandler for Handler {
 
     }
 
+    async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        handle_like_reaction(&ctx, &reaction, true).await;
+    }
+
+    async fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        handle_like_reaction(&ctx, &reaction, false).await;
+    }
+
     async fn voice_state_update(&self, ctx: Context, old: Option<VoiceState>, new: VoiceState) {
         // TODO: maybe factor out common useful values like, botid, guild, etc
 
@@ -121,6 +132,7 @@ impl EventHandler for Handler {
         }
 
         last_one_in_checker(&ctx, &new.guild_id, &old, &new).await;
+        update_listeners(&ctx, new.guild_id).await;
         autoplay_voice_state_update(ctx, new.guild_id, old, new).await;
     }
 }
@@ -247,6 +259,7 @@ pub async fn create_player(mstate: MusicAdapter, dplayer: Arc<Mutex<DiscordPlaye
         | GatewayIntents::GUILDS
         | GatewayIntents::GUILD_VOICE_STATES
         | GatewayIntents::GUILD_MESSAGES
+        | GatewayIntents::GUILD_MESSAGE_REACTIONS
         | GatewayIntents::MESSAGE_CONTENT;
     let client =
         Client::builder(&token, intents)
@@ -262,25 +275,56 @@ pub async fn create_player(mstate: MusicAdapter, dplayer: Arc<Mutex<DiscordPlaye
 
     let http = client.cache_and_http.clone();
     let mut rx = mstate.subscribe();
-    let mut mstate = mstate;
     tokio::spawn(async move {
+        // Kept up to date from the broadcast events, rather than asking for everything on every change
+        let mut data = mstate.get_webdata().await;
+
         loop {
-            match rx.recv().await {
-                Ok(MinstrelBroadcast::MusicState(data)) => {
-                    let dstate = dstate.lock().await;
-
-                    if let Some(sticky) = &dstate.sticky {
-                        let qs_embed = get_queuestate_embed(&data, mstate.autoplay.is_enabled().await);
-                        let np_embed = get_nowplay_embed(&data);
-
-                        sticky.channel_id.edit_message(&http.http, sticky, |m| {
-                            m.set_embeds(vec![qs_embed, np_embed])
-                        }).await.unwrap();
-                    }
-                },
-                // TODO: ignore broadcasted errors for now, perhaps these should be reported to a default channel
-                Ok(MinstrelBroadcast::Error(_)) => (),
-                Err(e) => error!("Error in discord broadcast handler: {e:?}"),
+            let mut recv = rx.recv().await;
+            let mut changed = false;
+            let mut missed = false;
+
+            loop {
+                match recv {
+                    Ok(MinstrelBroadcast::MusicState(new)) => {
+                        data = new;
+                        changed = true;
+                    },
+                    Ok(MinstrelBroadcast::Event { seq, event }) => {
+                        missed |= !data.apply(seq, event);
+                        changed = true;
+                    },
+                    // TODO: ignore broadcasted errors for now, perhaps these should be reported to a default channel
+                    Ok(MinstrelBroadcast::Error(_)) => (),
+                    // Any events lost to lagging show up as a gap above
+                    Err(e) => error!("Error in discord broadcast handler: {e:?}"),
+                }
+
+                // Apply anything else that came in at the same time, so the sticky is only edited once
+                recv = match rx.try_recv() {
+                    Ok(msg) => Ok(msg),
+                    Err(_) => break,
+                };
+            }
+
+            if missed {
+                debug!("missed a broadcast event, fetching the whole state again");
+                data = mstate.get_webdata().await;
+            }
+
+            if !changed {
+                continue;
+            }
+
+            let dstate = dstate.lock().await;
+
+            if let Some(sticky) = &dstate.sticky {
+                let qs_embed = get_queuestate_embed(&data, data.ap_enabled);
+                let np_embed = get_nowplay_embed(&data);
+
+                sticky.channel_id.edit_message(&http.http, sticky, |m| {
+                    m.set_embeds(vec![qs_embed, np_embed])
+                }).await.unwrap();
             }
         }
     });
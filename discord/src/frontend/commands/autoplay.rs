@@ -17,13 +17,14 @@ use crate::{get_mstate, join_voice};
 use crate::userconv::*;
 use crate::helpers::check_msg;
 use music::MusicError;
+use minstrel_config::AutoplayStrategyKind;
 
 use crate::helpers::*;
 
 #[group]
 #[description = "Commands to manage autoplay state"]
 #[prefixes("autoplay", "ap")]
-#[commands(toggle, upcoming, enrolluser, removeuser, rebalance, shuffle, dump, advance)]
+#[commands(toggle, upcoming, enrolluser, removeuser, rebalance, scores, strategy, seed, shuffle, dump, advance)]
 struct AutoplayCmd;
 
 
@@ -63,7 +64,7 @@ async fn toggle(ctx: &Context, msg: &Message) -> CommandResult {
 
     match ret {
         Err(MusicError::AlreadyPlaying) => (), // Suppress AlreadyPlaying, doesn't matter here
-        Err(e) => check_msg(msg.channel_id.say(&ctx.http, format!("Error starting autoplay: {:?}", e)).await),
+        Err(e) => check_msg(msg.channel_id.say(&ctx.http, format!("Error starting autoplay: {}", e)).await),
         _ => (),
     };
 
@@ -107,7 +108,7 @@ async fn enrolluser(ctx: &Context, msg: &Message) -> CommandResult {
 
     let ret = match mstate.autoplay.enable_user(&mstate.muid_from_userid(&msg.author.id).await).await {
         Ok(m) => m.to_string(),
-        Err(e) => format!("Error enabling user: {:?}", e),
+        Err(e) => format!("Error enabling user: {}", e),
     };
 
     check_msg(msg.channel_id.say(&ctx.http, ret).await);
@@ -123,7 +124,7 @@ async fn removeuser(ctx: &Context, msg: &Message) -> CommandResult {
 
     let ret = match mstate.autoplay.disable_user(&mstate.muid_from_userid(&msg.author.id).await).await {
         Ok(m) => m.to_string(),
-        Err(e) => format!("Error disabling user: {:?}", e),
+        Err(e) => format!("Error disabling user: {}", e),
     };
 
     check_msg(msg.channel_id.say(&ctx.http, ret).await);
@@ -134,14 +135,116 @@ async fn removeuser(ctx: &Context, msg: &Message) -> CommandResult {
 
 #[command]
 #[only_in(guilds)]
-#[checks(in_same_voice)]
-// TODO: require permissions for this
+#[checks(in_same_voice, is_admin)]
 async fn rebalance(ctx: &Context, msg: &Message) -> CommandResult {
     get_mstate!(mut, mstate, ctx);
 
-    mstate.autoplay.reset_usertime().await;
+    let ret = match mstate.autoplay.reset_scores().await {
+        Ok(m) => m.to_string(),
+        Err(e) => format!("Error resetting scores: {}", e),
+    };
+
+    check_msg(msg.channel_id.say(&ctx.http, ret).await);
+
+    Ok(())
+}
+
+
+#[command]
+#[only_in(guilds)]
+async fn scores(ctx: &Context, msg: &Message) -> CommandResult {
+    get_mstate!(mut, mstate, ctx);
+
+    let scores = match mstate.autoplay.get_scores().await {
+        Ok(s) => s,
+        Err(e) => {
+            check_msg(msg.channel_id.say(&ctx.http, format!("Error getting scores: {}", e)).await);
+            return Ok(())
+        }
+    };
+
+    let mut ret = String::from("Autoplay scores (lowest plays next):\n");
+    for s in scores {
+        ret += &format!("{}: {}s{}\n", s.displayname, s.score, if s.enabled { "" } else { " (not enrolled)" });
+    }
+
+    check_msg(msg.channel_id.say(&ctx.http, ret).await);
+
+    Ok(())
+}
+
+
+#[command]
+#[only_in(guilds)]
+#[min_args(0)]
+#[max_args(1)]
+async fn strategy(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    get_mstate!(mut, mstate, ctx);
+
+    // No argument just shows the current strategy
+    if args.is_empty() {
+        check_msg(msg.channel_id.say(&ctx.http, match mstate.autoplay.get_strategy().await {
+            Ok(s) => format!("Autoplay strategy is {}.", s),
+            Err(e) => format!("Error getting strategy: {}", e),
+        }).await);
+        return Ok(())
+    }
+
+    // Anyone can look, but only admins can change it
+    match mstate.db.get_userid_from_discordid(msg.author.id.0).await {
+        Ok(Some(muid)) if mstate.user.is_admin(muid) => (),
+        _ => {
+            check_msg(msg.channel_id.say(&ctx.http, "Only admins can change the autoplay strategy.").await);
+            return Ok(())
+        }
+    }
+
+    let kind = match args.single::<String>()?.parse::<AutoplayStrategyKind>() {
+        Ok(k) => k,
+        Err(_) => {
+            check_msg(msg.channel_id.say(&ctx.http, "Strategy should be one of: time, roundrobin, random, catchup").await);
+            return Ok(())
+        }
+    };
+
+    check_msg(msg.channel_id.say(&ctx.http, match mstate.autoplay.set_strategy(kind).await {
+        Ok(m) => format!("{} Scores have been reset.", m),
+        Err(e) => format!("Error setting strategy: {}", e),
+    }).await);
+
+    Ok(())
+}
+
+
+#[command]
+#[only_in(guilds)]
+#[min_args(0)]
+#[max_args(2)]
+// TODO: require permissions for changing this
+async fn seed(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    get_mstate!(mut, mstate, ctx);
+
+    // No argument just shows the current seed, to include in a bug report
+    let ret = if args.is_empty() {
+        mstate.autoplay.get_seed().await.map(|(s, t)| format!("Autoplay seed is {}, shuffling as of {}.", s, t))
+    } else {
+        // The shuffle time is optional, to replay a run exactly it should be the one reported with the seed
+        let seed = args.single::<u64>();
+        let time = if args.is_empty() { Ok(None) } else { args.single::<i64>().map(Some) };
+        let (seed, time) = match (seed, time) {
+            (Ok(s), Ok(t)) => (s, t),
+            _ => {
+                check_msg(msg.channel_id.say(&ctx.http, "Usage: seed <seed> [shuffle time]").await);
+                return Ok(())
+            }
+        };
+        mstate.autoplay.reseed(seed, time).await.map(|m| m.to_string())
+    };
 
-    check_msg(msg.channel_id.say(&ctx.http, "Reset all users' autoplay scores to 0.").await);
+    check_msg(msg.channel_id.say(&ctx.http, match ret {
+        Ok(m) => m,
+        Err(e) => format!("Error with autoplay seed: {}", e),
+    }).await);
 
     Ok(())
 }
@@ -234,7 +337,7 @@ async fn advance(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
 
     let out = match mstate.autoplay.advance_userplaylist(&mstate.muid_from_userid(&msg.author.id).await, num).await {
         Ok(_)  => format!("Advanced your playlist ahead {} song(s)", num),
-        Err(e) => format!("Could not advance playlist: {:?}", e),
+        Err(e) => format!("Could not advance playlist: {}", e),
     };
 
     check_msg(msg.channel_id.say(&ctx.http, out).await);
@@ -28,9 +28,9 @@ struct DebugCmd;
 #[command]
 #[only_in(guilds)]
 async fn usertime(ctx: &Context, msg: &Message) -> CommandResult {
-    get_mstate!(mstate, ctx);
+    get_mstate!(mut, mstate, ctx);
 
-    //let ut = mstate.autoplay.debug_get_usertime();
+    let ut = format!("{:?}", mstate.autoplay.get_scores().await);
 
     msg.channel_id.say(&ctx.http, format!("```{}```", ut)).await?;
 
@@ -52,7 +52,7 @@ async fn dropapuser(ctx: &Context, msg: &Message, mut args: Args) -> CommandResu
 
     mstate.autoplay.disable_user(&muid_from_userid(&member.user.id)).unwrap();
 
-    let ut = mstate.autoplay.debug_get_usertime();
+    let ut = format!("{:?}", mstate.autoplay.get_scores().await);
 
     msg.channel_id.say(&ctx.http, format!("In theory dropped user:\n```{}```", ut)).await?;
 
@@ -74,7 +74,7 @@ async fn addapuser(ctx: &Context, msg: &Message, mut args: Args) -> CommandResul
 
     mstate.autoplay.enable_user(&muid_from_userid(&member.user.id)).unwrap();
 
-    let ut = mstate.autoplay.debug_get_usertime();
+    let ut = format!("{:?}", mstate.autoplay.get_scores().await);
 
     msg.channel_id.say(&ctx.http, format!("In theory added user:\n```{}```", ut)).await?;
 
@@ -23,15 +23,17 @@ use crate::userconv::*;
 use music::{
     MusicOk,
     MusicError,
-    song::fetch_song_from_yt,
 };
 use model::{
+    error::ErrorCode,
+    SeekPosition,
     SongRequest,
+    MAX_VOLUME,
 };
 
 #[group]
 #[description = "Commands for controlling the music player"]
-#[commands(play, nowplaying, next, stop, start, display, history, clearhistory, previous)]
+#[commands(play, nowplaying, next, stop, start, pause, resume, seek, volume, display, history, clearhistory, previous)]
 struct MusicControlCmd;
 
 
@@ -45,10 +47,10 @@ async fn play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
 
     let requester = mstate.requester_from_user(&msg.author).await;
 
-    let song = match fetch_song_from_yt(url) {
+    let song = match mstate.fetch_song(url).await {
         Ok(u) => u,
-        Err(_) => {
-            check_msg(msg.channel_id.say(&ctx.http, "Must provide a URL to a video or audio").await);
+        Err(e) => {
+            check_msg(msg.channel_id.say(&ctx.http, format!("Could not get that song: {}", e)).await);
             return Ok(())
         }
     };
@@ -60,7 +62,7 @@ async fn play(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     // TODO: maybe factor this out into a generic reply handler?
     match ret {
         Ok(m) => check_msg(msg.channel_id.say(&ctx.http, m).await),
-        Err(e) => check_msg(msg.channel_id.say(&ctx.http, format!("Error playing song: {:?}", e)).await),
+        Err(e) => check_msg(msg.channel_id.say(&ctx.http, format!("Error playing song: {}", e)).await),
     }
 
     Ok(())
@@ -75,9 +77,14 @@ async fn nowplaying(ctx: &Context, msg: &Message) -> CommandResult {
 
     let embed = get_nowplay_embed(&mstate);
 
-    check_msg(msg.channel_id.send_message(&ctx.http, |m| {
+    let ret = msg.channel_id.send_message(&ctx.http, |m| {
         m.set_embed(embed)
-    }).await);
+    }).await;
+
+    if let (Ok(m), Some(_)) = (&ret, &mstate.current_track) {
+        add_like_reaction(ctx, m).await;
+    }
+    check_msg(ret);
 
     Ok(())
 }
@@ -86,17 +93,27 @@ async fn nowplaying(ctx: &Context, msg: &Message) -> CommandResult {
 #[aliases(skip)]
 #[only_in(guilds)]
 #[checks(in_same_voice)]
-// TODO: require permissions to do this
 async fn next(ctx: &Context, msg: &Message) -> CommandResult {
     get_mstate!(mut, mstate, ctx);
 
-    let ret = mstate.skip().await;
+    let muid = match mstate.db.get_userid_from_discordid(*msg.author.id.as_u64()).await {
+        Ok(Some(id)) => id,
+        Ok(None) => {
+            msg.reply(&ctx.http, "You need to register first to vote to skip.").await?;
+            return Ok(())
+        },
+        Err(_) => {
+            msg.reply(&ctx.http, format!("Error attempting to get userid: {}", ErrorCode::DbError)).await?;
+            return Ok(())
+        }
+    };
+    let ret = mstate.vote_skip(muid).await;
 
     if let Ok(s) = ret {
         check_msg(msg.channel_id.say(&ctx.http, s).await);
     }
     else if let Err(e) = ret {
-        check_msg(msg.channel_id.say(&ctx.http, format!("Error playing next: {:?}", e)).await);
+        check_msg(msg.channel_id.say(&ctx.http, format!("Error playing next: {}", e)).await);
     }
 
     Ok(())
@@ -115,7 +132,7 @@ async fn stop(ctx: &Context, msg: &Message) -> CommandResult {
         check_msg(msg.channel_id.say(&ctx.http, s).await);
     }
     else if let Err(e) = ret {
-        check_msg(msg.channel_id.say(&ctx.http, format!("Error stopping song: {:?}", e)).await);
+        check_msg(msg.channel_id.say(&ctx.http, format!("Error stopping song: {}", e)).await);
     }
 
     Ok(())
@@ -133,9 +150,115 @@ async fn start(ctx: &Context, msg: &Message) -> CommandResult {
         check_msg(msg.channel_id.say(&ctx.http, s).await);
     }
     else if let Err(e) = ret {
-        check_msg(msg.channel_id.say(&ctx.http, format!("Error stopping song: {:?}", e)).await);
+        check_msg(msg.channel_id.say(&ctx.http, format!("Error stopping song: {}", e)).await);
+    }
+
+    Ok(())
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(in_same_voice)]
+async fn pause(ctx: &Context, msg: &Message) -> CommandResult {
+    get_mstate!(mut, mstate, ctx);
+
+    check_msg(msg.channel_id.say(&ctx.http, match mstate.pause().await {
+        Ok(o) => o.to_string(),
+        Err(MusicError::NotPlaying) => "Nothing is playing.".to_string(),
+        Err(e) => format!("Error pausing song: {}", e),
+    }).await);
+
+    Ok(())
+}
+
+#[command]
+#[aliases(unpause)]
+#[only_in(guilds)]
+#[checks(in_same_voice)]
+async fn resume(ctx: &Context, msg: &Message) -> CommandResult {
+    get_mstate!(mut, mstate, ctx);
+
+    check_msg(msg.channel_id.say(&ctx.http, match mstate.resume().await {
+        Ok(o) => o.to_string(),
+        Err(MusicError::NotPaused) => "Playback is not paused.".to_string(),
+        Err(e) => format!("Error resuming song: {}", e),
+    }).await);
+
+    Ok(())
+}
+
+// Accepts seconds or m:ss, with a leading +/- for seeking relative to the current position
+fn parse_seek(arg: &str) -> Option<SeekPosition> {
+    let (sign, time) = match arg.chars().next()? {
+        '+' => (Some(1), &arg[1..]),
+        '-' => (Some(-1), &arg[1..]),
+        _ => (None, arg),
+    };
+
+    let secs = time.split(':')
+        .try_fold(0u64, |acc, part| part.parse::<u64>().ok().map(|p| acc * 60 + p))?;
+
+    Some(match sign {
+        Some(sign) => SeekPosition::Relative(sign * secs as i64),
+        None => SeekPosition::Absolute(secs),
+    })
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(in_same_voice)]
+#[num_args(1)]
+async fn seek(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let arg = args.single::<String>()?;
+
+    let position = match parse_seek(&arg) {
+        Some(p) => p,
+        None => {
+            check_msg(msg.channel_id.say(&ctx.http, "Seek position should look like 1:30, 90, +30 or -15").await);
+            return Ok(())
+        }
+    };
+
+    get_mstate!(mut, mstate, ctx);
+
+    check_msg(msg.channel_id.say(&ctx.http, match mstate.seek(position).await {
+        Ok(o) => o.to_string(),
+        Err(MusicError::NotPlaying) => "Nothing is playing.".to_string(),
+        Err(e) => format!("Error seeking: {}", e),
+    }).await);
+
+    Ok(())
+}
+
+#[command]
+#[aliases(vol)]
+#[only_in(guilds)]
+#[checks(in_same_voice)]
+#[min_args(0)]
+#[max_args(1)]
+async fn volume(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    get_mstate!(mut, mstate, ctx);
+
+    // No argument just shows the current volume
+    if args.is_empty() {
+        let vol = mstate.get_volume().await;
+        check_msg(msg.channel_id.say(&ctx.http, format!("Volume is {}%.", vol)).await);
+        return Ok(())
     }
 
+    let vol = match args.single::<String>()?.trim_end_matches('%').parse::<u8>() {
+        Ok(v) if v <= MAX_VOLUME => v,
+        _ => {
+            check_msg(msg.channel_id.say(&ctx.http, format!("Volume should be a number from 0 to {}", MAX_VOLUME)).await);
+            return Ok(())
+        }
+    };
+
+    check_msg(msg.channel_id.say(&ctx.http, match mstate.set_volume(vol).await {
+        Ok(o) => o.to_string(),
+        Err(e) => format!("Error setting volume: {}", e),
+    }).await);
+
     Ok(())
 }
 
@@ -206,7 +329,7 @@ async fn previous(ctx: &Context, msg: &Message) -> CommandResult {
         Ok(MusicOk::EnqueuedSong) => "Enqueued last played song.".to_string(),
         Ok(o) => o.to_string(),
         Err(MusicError::EmptyHistory) => "No history to pull a song from".to_string(),
-        Err(e) => format!("{:?}", e),
+        Err(e) => e.to_string(),
     }).await);
 
     Ok(())
@@ -1,4 +1,3 @@
-use music::song::fetch_song_from_yt;
 use serenity::{
     model::{
         channel::Message,
@@ -14,18 +13,23 @@ use serenity::{
     },
 };
 
-use crate::get_mstate;
+use crate::{
+    get_mstate,
+    join_voice,
+};
 use crate::helpers::*;
 use crate::userconv::*;
 
+use music::MusicError;
 use model::{
-    SongRequest
+    RequestId,
+    SongRequest,
 };
 
 
 #[group]
 #[description = "Commands to manage the music queue"]
-#[commands(queue, enqueue, clearqueue, queuestatus)]
+#[commands(queue, enqueue, playnext, insert, remove, move_song, clearqueue, queuestatus)]
 struct QueueControlCmd;
 
 
@@ -56,10 +60,10 @@ async fn enqueue(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
 
     let requester = mstate.requester_from_user(&msg.author).await;
 
-    let song = match fetch_song_from_yt(url) {
+    let song = match mstate.fetch_song(url).await {
         Ok(u) => u,
-        Err(_) => { // TODO: actually handle errors, probably make a generic surrender replier
-            check_msg(msg.channel_id.say(&ctx.http, "Must provide a URL to a video or audio").await);
+        Err(e) => {
+            check_msg(msg.channel_id.say(&ctx.http, format!("Could not get that song: {}", e)).await);
             return Ok(())
         }
     };
@@ -73,12 +77,163 @@ async fn enqueue(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult
     // TODO: maybe factor this out into a generic reply handler?
     match ret {
         Ok(m) => check_msg(msg.channel_id.say(&ctx.http, m).await),
-        Err(e) => check_msg(msg.channel_id.say(&ctx.http, format!("Error playing song: {:?}", e)).await),
+        Err(MusicError::QueueJump) => check_msg(msg.channel_id.say(&ctx.http, "You can't put a song ahead of someone else's.").await),
+        Err(e) => check_msg(msg.channel_id.say(&ctx.http, format!("Error playing song: {}", e)).await),
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases(prepend)]
+#[only_in(guilds)]
+#[checks(in_same_voice)]
+async fn playnext(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let url = args.single::<String>()?;
+
+    get_mstate!(mut, mstate, ctx);
+
+    let requester = mstate.requester_from_user(&msg.author).await;
+
+    let song = match mstate.fetch_song(url).await {
+        Ok(u) => u,
+        Err(e) => {
+            check_msg(msg.channel_id.say(&ctx.http, format!("Could not get that song: {}", e)).await);
+            return Ok(())
+        }
+    };
+    let song = SongRequest::new(song, requester);
+
+    join_voice!(ctx, msg);
+    let ret = mstate.play_next(song).await;
+
+    match ret {
+        Ok(m) => check_msg(msg.channel_id.say(&ctx.http, m).await),
+        Err(MusicError::QueueJump) => check_msg(msg.channel_id.say(&ctx.http, "You can't put a song ahead of someone else's.").await),
+        Err(e) => check_msg(msg.channel_id.say(&ctx.http, format!("Error playing song: {}", e)).await),
     }
 
     Ok(())
 }
 
+// Queue positions are shown to users starting from 1
+fn queue_index(args: &mut Args) -> Option<usize> {
+    args.single::<usize>().ok()?.checked_sub(1)
+}
+
+// Look up which request is at a position in the queue right now
+fn queue_request_id(mdata: &model::MinstrelWebData, index: usize) -> Option<RequestId> {
+    mdata.queue.get(index).map(|r| r.id)
+}
+
+#[command]
+#[only_in(guilds)]
+#[checks(in_same_voice)]
+#[num_args(2)]
+async fn insert(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let index = match queue_index(&mut args) {
+        Some(i) => i,
+        None => {
+            check_msg(msg.channel_id.say(&ctx.http, "Usage: insert <position> <url>").await);
+            return Ok(())
+        }
+    };
+    let url = args.single::<String>()?;
+
+    get_mstate!(mut, mstate, ctx);
+
+    let requester = mstate.requester_from_user(&msg.author).await;
+
+    let song = match mstate.fetch_song(url).await {
+        Ok(u) => u,
+        Err(e) => {
+            check_msg(msg.channel_id.say(&ctx.http, format!("Could not get that song: {}", e)).await);
+            return Ok(())
+        }
+    };
+    let song = SongRequest::new(song, requester);
+
+    match mstate.insert_at(index, song).await {
+        Ok(m) => check_msg(msg.channel_id.say(&ctx.http, m).await),
+        Err(MusicError::QueueJump) => check_msg(msg.channel_id.say(&ctx.http, "You can't put a song ahead of someone else's.").await),
+        Err(e) => check_msg(msg.channel_id.say(&ctx.http, format!("Error enqueueing song: {}", e)).await),
+    }
+
+    Ok(())
+}
+
+#[command]
+#[aliases(rm)]
+#[only_in(guilds)]
+#[checks(in_same_voice)]
+#[num_args(1)]
+async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let index = match queue_index(&mut args) {
+        Some(i) => i,
+        None => {
+            check_msg(msg.channel_id.say(&ctx.http, "Usage: remove <position>").await);
+            return Ok(())
+        }
+    };
+
+    get_mstate!(mut, mstate, ctx);
+    let muid = mstate.muid_from_userid(&msg.author.id).await;
+
+    let id = match queue_request_id(&mstate.get_webdata().await, index) {
+        Some(id) => id,
+        None => {
+            check_msg(msg.channel_id.say(&ctx.http, "There is no song at that position.").await);
+            return Ok(())
+        }
+    };
+
+    check_msg(msg.channel_id.say(&ctx.http, match mstate.remove(id, muid).await {
+        Ok(o) => o.to_string(),
+        Err(MusicError::NotRequester) => "You can only remove songs you requested.".to_string(),
+        Err(MusicError::RequestNotFound) => "That song is no longer in the queue.".to_string(),
+        Err(e) => format!("Error removing song: {}", e),
+    }).await);
+
+    Ok(())
+}
+
+#[command("move")]
+#[aliases(mv)]
+#[only_in(guilds)]
+#[checks(in_same_voice)]
+#[num_args(2)]
+async fn move_song(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let (from, to) = match (queue_index(&mut args), queue_index(&mut args)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => {
+            check_msg(msg.channel_id.say(&ctx.http, "Usage: move <from> <to>").await);
+            return Ok(())
+        }
+    };
+
+    get_mstate!(mut, mstate, ctx);
+    let muid = mstate.muid_from_userid(&msg.author.id).await;
+
+    let mdata = mstate.get_webdata().await;
+    let (id, target) = match (queue_request_id(&mdata, from), queue_request_id(&mdata, to)) {
+        (Some(id), Some(target)) => (id, target),
+        _ => {
+            check_msg(msg.channel_id.say(&ctx.http, "There is no song at that position.").await);
+            return Ok(())
+        }
+    };
+
+    check_msg(msg.channel_id.say(&ctx.http, match mstate.move_song(id, target, muid).await {
+        Ok(o) => o.to_string(),
+        Err(MusicError::NotRequester) => "You can only move songs you requested.".to_string(),
+        Err(MusicError::QueueJump) => "You can't move a song ahead of someone else's.".to_string(),
+        Err(MusicError::RequestNotFound) => "That song is no longer in the queue.".to_string(),
+        Err(e) => format!("Error moving song: {}", e),
+    }).await);
+
+    Ok(())
+}
+
 
 #[command]
 #[only_in(guilds)]
@@ -93,7 +248,7 @@ async fn clearqueue(ctx: &Context, msg: &Message) -> CommandResult {
         check_msg(msg.channel_id.say(&ctx.http, s).await);
     }
     else if let Err(e) = ret {
-        check_msg(msg.channel_id.say(&ctx.http, format!("Error stopping song: {:?}", e)).await);
+        check_msg(msg.channel_id.say(&ctx.http, format!("Error stopping song: {}", e)).await);
     }
 
     Ok(())
@@ -1,6 +1,12 @@
 use model::{
+    error::ErrorCode,
     SourceType,
 };
+use music::{
+    MusicError,
+    local::resolve_local_path,
+    song::source_type_from_path,
+};
 use serenity::{
     model::{
         channel::Message,
@@ -28,7 +34,7 @@ struct SourceCmd;
 async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let url = args.single::<String>()?;
 
-    get_mstate!(mut, mstate, ctx);
+    get_mstate!(mstate, ctx);
 
     let muid = mstate.db.get_userid_from_discordid(*msg.author.id.as_u64()).await;
     let muid = match muid {
@@ -37,25 +43,39 @@ async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
             msg.reply(&ctx.http, "You are not registered.").await?;
             return Ok(())
         },
-        Err(e) => {
-            msg.reply(&ctx.http, format!("Error attempting to get userid: {:?}", e)).await?;
+        Err(_) => {
+            msg.reply(&ctx.http, format!("Error attempting to get userid: {}", ErrorCode::DbError)).await?;
             return Ok(())
         }
     };
 
-    let resp = mstate.db.create_source(muid, &SourceType::YoutubePlaylist(url), true).await;
+    let srctype = match url.as_str() {
+        "liked" | "likes" => SourceType::LikedSongs,
+        _ => source_type_from_path(url),
+    };
+    if let SourceType::LocalDirectory(path) | SourceType::PlaylistFile(path) = &srctype {
+        match resolve_local_path(path) {
+            Ok(_) => (),
+            Err(MusicError::LocalSourcesDisabled) => {
+                msg.reply(&ctx.http, "Local sources are not enabled on this bot.").await?;
+                return Ok(())
+            },
+            Err(_) => {
+                msg.reply(&ctx.http, "That path does not exist.").await?;
+                return Ok(())
+            },
+        }
+    }
+
+    let resp = mstate.db.create_source(muid, &srctype, true).await;
     if resp.is_err() {
         msg.reply(&ctx.http, "Failed to add source").await?;
         return Ok(())
     }
 
     let req = mstate.db.get_requester(muid).await.unwrap();
-    // TODO: put this on another thread
-    let resp = mstate.autoplay.update_userplaylist(&req).await;
-    match resp {
-        Ok(_) => msg.reply(&ctx.http, "Added source and refreshed upcoming!").await?,
-        Err(e) => msg.reply(&ctx.http, format!("Error updating sources: {:?}", e)).await?,
-    };
+    mstate.autoplay.refresh_userplaylist(req);
+    msg.reply(&ctx.http, "Added source, upcoming will update once it has been fetched.").await?;
 
     Ok(())
 }
@@ -71,8 +91,8 @@ async fn show(ctx: &Context, msg: &Message) -> CommandResult {
             msg.reply(&ctx.http, "You are not registered.").await?;
             return Ok(())
         },
-        Err(e) => {
-            msg.reply(&ctx.http, format!("Error attempting to get userid: {:?}", e)).await?;
+        Err(_) => {
+            msg.reply(&ctx.http, format!("Error attempting to get userid: {}", ErrorCode::DbError)).await?;
             return Ok(())
         }
     };
@@ -80,8 +100,8 @@ async fn show(ctx: &Context, msg: &Message) -> CommandResult {
     let sources = mstate.db.get_sources_from_userid(muid, false).await;
     let mut sources = match sources {
         Ok(srcs) => srcs,
-        Err(e) => {
-            msg.reply(&ctx.http, format!("Error fetching sources: {:?}", e)).await?;
+        Err(_) => {
+            msg.reply(&ctx.http, format!("Error fetching sources: {}", ErrorCode::DbError)).await?;
             return Ok(())
         }
     };
@@ -97,6 +117,9 @@ async fn show(ctx: &Context, msg: &Message) -> CommandResult {
     for (i, src) in sources.iter().enumerate() {
         output += match &src.path {
             SourceType::YoutubePlaylist(url) => format!("{}: {}\n", i+1, url),
+            SourceType::LocalDirectory(path) => format!("{}: {} (local)\n", i+1, path),
+            SourceType::PlaylistFile(path) => format!("{}: {} (playlist file)\n", i+1, path),
+            SourceType::LikedSongs => format!("{}: your liked songs\n", i+1),
         }.as_str();
     }
     output += "```";
@@ -113,7 +136,7 @@ async fn show(ctx: &Context, msg: &Message) -> CommandResult {
 async fn update(ctx: &Context, msg: &Message, mut _args: Args) -> CommandResult {
     // TODO: update only selected source
 
-    get_mstate!(mut, mstate, ctx);
+    get_mstate!(mstate, ctx);
 
     let muid = mstate.db.get_userid_from_discordid(*msg.author.id.as_u64()).await;
     let muid = match muid {
@@ -122,19 +145,15 @@ async fn update(ctx: &Context, msg: &Message, mut _args: Args) -> CommandResult
             msg.reply(&ctx.http, "You are not registered.").await?;
             return Ok(())
         },
-        Err(e) => {
-            msg.reply(&ctx.http, format!("Error attempting to get userid: {:?}", e)).await?;
+        Err(_) => {
+            msg.reply(&ctx.http, format!("Error attempting to get userid: {}", ErrorCode::DbError)).await?;
             return Ok(())
         }
     };
 
     let req = mstate.db.get_requester(muid).await.unwrap();
-    // TODO: put this on another thread
-    let resp = mstate.autoplay.update_userplaylist(&req).await;
-    match resp {
-        Ok(_) => msg.reply(&ctx.http, "Added source and refreshed upcoming!").await?,
-        Err(e) => msg.reply(&ctx.http, format!("Error updating sources: {:?}", e)).await?,
-    };
+    mstate.autoplay.refresh_userplaylist(req);
+    msg.reply(&ctx.http, "Refreshing sources, upcoming will update once they have been fetched.").await?;
 
     Ok(())
 }
@@ -160,8 +179,8 @@ async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
             msg.reply(&ctx.http, "You are not registered.").await?;
             return Ok(())
         },
-        Err(e) => {
-            msg.reply(&ctx.http, format!("Error attempting to get userid: {:?}", e)).await?;
+        Err(_) => {
+            msg.reply(&ctx.http, format!("Error attempting to get userid: {}", ErrorCode::DbError)).await?;
             return Ok(())
         }
     };
@@ -169,8 +188,8 @@ async fn remove(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     let sources = mstate.db.get_sources_from_userid(muid, false).await;
     let mut sources = match sources {
         Ok(srcs) => srcs,
-        Err(e) => {
-            msg.reply(&ctx.http, format!("Error fetching sources: {:?}", e)).await?;
+        Err(_) => {
+            msg.reply(&ctx.http, format!("Error fetching sources: {}", ErrorCode::DbError)).await?;
             return Ok(())
         }
     };
@@ -48,7 +48,7 @@ async fn register(ctx: &Context, msg: &Message) -> CommandResult {
     let reply = match mstate.user.user_create(AuthType::Discord(*msg.author.id.as_u64()), info).await {
         Ok(_) => "Registered successfully!".into(),
         Err(UserMgmtError::UserExists) => "You have already registered! If you are trying to link to a web account, see `!help link`".into(),
-        Err(e) => format!("An unknown error occurred: {:?}", e),
+        Err(e) => format!("Error registering: {}", e),
     };
 
     msg.reply(&ctx.http, reply).await?;
@@ -84,11 +84,11 @@ async fn link(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
                 let resp = msg.author.dm(&ctx.http, |m| m.content(linktext)).await;
 
                 if let Err(e) = resp {
-                    msg.reply(&ctx.http, format!("There was an error DM'ing you the link number: {:?}", e)).await?;
+                    msg.reply(&ctx.http, format!("There was an error DM'ing you the link number: {}", e)).await?;
                 }
             },
             Err(e) => {
-                msg.reply(&ctx.http, format!("There was an error generating your link: {:?}", e)).await?;
+                msg.reply(&ctx.http, format!("There was an error generating your link: {}", e)).await?;
             }
         }
 
@@ -106,7 +106,7 @@ async fn link(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
                 Err(UserMgmtError::InvalidLink) => "Link is invalid or expired.".into(),
                 Err(UserMgmtError::UserExists) => "You already have a Discord auth, regenerate a link and use in some other auth.".into(),
                 Err(UserMgmtError::UserDoesNotExist) => "You are somehow not registered? Register first then try linking.".into(),
-                Err(e) => format!("There was a problem attempting to link users: {:?}", e),
+                Err(e) => format!("There was a problem attempting to link users: {}", e),
             };
 
             msg.reply(&ctx.http, response).await?;
@@ -64,6 +64,7 @@ async fn stickymessage_hook(ctx: &Context, _msg: &Message, _cmd_name: &str, _err
         let new = m.channel_id.send_message(&ctx.http, |m| {
             m.set_embeds(vec![qs_embed, np_embed])
         }).await.unwrap();
+        add_like_reaction(ctx, &new).await;
 
         dstate.sticky = Some(new);
     }
@@ -3,7 +3,11 @@ use log::*;
 use serenity::{
     builder::CreateEmbed,
     model::{
-        channel::Message,
+        channel::{
+            Message,
+            Reaction,
+            ReactionType,
+        },
     },
     prelude::*,
     framework::standard::{
@@ -204,6 +208,17 @@ pub async fn in_same_voice(ctx: &Context, msg: &Message) -> Result<(), Reason> {
     }
 }
 
+#[check]
+#[name = "is_admin"]
+pub async fn is_admin(ctx: &Context, msg: &Message) -> Result<(), Reason> {
+    get_mstate!(mstate, ctx);
+
+    match mstate.db.get_userid_from_discordid(msg.author.id.0).await {
+        Ok(Some(muid)) if mstate.user.is_admin(muid) => Ok(()),
+        _ => Err(Reason::User(String::from("Only admins can use this command"))),
+    }
+}
+
 /*** Functions that were previously on mstate, but for discord-specific output ***/
 /**   These are subject to moving again, but can live here now for convenience  **/
 
@@ -280,9 +295,75 @@ pub fn get_nowplay_embed(mstate: &model::MinstrelWebData) -> CreateEmbed {
             .text(format!("Requested by: {}", requester.displayname))
         });
 
+    if mstate.skip_votes > 0 {
+        ret.field("Votes to skip", format!("{}/{}", mstate.skip_votes, mstate.skip_votes_needed), true);
+    }
+
     ret
 }
 
+/// Reaction added to now playing messages, for users to like the song with
+pub const LIKE_EMOJI: &str = "\u{2764}\u{fe0f}";
+
+/// Put the like reaction on a now playing message, so there's something to click
+pub async fn add_like_reaction(ctx: &Context, msg: &Message) {
+    if let Err(e) = msg.react(&ctx.http, ReactionType::Unicode(LIKE_EMOJI.into())).await {
+        error!("failed to add like reaction: {:?}", e);
+    }
+}
+
+/// Like or unlike the song on a now playing message when someone reacts to it
+pub async fn handle_like_reaction(ctx: &Context, reaction: &Reaction, liked: bool) {
+    let bot = ctx.cache.current_user_id();
+
+    let userid = match reaction.user_id {
+        Some(u) if u != bot => u,
+        _ => return,
+    };
+    if !matches!(&reaction.emoji, ReactionType::Unicode(e) if e == LIKE_EMOJI) {
+        return;
+    }
+
+    let msg = match reaction.message(&ctx.http).await {
+        Ok(m) if m.author.id == bot => m,
+        Ok(_) => return,
+        Err(e) => {
+            error!("failed to fetch reacted message: {:?}", e);
+            return;
+        }
+    };
+
+    // Only the now playing embed links to a song
+    let url = match msg.embeds.iter().find_map(|e| e.url.clone()) {
+        Some(u) => u,
+        None => return,
+    };
+
+    get_mstate!(mstate, ctx);
+
+    let muid = match mstate.db.get_userid_from_discordid(userid.0).await {
+        Ok(Some(id)) => id,
+        _ => {
+            debug!("ignoring like from unregistered user {}", userid);
+            return;
+        }
+    };
+
+    // The message may be old, so like the song it shows rather than whatever is playing now
+    let data = mstate.get_webdata().await;
+    let id = match data.current_track.iter().chain(data.history.iter()).find(|r| r.song.url == url) {
+        Some(r) => r.id,
+        None => {
+            debug!("song {} is no longer playing or in the history, not liking it", url);
+            return;
+        }
+    };
+
+    if let Err(e) = mstate.like_song(muid, Some(id), liked).await {
+        error!("failed to update like of {} for {}: {}", url, muid, e);
+    }
+}
+
 pub fn show_history(mstate: &model::MinstrelWebData, num: usize) -> Option<String> {
     let history = mstate.get_history();
 
@@ -1,4 +1,9 @@
 use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+use std::time::Duration;
 
 use serenity::{
     prelude::Context,
@@ -18,23 +23,48 @@ use songbird::{
 };
 
 use async_trait::async_trait;
-use rand::seq::SliceRandom;
 
 use log::*;
 use music::player::MusicPlayer;
-use model::Song;
+use model::{
+    RequestId,
+    Song,
+    MAX_VOLUME,
+};
 use music::*;
+use minstrel_config::read_config;
 
 use crate::get_mstate;
 use crate::helpers::*;
 use crate::userconv::*;
 
 
+const LOUDNORM_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+
 /// Struct to maintain discord's music player state
-#[derive(Default)]
 pub struct DiscordPlayer {
     pub songcall: Option<Arc<tokio::sync::Mutex<songbird::Call>>>,
     songhandler: Option<songbird::tracks::TrackHandle>,
+    ctx: Option<Context>, // For telling MusicState when a track ends
+    current: Option<Song>,
+    current_id: RequestId, // Request the current song is for, to report back when it ends
+    volume: u8, // percent
+    // Number of upcoming track end events that were caused by seeking, rather than the song ending
+    ignore_ends: Arc<AtomicUsize>,
+}
+
+impl Default for DiscordPlayer {
+    fn default() -> Self {
+        Self {
+            songcall: None,
+            songhandler: None,
+            ctx: None,
+            current: None,
+            current_id: 0,
+            volume: read_config!(music.default_volume).min(MAX_VOLUME),
+            ignore_ends: Arc::new(AtomicUsize::new(0)),
+        }
+    }
 }
 
 impl DiscordPlayer {
@@ -49,13 +79,7 @@ impl DiscordPlayer {
 
         let handler = manager.join(guild_id, channel_id).await.0;
 
-        handler.lock().await.add_global_event(
-            Event::Track(TrackEvent::End),
-            TrackEndNotifier {
-                ctx: ctx.clone()
-            },
-        );
-
+        self.ctx = Some(ctx.clone());
         self.songcall = Some(handler);
     }
 
@@ -75,17 +99,9 @@ impl DiscordPlayer {
             call.remove_all_global_events();
         }
     }
-}
-
-#[async_trait]
-impl MusicPlayer for DiscordPlayer {
-
-    async fn init(&self) -> Result<(), MusicError> {
-        Ok(())
-    }
-
-    async fn play(&mut self, song: &Song) -> Result<(), MusicError> {
 
+    /// Start playing a song from the supplied position
+    async fn start_source(&mut self, song: &Song, position: Duration) -> Result<(), MusicError> {
         // TODO: don't let this panic here
         let mut handler = match &self.songcall {
             Some(c) => c.lock().await,
@@ -95,19 +111,69 @@ impl MusicPlayer for DiscordPlayer {
             },
         };
 
-        let source = match songbird::ytdl_ffmpeg_args(&song.url, &[], &["-af", "loudnorm=I=-16:TP=-1.5:LRA=11"]).await {
+        // Neither yt-dlp or ffmpeg sources are seekable in songbird, so seeking is done by restarting
+        //  ffmpeg at the new position instead
+        let ss = position.as_secs_f64().to_string();
+        let mut pre_input = Vec::new();
+        if !position.is_zero() {
+            pre_input.extend(["-ss", ss.as_str()]);
+        }
+
+        // Local files can go straight to ffmpeg, everything else needs to go through yt-dlp
+        let source = if song.url.starts_with("http") {
+            songbird::ytdl_ffmpeg_args(&song.url, &pre_input, &["-af", LOUDNORM_FILTER]).await
+        } else {
+            songbird::input::ffmpeg_optioned(&song.url, &pre_input, &[
+                "-af", LOUDNORM_FILTER,
+                "-f", "s16le", "-ac", "2", "-ar", "48000", "-acodec", "pcm_f32le", "-",
+            ]).await
+        };
+
+        let source = match source {
             Ok(source) => source,
             Err(why) => {
                 error!("Err starting source: {:?}", why);
                 self.songhandler = None;
+                self.current = None;
                 return Err(MusicError::UnknownError);
             },
         };
 
-        self.songhandler = Some(handler.play_source(source));
+        let thandle = handler.play_source(source);
+        if let Err(e) = thandle.set_volume(self.volume as f32 / 100.0) {
+            error!("failed to set volume on new track: {:?}", e);
+        }
+
+        // Each track reports its own end, so a late one from an old track can be told apart from the current one
+        if let Some(ctx) = &self.ctx {
+            let notifier = TrackEndNotifier {
+                ctx: ctx.clone(),
+                id: self.current_id,
+                ignore_ends: self.ignore_ends.clone(),
+            };
+            if let Err(e) = thandle.add_event(Event::Track(TrackEvent::End), notifier) {
+                error!("failed to add end event to new track: {:?}", e);
+            }
+        }
+
+        self.songhandler = Some(thandle);
+        self.current = Some(song.clone());
 
         Ok(())
     }
+}
+
+#[async_trait]
+impl MusicPlayer for DiscordPlayer {
+
+    async fn init(&self) -> Result<(), MusicError> {
+        Ok(())
+    }
+
+    async fn play(&mut self, song: &Song, id: RequestId) -> Result<(), MusicError> {
+        self.current_id = id;
+        self.start_source(song, Duration::ZERO).await
+    }
 
     async fn stop(&mut self) -> Result<(), MusicError> {
         if let Some(thandle) = &self.songhandler {
@@ -115,9 +181,60 @@ impl MusicPlayer for DiscordPlayer {
             thandle.stop().ok();
             self.songhandler = None
         }
+        self.current = None;
 
         Ok(())
     }
+
+    async fn pause(&mut self) -> Result<(), MusicError> {
+        match &self.songhandler {
+            Some(thandle) => thandle.pause().map_err(|e| {
+                error!("failed to pause track: {:?}", e);
+                MusicError::PlaybackFailed
+            }),
+            None => Err(MusicError::NotPlaying),
+        }
+    }
+
+    async fn resume(&mut self) -> Result<(), MusicError> {
+        match &self.songhandler {
+            Some(thandle) => thandle.play().map_err(|e| {
+                error!("failed to resume track: {:?}", e);
+                MusicError::PlaybackFailed
+            }),
+            None => Err(MusicError::NotPlaying),
+        }
+    }
+
+    async fn seek(&mut self, position: Duration) -> Result<(), MusicError> {
+        let song = match (&self.songhandler, &self.current) {
+            (Some(thandle), Some(song)) => {
+                // Swallow the end event from the old track, so MusicState doesn't move on to the next song
+                self.ignore_ends.fetch_add(1, Ordering::SeqCst);
+                thandle.stop().ok();
+                song.clone()
+            },
+            _ => return Err(MusicError::NotPlaying),
+        };
+
+        self.start_source(&song, position).await
+    }
+
+    async fn set_volume(&mut self, volume: u8) -> Result<(), MusicError> {
+        if let Some(thandle) = &self.songhandler {
+            thandle.set_volume(volume as f32 / 100.0).map_err(|e| {
+                error!("failed to set volume: {:?}", e);
+                MusicError::PlaybackFailed
+            })?;
+        }
+        self.volume = volume;
+
+        Ok(())
+    }
+
+    async fn get_volume(&self) -> u8 {
+        self.volume
+    }
 }
 
 
@@ -126,6 +243,8 @@ impl MusicPlayer for DiscordPlayer {
 
 pub struct TrackEndNotifier {
     pub ctx: Context,
+    pub id: RequestId, // Request the track was played for
+    pub ignore_ends: Arc<AtomicUsize>,
 }
 
 #[async_trait]
@@ -134,12 +253,20 @@ impl VoiceEventHandler for TrackEndNotifier {
     async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
         debug!("TrackEndNotifier fired");
 
+        // Track was replaced by a seek, the song itself hasn't ended
+        let ignored = self.ignore_ends.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if ignored.is_ok() {
+            debug!("ignoring track end caused by seek");
+            return None;
+        }
+
         let ctx = self.ctx.clone();
+        let id = self.id;
         // Plopping this on another thread so that this VoiceEvent handler can be brief
         tokio::spawn(async move {
             let mut mstate = mstate_get(&ctx).await.unwrap();
 
-            mstate.song_ended().await;
+            mstate.song_ended(id).await;
         });
 
         None
@@ -147,6 +274,43 @@ impl VoiceEventHandler for TrackEndNotifier {
 }
 
 
+// Keep the listener count used for skip votes up to date
+pub async fn update_listeners(ctx: &Context, guildid: Option<GuildId>) {
+    let bot = ctx.cache.current_user_id();
+    let guild = match guildid.and_then(|g| ctx.cache.guild(g)) {
+        Some(g) => g,
+        None => return,
+    };
+
+    // Not in voice means nobody is listening, fall back to the enrolled users
+    let listeners: Option<Vec<UserId>> = guild.voice_states.get(&bot)
+        .and_then(|vs| vs.channel_id)
+        .map(|chan| guild.voice_states.iter()
+            .filter(|(uid, _)| **uid != bot)
+            .filter(|(_, vs)| vs.channel_id == Some(chan))
+            .map(|(uid, _)| *uid)
+            .collect());
+
+    get_mstate!(mut, mstate, ctx);
+
+    // Unregistered listeners can't vote, so they shouldn't raise the number of votes needed
+    let count = match listeners {
+        Some(listeners) => {
+            let mut registered = 0;
+            for uid in listeners {
+                if let Ok(Some(_)) = mstate.db.get_userid_from_discordid(uid.0).await {
+                    registered += 1;
+                }
+            }
+            Some(registered)
+        },
+        None => None,
+    };
+
+    mstate.set_listeners(count).await;
+}
+
+
 // Autoplay auto-rebalance userlists
 pub async fn autoplay_voice_state_update(ctx: Context, guildid: Option<GuildId>, old: Option<VoiceState>, new: VoiceState) {
     let bot = ctx.cache.current_user_id();
@@ -174,15 +338,12 @@ pub async fn autoplay_voice_state_update(ctx: Context, guildid: Option<GuildId>,
             mstate.autoplay.disable_all_users().await;
 
             // ...and enable only users in this new channel
-            let mut vstates = guild.voice_states.iter()
+            let vstates = guild.voice_states.iter()
                 .filter(|(uid,_)| **uid != bot)                  // Ignore self
                 .filter(|(_,vs)| vs.channel_id.unwrap() == chan) // Ignore states for other channels
                 .collect::<Vec<(&UserId, &VoiceState)>>();
 
-            // Randomize the order that we enable users, so that the first user picked
-            //  SHOULD be random and not alphabetical by whatever order the voice states are in
-            vstates.shuffle(&mut rand::thread_rng());
-
+            let mut muids = Vec::new();
             for (uid, vs) in vstates.iter() {
                 let user = if let &Some(mem) = &vs.member.as_ref() {
                     debug!("vs.member not None, using from there");
@@ -205,10 +366,14 @@ pub async fn autoplay_voice_state_update(ctx: Context, guildid: Option<GuildId>,
 
                 };
 
-                match mstate.autoplay.enable_user(&mstate.muid_from_userid(&user.id).await).await {
-                    Ok(o) => debug!("enrolling user {}: {:?}", user.tag(), o),
-                    Err(e) => debug!("did not enroll user {}: {:?}", user.tag(), e),
-                };
+                debug!("enrolling user {}", user.tag());
+                muids.push(mstate.muid_from_userid(&user.id).await);
+            }
+
+            // Autoplay randomizes the order these are enabled in, so that the first user picked
+            //  SHOULD be random and not alphabetical by whatever order the voice states are in
+            if let Err(e) = mstate.autoplay.enable_users(muids).await {
+                error!("failed to enroll users in the new channel: {:?}", e);
             }
         }
 
@@ -10,9 +10,11 @@ use tokio::sync::Mutex;
 use minstrel_config::{
     CONFIG,
     read_config,
+    PlayerKind,
 };
 
 use music::MusicState;
+use music::streamplayer::StreamPlayer;
 
 #[tokio::main]
 async fn main() {
@@ -35,35 +37,88 @@ async fn main() {
     let db = db::init_db().await;
 
     let (tx, rx) = tokio::sync::mpsc::channel(3);
-    let mut mstate = MusicState::new(tx, db.clone()).await;
+    let mut mstate = MusicState::new(tx, db.clone(), music::resolver::default_resolver()).await;
 
     // TODO: I really don't like this flow, it needs to be handled by some higher level controller probably.
 
-    #[cfg(feature = "discord")]
-    {
-        // TODO: make this under a discord-player feature, depends on splitting DiscordPlayer into a DiscordState probably
-        let dplayer = Arc::new(Mutex::new(discord::player::DiscordPlayer::new()));
-        let mut dplayertask = music::player::MusicPlayerTask::new(dplayer.clone(), rx);
+    // Only set when a player is streaming, for the web server to serve
+    #[cfg_attr(not(feature = "web-frontend"), allow(unused_variables))]
+    let stream = match read_config!(music.player) {
+        PlayerKind::Simulated => {
+            let splayer = Arc::new(Mutex::new(music::simulatedplayer::SimulatedPlayer::new(mstate.get_adapter())));
+            let mut splayertask = music::player::MusicPlayerTask::new(splayer, rx);
 
-        debug!("spawning discord player task");
-        tokio::spawn(async move {
-            dplayertask.run().await;
-        });
+            // The discord frontend needs the discord player to join voice, so it doesn't run here
+            info!("spawning simulated player task, discord will not be started");
+            tokio::spawn(async move {
+                splayertask.run().await;
+            });
 
-        let mut client = discord::client::create_player(mstate.get_adapter(), dplayer.clone()).await;
+            None
+        }
 
+        PlayerKind::Stream => {
+            let splayer = StreamPlayer::new(Some(mstate.get_adapter()));
+            let stream = splayer.get_stream();
+            let mut splayertask = music::player::MusicPlayerTask::new(Arc::new(Mutex::new(splayer)), rx);
 
-        info!("spawning discord client");
-        tokio::spawn(async move {
-            if let Err(why) = client.start().await {
-                error!("Client error: {:?}", why);
-            }
-        });
-    }
+            info!("spawning stream player task, discord will not be started");
+            tokio::spawn(async move {
+                splayertask.run().await;
+            });
+
+            Some(stream)
+        }
+
+        #[cfg(feature = "discord")]
+        PlayerKind::Discord => {
+            // TODO: make this under a discord-player feature, depends on splitting DiscordPlayer into a DiscordState probably
+            let dplayer = Arc::new(Mutex::new(discord::player::DiscordPlayer::new()));
+
+            // Discord is in charge of when songs end, the stream just follows along
+            let stream = if read_config!(music.stream_alongside) {
+                let splayer = StreamPlayer::new(None);
+                let stream = splayer.get_stream();
+                let tee = music::player::TeePlayer::new(dplayer.clone(), Arc::new(Mutex::new(splayer)));
+                let mut teetask = music::player::MusicPlayerTask::new(Arc::new(Mutex::new(tee)), rx);
+
+                debug!("spawning discord and stream player task");
+                tokio::spawn(async move {
+                    teetask.run().await;
+                });
+
+                Some(stream)
+            } else {
+                let mut dplayertask = music::player::MusicPlayerTask::new(dplayer.clone(), rx);
+
+                debug!("spawning discord player task");
+                tokio::spawn(async move {
+                    dplayertask.run().await;
+                });
+
+                None
+            };
+
+            let mut client = discord::client::create_player(mstate.get_adapter(), dplayer.clone()).await;
+
+
+            info!("spawning discord client");
+            tokio::spawn(async move {
+                if let Err(why) = client.start().await {
+                    error!("Client error: {:?}", why);
+                }
+            });
+
+            stream
+        }
+
+        #[cfg(not(feature = "discord"))]
+        PlayerKind::Discord => panic!("the discord player is configured, but the discord feature is not enabled"),
+    };
 
     #[cfg(feature = "web-frontend")]
     {
-        let site = webapi::web::get_web_filter(mstate.get_adapter());
+        let site = webapi::web::get_web_filter(mstate.get_adapter(), stream);
         let addr = format!("{}:{}", read_config!(web.bind_address), read_config!(web.port))
             .parse::<std::net::SocketAddr>().unwrap();
 
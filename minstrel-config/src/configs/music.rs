@@ -1,24 +1,166 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
 
 
+/// How manually queued songs are ordered
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueMode {
+    /// Songs play in the order they were requested
+    Fifo,
+    /// Requesters take turns, whoever has had the least time played goes next
+    Fair,
+}
+
+/// What actually plays the music
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerKind {
+    /// Play into a Discord voice channel
+    Discord,
+    /// Pretend to play songs for their duration without any audio, no Discord needed
+    Simulated,
+    /// Only play to the web stream at /stream, no Discord needed
+    Stream,
+}
+
+/// Who counts towards the number of votes needed to skip a song
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SkipVoteBasis {
+    /// Everyone in the voice channel with the bot, or enrolled users if the player has no listeners to count
+    Listening,
+    /// Users enrolled in autoplay
+    Enrolled,
+}
+
+/// How autoplay decides whose song plays next
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoplayStrategyKind {
+    /// Whoever has had the least time played goes next
+    Time,
+    /// Whoever has had the fewest songs played goes next, regardless of length
+    RoundRobin,
+    /// Random, but users who have had a bigger share of the time played are less likely to go next
+    Random,
+    /// Same as time, but users joining in are brought up to the average instead of going first
+    CatchUp,
+}
+
+impl fmt::Display for AutoplayStrategyKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ret = match self {
+            AutoplayStrategyKind::Time => "time",
+            AutoplayStrategyKind::RoundRobin => "roundrobin",
+            AutoplayStrategyKind::Random => "random",
+            AutoplayStrategyKind::CatchUp => "catchup",
+        };
+
+        write!(f, "{}", ret)
+    }
+}
+
+impl FromStr for AutoplayStrategyKind {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "time" => Ok(AutoplayStrategyKind::Time),
+            "roundrobin" => Ok(AutoplayStrategyKind::RoundRobin),
+            "random" => Ok(AutoplayStrategyKind::Random),
+            "catchup" => Ok(AutoplayStrategyKind::CatchUp),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(unused)]
 pub struct MusicConfig {
+    pub player: PlayerKind,
+    // How fast simulated songs play, e.g. 2.0 ends songs in half their length
+    pub simulated_timescale: f64,
+    // Also play to the web stream when playing in Discord
+    pub stream_alongside: bool,
+    // Kbps for the web stream
+    pub stream_bitrate: u32,
+    // Fraction of listening (or enrolled) users who need to vote to skip a song, 0 to let anyone skip right away
+    pub skip_vote_fraction: f64,
+    pub skip_vote_basis: SkipVoteBasis,
+    // Let whoever requested a song skip it without a vote
+    pub skip_vote_requester_override: bool,
+    // Seconds past a song's length to wait for the player to end it before skipping ahead, 0 to always wait
+    pub song_end_grace: u64,
     pub queue_length: usize,
+    pub queue_mode: QueueMode,
     pub queue_adds_usertime: bool,
     pub autoplay_prefetch_max: u64,
+    // Strategy autoplay starts with, can be changed while running
+    pub autoplay_strategy: AutoplayStrategyKind,
+    // Seed for autoplay's picks and shuffles, for replaying a run. 0 picks a new one each start
+    pub autoplay_seed: u64,
+    // Unix time autoplay's shuffles are weighted as of, reported alongside the seed. 0 uses the time it starts
+    pub autoplay_seed_time: i64,
+    // Hours for an autoplay score to decay to half, 0 to never decay
+    pub autoplay_score_halflife: u64,
+    // Don't autoplay from the same user twice in a row, as long as someone else is enrolled
+    pub autoplay_avoid_same_user: bool,
+    // Skip over songs that were played within the last N tracks or N minutes, 0 to disable either
+    pub autoplay_repeat_tracks: usize,
+    pub autoplay_repeat_minutes: u64,
+    // How strongly autoplay shuffles favor songs played less often and less recently, 0 for a plain shuffle
+    pub autoplay_shuffle_weight: f64,
     pub upcoming_count: u64,
     pub history_count: u64,
+    // Volume percentage (0-100) used until someone changes it
+    pub default_volume: u8,
+    // Put the track that was playing when the bot went down back at the front of the queue
+    pub resume_interrupted: bool,
+    // Directory that local sources are allowed to read from, local sources are disabled if empty
+    pub local_music_root: String,
+    // Max number of metadata lookups (yt-dlp, tag reading) running at once
+    pub resolver_workers: usize,
+    // Seconds before giving up on a single lookup
+    pub resolver_timeout: u64,
+    pub resolver_retries: u32,
+    // Seconds to wait before the first retry, each retry after that waits one more multiple of this
+    pub resolver_retry_backoff: u64,
 }
 
 impl Default for MusicConfig {
     fn default() -> Self {
         Self {
+            player: PlayerKind::Discord,
+            simulated_timescale: 1.0,
+            stream_alongside: false,
+            stream_bitrate: 128,
+            skip_vote_fraction: 0.5,
+            skip_vote_basis: SkipVoteBasis::Listening,
+            skip_vote_requester_override: true,
+            song_end_grace: 30,
             queue_length: 10,
+            queue_mode: QueueMode::Fifo,
             queue_adds_usertime: true,
             autoplay_prefetch_max: 50,
+            autoplay_strategy: AutoplayStrategyKind::Time,
+            autoplay_seed: 0,
+            autoplay_seed_time: 0,
+            autoplay_score_halflife: 0,
+            autoplay_avoid_same_user: true,
+            autoplay_repeat_tracks: 10,
+            autoplay_repeat_minutes: 60,
+            autoplay_shuffle_weight: 1.0,
             upcoming_count: 20,
             history_count: 20,
+            default_volume: 100,
+            resume_interrupted: true,
+            local_music_root: String::new(),
+            resolver_workers: 4,
+            resolver_timeout: 120,
+            resolver_retries: 2,
+            resolver_retry_backoff: 1,
         }
     }
 }
\ No newline at end of file
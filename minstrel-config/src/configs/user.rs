@@ -4,12 +4,15 @@ use serde::{Deserialize, Serialize};
 #[allow(unused)]
 pub struct UserConfig {
     pub link_timeout: u64,
+    // User ids allowed to change things that affect everyone, like resetting autoplay scores
+    pub admins: Vec<i64>,
 }
 
 impl Default for UserConfig {
     fn default() -> Self {
         Self {
             link_timeout: 60 * 60,
+            admins: Vec::new(),
         }
     }
 }
\ No newline at end of file
@@ -5,6 +5,12 @@ use std::sync::RwLock;
 
 mod configs;
 use configs::*;
+pub use configs::{
+    AutoplayStrategyKind,
+    PlayerKind,
+    QueueMode,
+    SkipVoteBasis,
+};
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[allow(unused)]
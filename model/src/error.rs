@@ -0,0 +1,158 @@
+/// Errors as frontends see them. Each backend error maps to one of these codes, so every frontend
+/// shows the same messages, and clients can decide what to do from the code alone.
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use std::fmt;
+
+
+/// Stable, machine-readable error codes. These serialize as snake_case strings (e.g. "queue_full"),
+/// which should never change once added.
+#[non_exhaustive]
+#[derive(Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    Unknown,
+
+    // Playback
+    AlreadyPlaying,
+    NotPlaying,
+    NotPaused,
+    EmptyHistory,
+    InvalidVolume,
+    PlaybackFailed,
+    SongStalled,
+    AlreadyVoted,
+
+    // Queue
+    QueueFull,
+    RequestNotFound,
+    NotRequester,
+    QueueJump,
+
+    // Looking up songs
+    InvalidUrl,
+    InvalidSource,
+    LocalSourcesDisabled,
+    FailedToRetrieve,
+    Unplayable,
+    ResolverTimeout,
+
+    // Autoplay
+    AlreadyEnrolled,
+    NotEnrolled,
+    NoPlaylist,
+    UrlNotPlaylist,
+    PlaylistTooLarge,
+    UnknownStrategy,
+
+    // Users
+    UserExists,
+    UserDoesNotExist,
+    InvalidLink,
+    BadLogin,
+    NotLoggedIn,
+    NotAdmin,
+    DbError,
+
+    // The request itself didn't make sense
+    BadRequest,
+}
+
+impl ErrorCode {
+    /// Message to show a user, when there's nothing more specific to say
+    pub fn message(&self) -> &'static str {
+        match self {
+            Self::Unknown => "Something went wrong internally",
+            Self::AlreadyPlaying => "Something is already playing",
+            Self::NotPlaying => "Nothing is playing",
+            Self::NotPaused => "Playback is not paused",
+            Self::EmptyHistory => "There is nothing in the history to go back to",
+            Self::InvalidVolume => "Volume must be between 0 and 100",
+            Self::PlaybackFailed => "The player failed to play the song",
+            Self::SongStalled => "The player stopped responding partway through a song",
+            Self::AlreadyVoted => "You already voted to skip this song",
+            Self::QueueFull => "The queue is full",
+            Self::RequestNotFound => "That song is no longer in the list",
+            Self::NotRequester => "Only the person who requested that song can change it",
+            Self::QueueJump => "Songs can't go ahead of other people's requests",
+            Self::InvalidUrl => "Could not find a song at that link",
+            Self::InvalidSource => "That source is not valid",
+            Self::LocalSourcesDisabled => "Local sources are disabled",
+            Self::FailedToRetrieve => "Failed to look up the song",
+            Self::Unplayable => "That song can't be played, it may be private, deleted or corrupt",
+            Self::ResolverTimeout => "Timed out looking up the song",
+            Self::AlreadyEnrolled => "You are already enrolled in autoplay",
+            Self::NotEnrolled => "You are not enrolled in autoplay",
+            Self::NoPlaylist => "You do not have an autoplay playlist, add a source first",
+            Self::UrlNotPlaylist => "That link is not a playlist",
+            Self::PlaylistTooLarge => "That playlist is too large",
+            Self::UnknownStrategy => "Unknown autoplay strategy",
+            Self::UserExists => "That user already exists",
+            Self::UserDoesNotExist => "That user does not exist",
+            Self::InvalidLink => "Invalid or expired link, please regenerate it and try again",
+            Self::BadLogin => "Incorrect username or password",
+            Self::NotLoggedIn => "You are not logged in",
+            Self::NotAdmin => "Only admins can do that",
+            Self::DbError => "Something went wrong with the database",
+            Self::BadRequest => "Invalid request",
+        }
+    }
+
+    /// HTTP status code to reply with
+    pub fn http_status(&self) -> u16 {
+        match self {
+            Self::Unknown | Self::PlaybackFailed | Self::SongStalled | Self::DbError => 500,
+            Self::FailedToRetrieve => 502,
+            Self::ResolverTimeout => 504,
+            Self::BadLogin | Self::NotLoggedIn | Self::InvalidLink => 401,
+            Self::NotRequester | Self::QueueJump | Self::LocalSourcesDisabled | Self::NotAdmin => 403,
+            Self::RequestNotFound | Self::UserDoesNotExist => 404,
+            Self::AlreadyPlaying | Self::AlreadyVoted | Self::AlreadyEnrolled | Self::UserExists => 409,
+            _ => 400,
+        }
+    }
+
+    /// Whether this is the user's doing (e.g. a bad link), rather than something breaking
+    pub fn is_user_error(&self) -> bool {
+        self.http_status() < 500
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+/// An error sent to a frontend, a code to act on and a message to show
+#[derive(Clone, Serialize, Deserialize, Eq, PartialEq, Debug)]
+pub struct MinstrelError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl MinstrelError {
+    /// Error with a more specific message than the code's own
+    pub fn new<S: Into<String>>(code: ErrorCode, message: S) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl From<ErrorCode> for MinstrelError {
+    fn from(code: ErrorCode) -> Self {
+        Self::new(code, code.message())
+    }
+}
+
+impl fmt::Display for MinstrelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
@@ -6,8 +6,14 @@ use serde::{
 use std::collections::VecDeque;
 use std::fmt;
 
+pub mod error;
 pub mod web;
 
+use error::{
+    ErrorCode,
+    MinstrelError,
+};
+
 // Literal copy of what is in music::Requester
 //  Subject to deletion if/when all the structs in music:: become "web compatible"
 #[derive(Clone, Serialize, Eq, PartialEq, Deserialize, Debug)]
@@ -19,6 +25,12 @@ pub struct Requester {
 
 pub type MinstrelUserId = i64;
 
+/// Identifies a single request in the queue, history or autoplay's upcoming list
+pub type RequestId = u64;
+
+/// Highest volume accepted, as a percentage of the source's (normalized) loudness
+pub const MAX_VOLUME: u8 = 100;
+
 #[derive(Clone, Serialize, Eq, PartialEq, Deserialize, Debug)]
 pub struct Song {
     pub title: String,
@@ -32,6 +44,9 @@ pub struct Song {
 pub struct SongRequest {
     pub song: Song,
     pub requested_by: Requester,
+    // Assigned when the request is enqueued or prefetched by autoplay, 0 until then
+    #[serde(default)]
+    pub id: RequestId,
 }
 
 impl SongRequest {
@@ -40,17 +55,41 @@ impl SongRequest {
         Self {
             song,
             requested_by,
+            id: 0,
         }
     }
 }
 
+/// Where to seek to in the current track
+#[derive(Clone, Copy, Serialize, Eq, PartialEq, Deserialize, Debug)]
+pub enum SeekPosition {
+    /// Seconds from the start of the track
+    Absolute(u64),
+    /// Seconds forward (or backward, if negative) from the current position
+    Relative(i64),
+}
+
+/// A user's autoplay fairness score, lower scores get picked first
+#[derive(Clone, Serialize, Eq, PartialEq, Deserialize, Debug)]
+pub struct AutoplayScore {
+    pub userid: MinstrelUserId,
+    pub displayname: String,
+    pub score: i64, // seconds of music played, possibly decayed
+    pub enabled: bool,
+}
+
 #[derive(Clone, Serialize, Eq, PartialEq, Deserialize, Debug)]
 
 /// Path to a source of music, to be used in autoplay.
 /// May be a playlist, or just a single song.
-/// TODO: Support other Source types
 pub enum SourceType {
     YoutubePlaylist(String),
+    /// Directory of audio files, scanned recursively. Relative to the configured local music root.
+    LocalDirectory(String),
+    /// M3U/PLS/XSPF playlist file, same path rules as LocalDirectory. Entries may be local files or URLs.
+    PlaylistFile(String),
+    /// Every song the source's owner has liked
+    LikedSongs,
 }
 
 pub struct Source {
@@ -63,10 +102,19 @@ pub struct MinstrelWebData {
     pub current_track: Option<SongRequest>,
     pub song_progress: u64,
     pub status: MusicStateStatus,
+    pub volume: u8, // percent
     pub queue: VecDeque<SongRequest>,
     pub upcoming: Vec<SongRequest>,
     pub history: VecDeque<SongRequest>,
     pub ap_enabled: bool,
+    // Votes to skip the current song, and how many it takes
+    #[serde(default)]
+    pub skip_votes: usize,
+    #[serde(default)]
+    pub skip_votes_needed: usize,
+    // Sequence number of the last event this state includes
+    #[serde(default)]
+    pub seq: u64,
 }
 
 
@@ -75,6 +123,7 @@ pub struct MinstrelWebData {
 #[derive(Clone, Serialize, Eq, PartialEq, Deserialize, Debug)]
 pub enum MusicStateStatus {
     Playing,
+    Paused,
     Stopping,
     Stopped,
     Idle,
@@ -119,19 +168,90 @@ impl MinstrelWebData {
     pub fn is_queue_empty(&self) -> bool {
         self.queue.is_empty()
     }
+
+    /// Apply an event on top of this state. Events this state already includes are ignored.
+    /// Returns false if an event was missed in between, in which case a fresh state is needed.
+    pub fn apply(&mut self, seq: u64, event: MinstrelEvent) -> bool {
+        if seq <= self.seq {
+            return true;
+        }
+        if seq != self.seq + 1 {
+            return false;
+        }
+
+        match event {
+            MinstrelEvent::TrackStarted(track) => {
+                self.current_track = Some(track);
+                self.song_progress = 0;
+            },
+            MinstrelEvent::TrackEnded(_) => {
+                self.current_track = None;
+                self.song_progress = 0;
+            },
+            MinstrelEvent::QueueChanged(queue) => self.queue = queue,
+            MinstrelEvent::UpcomingChanged(upcoming) => self.upcoming = upcoming,
+            MinstrelEvent::HistoryChanged(history) => self.history = history,
+            MinstrelEvent::StatusChanged { status, song_progress, volume, ap_enabled } => {
+                self.status = status;
+                self.song_progress = song_progress;
+                self.volume = volume;
+                self.ap_enabled = ap_enabled;
+            },
+            MinstrelEvent::SkipVotesChanged { votes, needed } => {
+                self.skip_votes = votes;
+                self.skip_votes_needed = needed;
+            },
+            // Any change to upcoming from these comes with its own UpcomingChanged
+            MinstrelEvent::UserEnrolled { .. } | MinstrelEvent::PlaylistRefreshed(_) => (),
+        }
+
+        self.seq = seq;
+        true
+    }
+}
+
+/// A single change to the music state. Lists are sent whole, but only when they actually changed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum MinstrelEvent {
+    TrackStarted(SongRequest),
+    // Only clears the current track, the history change comes separately
+    TrackEnded(SongRequest),
+    QueueChanged(VecDeque<SongRequest>),
+    UpcomingChanged(Vec<SongRequest>),
+    HistoryChanged(VecDeque<SongRequest>),
+    // Sent when the status, volume or autoplay toggle change, or the song progress jumps (e.g. a seek)
+    StatusChanged {
+        status: MusicStateStatus,
+        song_progress: u64,
+        volume: u8,
+        ap_enabled: bool,
+    },
+    // Also sent when the number needed changes, e.g. someone left, or the song changed and votes were reset
+    SkipVotesChanged {
+        votes: usize,
+        needed: usize,
+    },
+    UserEnrolled {
+        userid: MinstrelUserId,
+        enrolled: bool, // false if they were removed
+    },
+    PlaylistRefreshed(MinstrelUserId),
 }
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Clone, Debug, Serialize, Deserialize)]
-// TODO: Add other types of partial broadcasts here after MusicState gets broken up
 pub enum MinstrelBroadcast {
+    /// The whole state, for anyone just connecting or who missed an event
     MusicState(MinstrelWebData),
-    // TODO: This should probably be an enum, so that frontends can display errors as they choose
-    Error(String),
+    /// Events are numbered one after the other, so a gap in `seq` means one was missed
+    Event {
+        seq: u64,
+        event: MinstrelEvent,
+    },
+    Error(MinstrelError),
 }
 
 #[derive(Copy, Clone, Debug)]
-// TODO: maybe impl display
 pub enum UserMgmtError {
     UserExists,
     UserDoesNotExist,
@@ -139,3 +259,27 @@ pub enum UserMgmtError {
     DbError,
     UnknownError,
 }
+
+impl UserMgmtError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::UserExists => ErrorCode::UserExists,
+            Self::UserDoesNotExist => ErrorCode::UserDoesNotExist,
+            Self::InvalidLink => ErrorCode::InvalidLink,
+            Self::DbError => ErrorCode::DbError,
+            Self::UnknownError => ErrorCode::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for UserMgmtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl From<UserMgmtError> for MinstrelError {
+    fn from(e: UserMgmtError) -> Self {
+        e.code().into()
+    }
+}
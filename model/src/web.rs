@@ -6,22 +6,35 @@ use serde::{
     Serialize,
 };
 
-use crate::Requester;
+use crate::{
+    error::ErrorCode,
+    Requester,
+    AutoplayScore,
+    SeekPosition,
+    RequestId,
+    Song,
+};
+
+/// Sent over the websocket by a client that missed an event, to get the whole state again
+pub const WS_RESYNC: &str = "resync";
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum ReplyData {
     UserInfo(Requester),
     LinkInfo(u64),
+    AutoplayScores(Vec<AutoplayScore>),
+    LikedSongs(Vec<Song>),
 }
 
-// TODO: Definitely make this way more robust, consider enuming and consider allowing
-//   payload returns
+// TODO: consider allowing payload returns
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ReplyStatus {
     pub status: u16,
-    // TODO: Consider using MusicOk/MusicError here, and allowing frontends
-    //  to implement their own Display functions
+    // Message to show the user, "ok" or similar on success
     pub error: String,
+    // Set on errors, for clients to act on rather than parsing the message
+    #[serde(default)]
+    pub code: Option<ErrorCode>,
     pub data: Option<ReplyData>,
 }
 
@@ -48,10 +61,48 @@ pub struct LinkRequest {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ApBumpRequest {
-    pub index: usize,
+    pub id: RequestId,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ApToggleRequest {
     pub enabled: bool,
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApStrategyRequest {
+    pub strategy: String, // time, roundrobin, random or catchup
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeekRequest {
+    pub position: SeekPosition,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueueRemoveRequest {
+    pub id: RequestId,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueueMoveRequest {
+    pub id: RequestId,
+    pub target: RequestId, // Takes the place of this request
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct QueueInsertRequest {
+    pub index: usize,
+    pub song: String, // url
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LikeRequest {
+    pub id: Option<RequestId>, // None for the current track, otherwise a track in the history
+    pub liked: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VolumeRequest {
+    pub volume: u8, // percent
+}
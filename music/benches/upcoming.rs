@@ -0,0 +1,77 @@
+use criterion::{
+    criterion_group,
+    criterion_main,
+    BatchSize,
+    Criterion,
+};
+
+use music::autoplay::AutoplayState;
+use music::testutil::test_request;
+use model::{
+    MinstrelUserId,
+    SongRequest,
+};
+
+const USERS: MinstrelUserId = 5;
+const SONGS_PER_USER: usize = 2000;
+const UPCOMING: u64 = 20;
+
+fn playlist(userid: MinstrelUserId) -> Vec<SongRequest> {
+    (0..SONGS_PER_USER)
+        .map(|i| {
+            let mut req = test_request(&format!("{}/{}", userid, i), userid);
+            req.song.artist = format!("Artist {}", i % 50);
+            req.song.duration = 120 + (i as i64 * 7) % 240;
+            req
+        })
+        .collect()
+}
+
+fn autoplay() -> AutoplayState {
+    let mut ap = AutoplayState::new();
+    for userid in 1..=USERS {
+        ap.set_userplaylist(&userid, playlist(userid)).unwrap();
+    }
+    ap.enable_users(&(1..=USERS).collect::<Vec<MinstrelUserId>>());
+    ap.update_upcoming(UPCOMING);
+
+    ap
+}
+
+fn bench_upcoming(c: &mut Criterion) {
+    let mut ap = autoplay();
+
+    // Nothing changed since the last update, e.g. a broadcast for a pause
+    c.bench_function("upcoming unchanged", |b| b.iter(|| {
+        ap.update_upcoming(UPCOMING);
+        ap.upcoming(UPCOMING)
+    }));
+
+    // A song played, only one more needs to be looked ahead
+    c.bench_function("upcoming after next", |b| b.iter(|| {
+        ap.next();
+        ap.update_upcoming(UPCOMING);
+        ap.upcoming(UPCOMING)
+    }));
+
+    // Scores changed, the whole lookahead gets rebuilt
+    c.bench_function("upcoming after score change", |b| b.iter(|| {
+        ap.add_time_to_user(&1, 1);
+        ap.update_upcoming(UPCOMING);
+        ap.upcoming(UPCOMING)
+    }));
+
+    // A fresh playlist for one user, which also has to be shuffled
+    c.bench_function("upcoming after playlist change", |b| b.iter_batched(
+        || playlist(1),
+        |songs| {
+            ap.set_userplaylist(&1, songs).unwrap();
+            ap.update_upcoming(UPCOMING);
+            ap.upcoming(UPCOMING)
+        },
+        BatchSize::SmallInput,
+    ));
+}
+
+criterion_group!(benches, bench_upcoming);
+criterion_main!(benches);
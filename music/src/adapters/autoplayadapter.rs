@@ -5,6 +5,7 @@ use tokio::sync::{
 
 use crate::{
     MusicOk, MusicError,
+    resolver::ResolverPool,
     musicstate::{
         MusicControlCmd,
         MSCMD,
@@ -18,22 +19,35 @@ use crate::{
 };
 
 use model::{
+    AutoplayScore,
     Requester,
     MinstrelUserId,
+    RequestId,
+    Song,
+    SongRequest,
+    Source,
+    SourceType,
 };
 
+use db::DbAdapter;
+use minstrel_config::AutoplayStrategyKind;
+
 use log::*;
 
 
 #[derive(Debug, Clone)]
 pub struct AutoplayAdapter {
     tx: mpsc::Sender<MSCMD>,
+    db: DbAdapter,
+    resolver: ResolverPool,
 }
 
 impl AutoplayAdapter {
-    pub fn new(tx: mpsc::Sender<MSCMD>) -> Self {
+    pub fn new(tx: mpsc::Sender<MSCMD>, db: DbAdapter, resolver: ResolverPool) -> Self {
         Self {
             tx,
+            db,
+            resolver,
         }
     }
 
@@ -43,13 +57,19 @@ impl AutoplayAdapter {
             AutoplayControlCmd::Disable => { ap.disable(); Ok(AutoplayOk::Status(false)) },
             AutoplayControlCmd::Status => { Ok(AutoplayOk::Status(ap.is_enabled())) },
             AutoplayControlCmd::EnableUser(uid) => ap.enable_user(&uid),
+            AutoplayControlCmd::EnableUsers(uids) => { ap.enable_users(&uids); Ok(AutoplayOk::EnrolledUser) },
             AutoplayControlCmd::DisableUser(uid) => ap.disable_user(&uid),
             AutoplayControlCmd::DisableAllUsers => { ap.disable_all_users(); Ok(AutoplayOk::RemovedUser) },
             AutoplayControlCmd::ShuffleUser(uid) => ap.shuffle_user(&uid),
-            AutoplayControlCmd::Rebalance => { ap.reset_usertime(); Ok(AutoplayOk::Status(true)) }, // bs Ok, ignored anyway
-            AutoplayControlCmd::UpdatePlaylist(req) => ap.update_userplaylist(&req).await,
+            AutoplayControlCmd::GetScores => Ok(AutoplayOk::Scores(ap.get_scores())),
+            AutoplayControlCmd::ResetScores => { ap.reset_scores(); Ok(AutoplayOk::ResetScores) },
+            AutoplayControlCmd::SetPlaylist((uid, songs)) => ap.set_userplaylist(&uid, songs),
             AutoplayControlCmd::AdvancePlaylist((uid, num)) => ap.advance_userplaylist(&uid, num),
-            AutoplayControlCmd::BumpPlaylist((uid, ind)) => ap.bump_userplaylist(&uid, ind),
+            AutoplayControlCmd::BumpPlaylist((uid, id)) => ap.bump_userplaylist(&uid, id),
+            AutoplayControlCmd::GetStrategy => Ok(AutoplayOk::Strategy(ap.strategy())),
+            AutoplayControlCmd::SetStrategy(kind) => { ap.set_strategy(kind); Ok(AutoplayOk::Strategy(kind)) },
+            AutoplayControlCmd::GetSeed => Ok(AutoplayOk::Seed(ap.seed())),
+            AutoplayControlCmd::Reseed((seed, time)) => { ap.reseed(seed, time); Ok(AutoplayOk::Seed(ap.seed())) },
         };
 
         match ret {
@@ -103,6 +123,11 @@ impl AutoplayAdapter {
         self.invoke(AutoplayControlCmd::EnableUser(*userid)).await
     }
 
+    /// Enable several users at once, in a random order
+    pub async fn enable_users(&mut self, userids: Vec<MinstrelUserId>) -> Result<AutoplayOk, AutoplayError> {
+        self.invoke(AutoplayControlCmd::EnableUsers(userids)).await
+    }
+
     pub async fn disable_user(&mut self, userid: &MinstrelUserId) -> Result<AutoplayOk, AutoplayError> {
         self.invoke(AutoplayControlCmd::DisableUser(*userid)).await
     }
@@ -116,20 +141,168 @@ impl AutoplayAdapter {
         self.invoke(AutoplayControlCmd::ShuffleUser(*userid)).await
     }
 
-    // This function does not have a return, ignore result from invoke
-    pub async fn reset_usertime(&mut self) {
-        self.invoke(AutoplayControlCmd::Rebalance).await.unwrap();
+    /// Get every user's autoplay score, lowest (next up) first
+    pub async fn get_scores(&mut self) -> Result<Vec<AutoplayScore>, AutoplayError> {
+        let mut scores = match self.invoke(AutoplayControlCmd::GetScores).await? {
+            AutoplayOk::Scores(s) => s,
+            _ => return Err(AutoplayError::UnknownError),
+        };
+
+        // Users without a loaded playlist don't have a name attached yet
+        for score in scores.iter_mut().filter(|s| s.displayname.is_empty()) {
+            if let Ok(req) = self.db.get_requester(score.userid).await {
+                score.displayname = req.displayname;
+            }
+        }
+
+        Ok(scores)
+    }
+
+    /// Reset every user's autoplay score to 0
+    pub async fn reset_scores(&mut self) -> Result<AutoplayOk, AutoplayError> {
+        self.invoke(AutoplayControlCmd::ResetScores).await
+    }
+
+    /// Get which strategy autoplay is using to pick users
+    pub async fn get_strategy(&mut self) -> Result<AutoplayStrategyKind, AutoplayError> {
+        match self.invoke(AutoplayControlCmd::GetStrategy).await? {
+            AutoplayOk::Strategy(s) => Ok(s),
+            _ => Err(AutoplayError::UnknownError),
+        }
     }
 
+    /// Switch the strategy autoplay uses to pick users, this resets everyone's scores
+    pub async fn set_strategy(&mut self, kind: AutoplayStrategyKind) -> Result<AutoplayOk, AutoplayError> {
+        self.invoke(AutoplayControlCmd::SetStrategy(kind)).await
+    }
+
+    /// Get the seed autoplay's random picks are coming from, and the time its shuffles are weighted as of
+    pub async fn get_seed(&mut self) -> Result<(u64, i64), AutoplayError> {
+        match self.invoke(AutoplayControlCmd::GetSeed).await? {
+            AutoplayOk::Seed(s) => Ok(s),
+            _ => Err(AutoplayError::UnknownError),
+        }
+    }
+
+    /// Restart autoplay's random picks from a seed, weighting shuffles as of `time` (or now if None)
+    pub async fn reseed(&mut self, seed: u64, time: Option<i64>) -> Result<AutoplayOk, AutoplayError> {
+        self.invoke(AutoplayControlCmd::Reseed((seed, time))).await
+    }
+
+    /// Reload a user's active sources and swap the result into autoplay.
+    /// Fetching happens here rather than in MusicState, so a slow source doesn't hold up everything else.
+    /// If none of the sources could be loaded, the playlist already in autoplay is kept.
     pub async fn update_userplaylist(&mut self, requester: &Requester) -> Result<AutoplayOk, AutoplayError> {
-        self.invoke(AutoplayControlCmd::UpdatePlaylist(requester.clone())).await
+        let sources = self.db.get_sources_from_userid(requester.id, true).await
+            .map_err(|_| AutoplayError::UnknownError)?;
+
+        let songs = self.fetch_sources(requester, &sources, false).await?;
+
+        self.invoke(AutoplayControlCmd::SetPlaylist((requester.id, songs))).await
+    }
+
+    /// Reload a user's active sources in the background, swapping the result into autoplay once done.
+    /// Failures are only logged, use update_userplaylist to wait on the result instead.
+    pub fn refresh_userplaylist(&self, requester: Requester) {
+        let mut ap = self.clone();
+
+        tokio::spawn(async move {
+            match ap.update_userplaylist(&requester).await {
+                Ok(_) => debug!("refreshed sources for {}", &requester.displayname),
+                Err(e) => error!("failed to refresh sources for {}: {:?}", &requester.displayname, e),
+            }
+        });
+    }
+
+    /// Load the playlists for every user with an active source, e.g. on startup.
+    /// Sources are loaded from the song cache if possible, and only fetched if they have never been fetched before.
+    pub async fn load_all_userplaylists(&mut self) {
+        let users = match self.db.get_active_sources().await {
+            Ok(u) => u,
+            Err(_) => {
+                error!("failed to get active sources from the db, autoplay will be empty");
+                return;
+            }
+        };
+
+        for (reqid, srcs) in users {
+            let req = match self.db.get_requester(reqid).await {
+                Ok(r) => r,
+                Err(_) => {
+                    error!("sources exist for user {} but the user does not, skipping", reqid);
+                    continue;
+                }
+            };
+
+            debug!("loading setlists for user {} from storage", &req.displayname);
+            let songs = match self.fetch_sources(&req, &srcs, true).await {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("failed to load any sources for {}: {:?}", &req.displayname, e);
+                    continue;
+                }
+            };
+
+            if let Err(e) = self.invoke(AutoplayControlCmd::SetPlaylist((reqid, songs))).await {
+                error!("failed to set playlist for {}: {:?}", &req.displayname, e);
+            }
+        }
+    }
+
+    /// Songs from all of a user's sources. Errors only if every source failed, since an empty playlist would
+    /// take the user out of autoplay over what may only be a temporary failure.
+    async fn fetch_sources(&self, requester: &Requester, sources: &[Source], cached: bool) -> Result<Vec<SongRequest>, AutoplayError> {
+        let mut ret = Vec::new();
+        let mut loaded = false;
+
+        for src in sources {
+            // One broken source shouldn't take the rest of the user's sources down with it
+            match self.fetch_source(requester, src, cached).await {
+                Ok(songs) => {
+                    loaded = true;
+                    ret.extend(songs.into_iter().map(|e| SongRequest::new(e, requester.clone())));
+                },
+                Err(e) => error!("failed to load source {:?} for {}: {:?}", &src.path, &requester.displayname, e),
+            }
+        }
+
+        if !sources.is_empty() && !loaded {
+            return Err(AutoplayError::SourcesUnavailable);
+        }
+
+        Ok(ret)
+    }
+
+    /// Get the songs for a single source, updating the song cache whenever it actually gets fetched
+    async fn fetch_source(&self, requester: &Requester, src: &Source, cached: bool) -> Result<Vec<Song>, MusicError> {
+        // Likes are already in the db, there's nothing to fetch or cache
+        if src.path == SourceType::LikedSongs {
+            return self.db.get_liked_songs(requester.id).await.map_err(|_| MusicError::DbError);
+        }
+
+        if cached {
+            match self.db.get_songs_from_source(src.id).await {
+                Ok(songs) if !songs.is_empty() => return Ok(songs),
+                Ok(_) => debug!("no cached songs for source {}, fetching", src.id),
+                Err(_) => error!("failed to read song cache for source {}, fetching", src.id),
+            }
+        }
+
+        let songs = self.resolver.resolve_source(src.path.clone()).await?;
+
+        match self.db.update_source_songs(src.id, &songs).await {
+            Ok((added, removed)) => debug!("refreshed source {}: {} added, {} removed", src.id, added, removed),
+            Err(_) => error!("failed to update song cache for source {}", src.id),
+        }
+
+        Ok(songs)
     }
 
     pub async fn advance_userplaylist(&mut self, userid: &MinstrelUserId, num: u64) -> Result<AutoplayOk, AutoplayError> {
         self.invoke(AutoplayControlCmd::AdvancePlaylist((*userid, num))).await
     }
 
-    pub async fn bump_userplaylist(&mut self, userid: &MinstrelUserId, index: usize) -> Result<AutoplayOk, AutoplayError> {
-        self.invoke(AutoplayControlCmd::BumpPlaylist((*userid, index))).await
+    pub async fn bump_userplaylist(&mut self, userid: &MinstrelUserId, id: RequestId) -> Result<AutoplayOk, AutoplayError> {
+        self.invoke(AutoplayControlCmd::BumpPlaylist((*userid, id))).await
     }
 }
\ No newline at end of file
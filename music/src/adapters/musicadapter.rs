@@ -10,9 +10,14 @@ use crate::{
         MusicControlCmd,
         MSCMD,
     },
+    resolver::ResolverPool,
 };
 
 use model::{
+    MinstrelUserId,
+    RequestId,
+    SeekPosition,
+    Song,
     SongRequest,
 };
 
@@ -28,16 +33,18 @@ pub struct MusicAdapter {
     pub autoplay: AutoplayAdapter,
     pub db: DbAdapter,
     pub user: UserMgmt,
+    pub resolver: ResolverPool,
     bcast: broadcast::Sender<model::MinstrelBroadcast>,
     tx: mpsc::Sender<MSCMD>,
 }
 
 impl MusicAdapter {
-    pub fn new(tx: mpsc::Sender<MSCMD>, bcast: broadcast::Sender<model::MinstrelBroadcast>, db: DbAdapter) -> Self {
+    pub fn new(tx: mpsc::Sender<MSCMD>, bcast: broadcast::Sender<model::MinstrelBroadcast>, db: DbAdapter, resolver: ResolverPool) -> Self {
         Self {
-            autoplay: AutoplayAdapter::new(tx.clone()),
+            autoplay: AutoplayAdapter::new(tx.clone(), db.clone(), resolver.clone()),
             user: UserMgmt::new(db.clone()),
             db,
+            resolver,
             tx,
             bcast,
         }
@@ -53,6 +60,20 @@ impl MusicAdapter {
         }
     }
 
+    /// Look up the metadata for a song, checking the song cache before asking a resolver
+    pub async fn fetch_song(&self, url: String) -> Result<Song, MusicError> {
+        if let Ok(Some(song)) = self.db.get_song_by_path(&url).await {
+            return Ok(song);
+        }
+
+        let song = self.resolver.resolve_song(url).await?;
+
+        // Failing to cache isn't worth failing the request over
+        let _ = self.db.create_song(&song).await;
+
+        Ok(song)
+    }
+
     pub fn subscribe(&self) -> broadcast::Receiver<model::MinstrelBroadcast> {
         self.bcast.subscribe()
     }
@@ -66,6 +87,16 @@ impl MusicAdapter {
         self.invoke(MusicControlCmd::Skip).await
     }
 
+    /// Vote to skip the current track, skips right away if that's enough votes
+    pub async fn vote_skip(&mut self, muid: MinstrelUserId) -> Result<MusicOk, MusicError> {
+        self.invoke(MusicControlCmd::VoteSkip(muid)).await
+    }
+
+    /// Tell MusicState how many are listening, for working out how many skip votes are needed
+    pub async fn set_listeners(&mut self, count: Option<usize>) {
+        self.invoke(MusicControlCmd::SetListeners(count)).await.ok();
+    }
+
     /// Stop the current playing track (if any)
     pub async fn stop(&mut self) -> Result<MusicOk, MusicError> {
         self.invoke(MusicControlCmd::Stop).await
@@ -86,6 +117,26 @@ impl MusicAdapter {
         self.invoke(MusicControlCmd::EnqueueAndPlay(song)).await
     }
 
+    /// Put a track at a position in the queue, 0 being the next song to play
+    pub async fn insert_at(&mut self, index: usize, song: SongRequest) -> Result<MusicOk, MusicError> {
+        self.invoke(MusicControlCmd::InsertAt((index, song))).await
+    }
+
+    /// Put a track at the front of the queue, and start playing if not already
+    pub async fn play_next(&mut self, song: SongRequest) -> Result<MusicOk, MusicError> {
+        self.invoke(MusicControlCmd::PlayNext(song)).await
+    }
+
+    /// Remove a track from the queue, `muid` must be the user who requested it
+    pub async fn remove(&mut self, id: RequestId, muid: MinstrelUserId) -> Result<MusicOk, MusicError> {
+        self.invoke(MusicControlCmd::Remove((id, muid))).await
+    }
+
+    /// Move a track into `target`'s position in the queue, `muid` must be the user who requested it
+    pub async fn move_song(&mut self, id: RequestId, target: RequestId, muid: MinstrelUserId) -> Result<MusicOk, MusicError> {
+        self.invoke(MusicControlCmd::Move((id, target, muid))).await
+    }
+
     pub async fn clear_queue(&mut self) -> Result<MusicOk, MusicError> {
         self.invoke(MusicControlCmd::ClearQueue).await
     }
@@ -94,6 +145,36 @@ impl MusicAdapter {
         self.invoke(MusicControlCmd::ClearHistory).await
     }
 
+    /// Like or unlike the current track (`id` of None), or one from the history
+    pub async fn like_song(&self, muid: MinstrelUserId, id: Option<RequestId>, liked: bool) -> Result<MusicOk, MusicError> {
+        let data = self.get_webdata().await;
+
+        let song = match id {
+            None => data.current_track.ok_or(MusicError::NotPlaying)?,
+            Some(id) => data.current_track.into_iter()
+                .chain(data.history)
+                .find(|r| r.id == id)
+                .ok_or(MusicError::RequestNotFound)?,
+        }.song;
+
+        // Liking twice or unliking something that wasn't liked is harmless, so don't complain about it
+        match liked {
+            true => {
+                self.db.create_song_like(muid, &song).await.map_err(|_| MusicError::DbError)?;
+                Ok(MusicOk::LikedSong)
+            },
+            false => {
+                self.db.delete_song_like(muid, &song.url).await.map_err(|_| MusicError::DbError)?;
+                Ok(MusicOk::UnlikedSong)
+            },
+        }
+    }
+
+    /// Get every song a user has liked
+    pub async fn get_liked_songs(&self, muid: MinstrelUserId) -> Result<Vec<Song>, MusicError> {
+        self.db.get_liked_songs(muid).await.map_err(|_| MusicError::DbError)
+    }
+
     pub async fn get_webdata(&self) -> model::MinstrelWebData {
         match self.invoke(MusicControlCmd::GetData).await {
             Ok(MusicOk::Data(d)) => *d,
@@ -101,14 +182,42 @@ impl MusicAdapter {
         }
     }
 
-    /// Handler to be called by the player when a song ends
+    /// Handler to be called by the player when the song it was given for request `id` ends
     // Ignore the result from invoke, there is no meaningful response here
-    pub async fn song_ended(&mut self) {
-        self.invoke(MusicControlCmd::SongEnded).await.unwrap();
+    pub async fn song_ended(&mut self, id: RequestId) {
+        self.invoke(MusicControlCmd::SongEnded(id)).await.unwrap();
     }
 
     pub async fn previous(&mut self) -> Result<MusicOk, MusicError> {
         self.invoke(MusicControlCmd::Previous).await
     }
 
+    /// Pause the current track
+    pub async fn pause(&mut self) -> Result<MusicOk, MusicError> {
+        self.invoke(MusicControlCmd::Pause).await
+    }
+
+    /// Resume a paused track
+    pub async fn resume(&mut self) -> Result<MusicOk, MusicError> {
+        self.invoke(MusicControlCmd::Resume).await
+    }
+
+    /// Jump to a position in the current track
+    pub async fn seek(&mut self, position: SeekPosition) -> Result<MusicOk, MusicError> {
+        self.invoke(MusicControlCmd::Seek(position)).await
+    }
+
+    /// Set the playback volume, as a percentage
+    pub async fn set_volume(&mut self, volume: u8) -> Result<MusicOk, MusicError> {
+        self.invoke(MusicControlCmd::SetVolume(volume)).await
+    }
+
+    /// Get the current playback volume, as a percentage
+    pub async fn get_volume(&self) -> u8 {
+        match self.invoke(MusicControlCmd::GetVolume).await {
+            Ok(MusicOk::Volume(v)) => v,
+            _ => panic!("get_volume invoke failed, should never happen"),
+        }
+    }
+
 }
@@ -91,6 +91,11 @@ impl UserMgmt {
         }
     }
 
+    /// Whether a user is allowed to change things that affect everyone, e.g. autoplay scores
+    pub fn is_admin(&self, user_id: MinstrelUserId) -> bool {
+        read_config!(user.admins).contains(&user_id)
+    }
+
     /// Create a new User and Auth from the specified information
     pub async fn user_create(&self, auth: AuthType, info: UserInfo) -> Result<MinstrelUserId, UserMgmtError> {
         // Check if the user has already registered, error if so
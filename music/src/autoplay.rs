@@ -1,22 +1,52 @@
-use minstrel_config::read_config;
-use crate::song::*;
+use minstrel_config::{
+    read_config,
+    AutoplayStrategyKind,
+};
+
+use crate::musicstate::next_request_id;
+use crate::autoplaystrategy::{
+    get_strategy,
+    AutoplayStrategy,
+    UserScores,
+};
 
 use model::{
-    Requester,
     SongRequest,
-    MinstrelUserId, Source,
+    MinstrelUserId,
+    RequestId,
+    AutoplayScore,
+};
+use model::error::{
+    ErrorCode,
+    MinstrelError,
 };
-
-use db::DbAdapter;
 
 use std::fmt;
-use std::collections::HashMap;
+use std::sync::Arc;
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+use std::time::{
+    Duration,
+    Instant,
+    SystemTime,
+    UNIX_EPOCH,
+};
 use priority_queue::PriorityQueue;
 use std::cmp::Reverse;
-use rand::seq::SliceRandom;
+use rand::{
+    Rng,
+    SeedableRng,
+    seq::SliceRandom,
+};
+use rand_chacha::ChaCha8Rng;
 use log::*;
 
 
+// How often scores are decayed while running
+const SCORE_DECAY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 #[allow(dead_code)]
 #[non_exhaustive]
 #[derive(Debug)]
@@ -26,17 +56,28 @@ pub enum AutoplayOk {
     UpdatedPlaylist,
     EnrolledUser,
     RemovedUser,
+    ResetScores,
+    Scores(Vec<AutoplayScore>),
+    Strategy(AutoplayStrategyKind),
+    Seed((u64, i64)),
     Ok,
 }
 
 impl fmt::Display for AutoplayOk {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AutoplayOk::Strategy(s) => return write!(f, "Autoplay strategy is {}.", s),
+            AutoplayOk::Seed((s, t)) => return write!(f, "Autoplay seed is {}, shuffling as of {}.", s, t),
+            _ => (),
+        }
+
         #[allow(unreachable_patterns)]
         let ret = match self {
             AutoplayOk::RegisteredUser => "Registered user and playlist for autoplay",
             AutoplayOk::UpdatedPlaylist => "Refreshed playlist, upcoming songs have been shuffled",
             AutoplayOk::EnrolledUser => "Enrolled user for current autoplay",
             AutoplayOk::RemovedUser => "Removed user from current autoplay",
+            AutoplayOk::ResetScores => "Reset all users' autoplay scores to 0.",
             _ => "Unknown response, fill me in!",
         };
 
@@ -52,10 +93,39 @@ pub enum AutoplayError {
     UserNotEnrolled,
     UrlNotPlaylist,
     UserNotRegistered,
+    RequestNotFound,
     ExcessiveSize,
+    SourcesUnavailable,
     UnknownError,
 }
 
+impl AutoplayError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::AlreadyEnrolled => ErrorCode::AlreadyEnrolled,
+            Self::UserNotEnrolled => ErrorCode::NotEnrolled,
+            Self::UrlNotPlaylist => ErrorCode::UrlNotPlaylist,
+            Self::UserNotRegistered => ErrorCode::NoPlaylist,
+            Self::RequestNotFound => ErrorCode::RequestNotFound,
+            Self::ExcessiveSize => ErrorCode::PlaylistTooLarge,
+            Self::SourcesUnavailable => ErrorCode::FailedToRetrieve,
+            Self::UnknownError => ErrorCode::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for AutoplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl From<AutoplayError> for MinstrelError {
+    fn from(e: AutoplayError) -> Self {
+        e.code().into()
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum AutoplayControlCmd {
     Enable,
@@ -63,66 +133,140 @@ pub enum AutoplayControlCmd {
     Status,
     //Register((Requester, Source)),
     EnableUser(MinstrelUserId),
+    EnableUsers(Vec<MinstrelUserId>),
     DisableUser(MinstrelUserId),
     DisableAllUsers,
     ShuffleUser(MinstrelUserId),
-    Rebalance,
-    UpdatePlaylist(Requester),
+    GetScores,
+    ResetScores,
+    // Playlists are fetched outside of MusicState, this just swaps in the result
+    SetPlaylist((MinstrelUserId, Vec<SongRequest>)),
     AdvancePlaylist((MinstrelUserId, u64)),
-    BumpPlaylist((MinstrelUserId, usize)),
+    BumpPlaylist((MinstrelUserId, RequestId)),
+    GetStrategy,
+    SetStrategy(AutoplayStrategyKind),
+    GetSeed,
+    Reseed((u64, Option<i64>)),
 }
 
 
+/// Every random choice autoplay makes goes through this, so a run can be replayed from its seed.
+/// ChaCha8 rather than StdRng, since StdRng's output isn't guaranteed to stay the same across rand versions.
+pub type AutoplayRng = ChaCha8Rng;
+
+// Songs not played for this long are all treated the same
+const MAX_STALE_DAYS: f64 = 90.0;
+
+/// How often and how recently a song has been played
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlayStats {
+    pub count: i64,
+    pub last_played: i64, // unix timestamp
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0)
+}
+
+/// Weight for a song in the shuffle, higher is more likely to come up early
+fn shuffle_weight(stats: Option<&PlayStats>, now: i64) -> f64 {
+    let (count, days) = match stats {
+        Some(s) => (s.count, ((now - s.last_played).max(0) as f64 / 86400.0).min(MAX_STALE_DAYS)),
+        None => (0, MAX_STALE_DAYS),
+    };
+
+    ((1.0 + days) / (1.0 + count as f64)).powf(read_config!(music.autoplay_shuffle_weight))
+}
+
 #[derive(Clone, Debug)]
 // TODO: consider maybe Song here, and appened to a Request later
 struct UserPlaylist {
     index: usize, // For non-destructive randomization, keeping consistent
-    list: Vec<SongRequest>,
+    // Shared with the lookahead copy, which only needs its own when it reorders something
+    list: Arc<Vec<SongRequest>>,
 }
 
 impl UserPlaylist {
     pub fn new(list: Vec<SongRequest>) -> UserPlaylist {
         UserPlaylist {
             index: 0,
-            list,
+            list: Arc::new(list),
         }
     }
 
-    // TODO: implement a better playlist randomizer logic, especially one that avoids
-    //  repeating songs too much
-    pub fn next(&mut self) -> SongRequest {
+    /// Take the next song, skipping ahead past any that were played recently.
+    /// `played_ago` is how many tracks ago a song was played, if recently at all.
+    /// If every remaining song this pass was played recently, the one played longest ago wins.
+    pub fn next(&mut self, played_ago: impl Fn(&SongRequest) -> Option<usize>, plays: &HashMap<String, PlayStats>, now: i64, rng: &mut AutoplayRng) -> SongRequest {
+        // Stop at the first song that wasn't played recently, usually the very first one
+        let mut pick = None;
+        let mut oldest: Option<(usize, usize)> = None;
+        for (i, req) in self.list[self.index..].iter().enumerate() {
+            match played_ago(req) {
+                None => {
+                    pick = Some(i);
+                    break;
+                },
+                Some(ago) => if Some(ago) >= oldest.map(|(_, o)| o) {
+                    oldest = Some((i, ago));
+                },
+            }
+        }
+
+        // Swap rather than remove, so the rest of the shuffled order stays as it was
+        if let Some(pick) = pick.or(oldest.map(|(i, _)| i)).filter(|p| *p > 0) {
+            Arc::make_mut(&mut self.list).swap(self.index, self.index + pick);
+        }
+
         let ret = self.list.get(self.index);
         self.index += 1;
 
         let ret = ret.unwrap().clone();
 
         if self.index >= self.list.len() {
-            self.shuffle();
+            self.shuffle(plays, now, rng);
         }
 
         ret
     }
 
-    /// Re-randomize the user's playlist
-    pub fn shuffle(&mut self) {
-        let mut rng = rand::thread_rng();
-
+    /// Re-randomize the user's playlist, songs that have been played less (and less recently, as of `now`)
+    /// are more likely to go first
+    pub fn shuffle(&mut self, plays: &HashMap<String, PlayStats>, now: i64, rng: &mut AutoplayRng) {
+        // Weighted random sampling (Efraimidis-Spirakis), sorting by ln(u)/w is the same as u^(1/w)
+        //  but doesn't underflow for tiny weights
+        let mut keyed = Arc::make_mut(&mut self.list).drain(..)
+            .map(|s| {
+                let weight = shuffle_weight(plays.get(&s.song.url), now);
+                ((1.0 - rng.gen::<f64>()).ln() / weight, s)
+            })
+            .collect::<Vec<(f64, SongRequest)>>();
+        keyed.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        // Every pass through the list is a new set of requests, so a song that comes up
+        //  again after a reshuffle can't be mistaken for its previous play
         self.index = 0;
-        self.list.shuffle(&mut rng);
+        self.list = Arc::new(keyed.into_iter()
+            .map(|(_, mut s)| {
+                s.id = next_request_id();
+                s
+            })
+            .collect());
     }
 
-    pub fn push_to_end(&mut self, index: usize) -> Result<(), AutoplayError> {
-        // Adjust for the internal non-destructive sequencing
-        let index = self.index + index;
+    /// Move an upcoming song to the very end of the playlist
+    pub fn push_to_end(&mut self, id: RequestId) -> Result<(), AutoplayError> {
+        // Only look at songs that haven't been played yet this pass
+        let index = self.list.iter()
+            .skip(self.index)
+            .position(|r| r.id == id)
+            .ok_or(AutoplayError::RequestNotFound)?;
 
-        if index >= self.list.len() {
-            Err(AutoplayError::ExcessiveSize)
-        } else {
-            let elem = self.list.remove(index);
-            self.list.push(elem);
+        let list = Arc::make_mut(&mut self.list);
+        let elem = list.remove(self.index + index);
+        list.push(elem);
 
-            Ok(())
-        }
+        Ok(())
     }
 }
 
@@ -131,42 +275,60 @@ impl UserPlaylist {
 #[derive(Clone)]
 pub struct AutoplayState {
     // TODO: consider just using UserId here for the index?
-    // TODO: consider Arc'ing the userlist so AutoplayState can be cloned when prefetching songs
     userlists: HashMap<MinstrelUserId, UserPlaylist>,
-    usertime: PriorityQueue<MinstrelUserId, Reverse<i64>>,
+    usertime: UserScores,
     usertimecache: HashMap<MinstrelUserId, i64>,
     enabled: bool,
-    // TODO: make this a global db that all things can access. this is fine for now though.
-    db: DbAdapter,
+    // Most recently played first, for avoiding repeats
+    recent: VecDeque<(String, Instant)>,
+    // Keyed by song url. Shared, since prefetching clones the whole state and never changes this
+    plays: Arc<HashMap<String, PlayStats>>,
+    last_user: Option<MinstrelUserId>,
+    last_decay: Instant, // When scores were last decayed
+    strategy: AutoplayStrategyKind,
+    // Cloned along with everything else, so prefetching sees the same rolls the real thing will
+    seed: u64,
+    // Shuffles weigh how recently songs were played as of this, rather than whenever they happen,
+    //  so replaying a seed gives the same order no matter when it's replayed. Anything played since counts as just played.
+    seed_time: i64,
+    rng: AutoplayRng,
+    // Songs next() is going to return, and a copy of the state as it will be after them.
+    //  Anything other than next() that could change what plays throws these out.
+    upcoming: VecDeque<SongRequest>,
+    lookahead: Option<Box<AutoplayState>>,
 }
 
 // TODO: reconsider the new() constructor here, Default doesn't feel like the right place to load the autoplay.json cache
 // TODO: optimize this entire thing to only request data when actually needed. take advantage of everything being cached.
 #[allow(clippy::new_without_default)]
 impl AutoplayState {
-    pub async fn new(db: DbAdapter) -> AutoplayState {
-
-        let users = db.get_active_sources().await.unwrap();
+    pub fn new() -> AutoplayState {
+        let seed = match read_config!(music.autoplay_seed) {
+            0 => rand::random(),
+            s => s,
+        };
+        let seed_time = match read_config!(music.autoplay_seed_time) {
+            0 => unix_now(),
+            t => t,
+        };
+        info!("autoplay seed is {}, shuffling as of {}", seed, seed_time);
 
-        let mut ret = AutoplayState {
+        AutoplayState {
             userlists: HashMap::new(),
             usertime: PriorityQueue::new(),
             usertimecache: HashMap::new(),
             enabled: false,
-            db,
-        };
-
-        for (reqid, srcs) in users {
-            // Panicking here is fine for now, if there's bad data in the json, let that be caught
-            let req = ret.db.get_requester(reqid).await.unwrap();
-
-            debug!("loading setlists for user {} from storage", &req.displayname);
-            ret.load_sources_for_requester(&req, &srcs).unwrap();
-
-            ret.usertimecache.insert(reqid, 0);
+            recent: VecDeque::new(),
+            plays: Arc::new(HashMap::new()),
+            last_user: None,
+            last_decay: Instant::now(),
+            strategy: read_config!(music.autoplay_strategy),
+            seed,
+            seed_time,
+            rng: AutoplayRng::seed_from_u64(seed),
+            upcoming: VecDeque::new(),
+            lookahead: None,
         }
-
-        ret
     }
 
     pub fn enable(&mut self) {
@@ -181,72 +343,107 @@ impl AutoplayState {
         self.enabled
     }
 
+    /// Number of users currently enrolled
+    pub fn enrolled_count(&self) -> usize {
+        self.usertime.len()
+    }
+
     /// Get the next song to play and increment the play state
     #[allow(clippy::should_implement_trait)] // TODO: actually make autoplay iterable
     pub fn next(&mut self) -> Option<SongRequest> {
-        let ut = match self.usertime.pop() {
-            Some(ut) => ut,
-            None => return None, // No users
-        };
-        let (user, Reverse(mut time)) = ut;
+        let user = self.get_strategy().pick(&self.usertime, self.last_user, &mut self.rng)?; // None if no users
 
         let up = match self.userlists.get_mut(&user) {
             Some(p) => p,
             None => panic!("usertime contains user not in userlist"),
         };
 
-        let song = up.next();
+        let recent = &self.recent;
+        let (tracks, window) = repeat_window();
+        let song = up.next(|s| played_ago(recent, &s.song.url, tracks, window), &self.plays, self.seed_time, &mut self.rng);
 
-        time += song.song.duration;
-        self.usertime.push(user, Reverse(time));
-        self.usertimecache.insert(user, time);
+        // Charging and recording throw out the upcoming songs, hold onto them in case they're still right
+        let mut upcoming = std::mem::take(&mut self.upcoming);
+        let lookahead = self.lookahead.take();
+
+        self.charge_user(&song);
+        self.record_played(&song);
+
+        // Shuffles on the lookahead get their own request IDs, so this also catches a wraparound
+        if upcoming.pop_front().map(|s| s.id) == Some(song.id) {
+            self.upcoming = upcoming;
+            self.lookahead = lookahead;
+        }
 
         Some(song)
     }
 
-    pub fn load_sources_for_requester(&mut self, requester: &Requester, sources: &Vec<Source>) -> Result<AutoplayOk, AutoplayError> {
-        let mut tmpdata = Vec::new();
-        for src in sources {
-            let mut tmp = fetch_songs_from_source(&src.path)
-                .into_iter().map(|e| SongRequest::new(e, requester.clone())).collect();
-            tmpdata.append(&mut tmp);
-        }
+    /// Remember a song that was played, whether it came from autoplay or not
+    pub fn record_played(&mut self, song: &SongRequest) {
+        self.invalidate_upcoming();
+        self.last_user = Some(song.requested_by.id);
+        self.recent.push_front((song.song.url.clone(), Instant::now()));
+
+        // Only keep around what is still inside either window
+        let (tracks, window) = repeat_window();
+        let mut i = 0;
+        self.recent.retain(|(_, played)| {
+            i += 1;
+            i <= tracks || played.elapsed() < window
+        });
+    }
 
+    /// Replace a user's playlist with a freshly loaded one
+    pub fn set_userplaylist(&mut self, userid: &MinstrelUserId, songs: Vec<SongRequest>) -> Result<AutoplayOk, AutoplayError> {
+        self.invalidate_upcoming();
         // If a user has no sources to load (possibly deleted the last one), remove them from the userlists
-        if tmpdata.is_empty(){
-            self.userlists.remove(&requester.id);
+        if songs.is_empty() {
+            self.userlists.remove(userid);
+            self.usertime.remove(userid);
 
             return Ok(AutoplayOk::RemovedUser)
         }
 
-        let mut tmpdata = UserPlaylist::new(tmpdata);
-        tmpdata.shuffle();
+        let mut tmpdata = UserPlaylist::new(songs);
+        tmpdata.shuffle(&self.plays, self.seed_time, &mut self.rng);
 
-        self.userlists.insert(requester.id, tmpdata);
+        self.userlists.insert(*userid, tmpdata);
+        self.usertimecache.entry(*userid).or_insert(0);
 
         Ok(AutoplayOk::UpdatedPlaylist)
     }
 
-    pub fn prefetch(&self, num: u64) -> Option<Vec<SongRequest>> {
-        let num = if num > read_config!(music.autoplay_prefetch_max) {
-            read_config!(music.autoplay_prefetch_max)
-        } else {
-            num
-        };
+    /// Fill in the upcoming songs, up to `num` (capped at `music.autoplay_prefetch_max`).
+    /// Only simulates whatever isn't already known, so this is cheap to call whenever.
+    pub fn update_upcoming(&mut self, num: u64) {
+        let num = num.min(read_config!(music.autoplay_prefetch_max)) as usize;
 
-        let mut ap = self.clone();
-        let mut ret = Vec::new();
+        if self.lookahead.is_none() {
+            self.upcoming.clear();
+            self.lookahead = Some(Box::new(self.clone()));
+        }
 
-        for _ in 0..num {
-            if let Some(song) = ap.next() {
-                ret.push(song);
-            }
-            else {
-                return None; // TODO: return an error here
+        // Unwrap is fine, just filled in above
+        let lookahead = self.lookahead.as_mut().unwrap();
+        while self.upcoming.len() < num {
+            match lookahead.next() {
+                Some(song) => self.upcoming.push_back(song),
+                None => break, // No users
             }
         }
+    }
 
-        Some(ret)
+    /// The next `num` songs autoplay will play, as of the last update_upcoming()
+    pub fn upcoming(&self, num: u64) -> Vec<SongRequest> {
+        self.upcoming.iter()
+            .take(num as usize)
+            .cloned()
+            .collect()
+    }
+
+    fn invalidate_upcoming(&mut self) {
+        self.upcoming.clear();
+        self.lookahead = None;
     }
 
 
@@ -271,27 +468,34 @@ impl AutoplayState {
             return Err(AutoplayError::AlreadyEnrolled);
         }
 
-        let time = match self.usertime.peek() {
-            Some((_, Reverse(lowest))) => {
-                debug!("user re-enabling with a cached score of {}, lowest is {}", prevtime, lowest);
-                if prevtime >= lowest {
-                    *prevtime
-                }
-                else {
-                    lowest - 1
-                }
-            }
-            None => 0,
-        };
-        debug!("user re-enabled with a score of {}", time);
+        let time = self.get_strategy().entry_score(*prevtime, &self.usertime);
+        debug!("user re-enabled with a score of {}, had a cached score of {}", time, prevtime);
 
+        self.invalidate_upcoming();
         self.usertime.push(*userid, Reverse(time));
         self.usertimecache.insert(*userid, time);
 
         Ok(AutoplayOk::EnrolledUser)
     }
 
+    /// Enable a group of users at once, e.g. everyone in a voice channel. They are enabled in a random
+    /// order, so whoever goes first among equal scores isn't down to the order they were listed in.
+    pub fn enable_users(&mut self, userids: &[MinstrelUserId]) {
+        self.invalidate_upcoming();
+
+        let mut userids = userids.to_vec();
+        userids.shuffle(&mut self.rng);
+
+        for userid in userids {
+            match self.enable_user(&userid) {
+                Ok(o) => debug!("enrolling user {}: {:?}", userid, o),
+                Err(e) => debug!("did not enroll user {}: {:?}", userid, e),
+            }
+        }
+    }
+
     pub fn disable_user(&mut self, userid: &MinstrelUserId) -> Result<AutoplayOk, AutoplayError> {
+        self.invalidate_upcoming();
         match self.usertime.remove(userid) {
             Some((user, Reverse(time))) => {
                 self.usertimecache.insert(user, time);
@@ -303,12 +507,14 @@ impl AutoplayState {
 
     /// Remove all users from the PriorityQueue, and set all cached scores to 0.
     pub fn disable_all_users(&mut self) {
+        self.invalidate_upcoming();
         self.usertimecache.iter_mut().for_each(|(_, time)| *time = 0);
         self.usertime.clear();
     }
 
     /// Reset all usertime scores to zero
-    pub fn reset_usertime(&mut self) {
+    pub fn reset_scores(&mut self) {
+        self.invalidate_upcoming();
         // TODO: there might be a more efficient way to do this
         self.usertime = self.usertime.clone()
             .into_iter()
@@ -317,8 +523,78 @@ impl AutoplayState {
         self.usertimecache.iter_mut().for_each(|(_, time)| *time = 0);
     }
 
-    pub fn debug_get_usertime(&self) -> String {
-        format!("{:?}", self.usertime)
+    /// Get everyone's current score, lowest (next up) first
+    pub fn get_scores(&self) -> Vec<AutoplayScore> {
+        let mut ret: Vec<AutoplayScore> = self.usertimecache.iter()
+            .map(|(userid, score)| AutoplayScore {
+                userid: *userid,
+                // Names aren't tracked here, borrow one from the user's playlist if there is one
+                displayname: self.userlists.get(userid)
+                    .and_then(|ul| ul.list.first())
+                    .map(|s| s.requested_by.displayname.clone())
+                    .unwrap_or_default(),
+                score: *score,
+                enabled: self.usertime.get(userid).is_some(),
+            })
+            .collect();
+
+        ret.sort_by_key(|s| s.score);
+        ret
+    }
+
+    /// Raw scores for all known users, enabled or not
+    pub fn get_score_map(&self) -> HashMap<MinstrelUserId, i64> {
+        self.usertimecache.clone()
+    }
+
+    fn get_strategy(&self) -> &'static dyn AutoplayStrategy {
+        get_strategy(self.strategy)
+    }
+
+    pub fn strategy(&self) -> AutoplayStrategyKind {
+        self.strategy
+    }
+
+    /// Switch how users are picked. Scores are reset, since not every strategy counts them the same way.
+    pub fn set_strategy(&mut self, kind: AutoplayStrategyKind) {
+        if self.strategy != kind {
+            self.strategy = kind;
+            self.reset_scores();
+        }
+    }
+
+    /// The seed, and the time shuffles are weighted as of. Both are needed to replay a run.
+    pub fn seed(&self) -> (u64, i64) {
+        (self.seed, self.seed_time)
+    }
+
+    /// Restart the random number generator from a seed, to replay a reported sequence of picks.
+    /// Shuffles are weighted as of `time` if given, otherwise from now on. Replays also need the same
+    /// play counts to weight by, i.e. the same database, unless autoplay_shuffle_weight is 0.
+    pub fn reseed(&mut self, seed: u64, time: Option<i64>) {
+        self.invalidate_upcoming();
+        self.seed = seed;
+        self.seed_time = time.unwrap_or_else(unix_now);
+        self.rng = AutoplayRng::seed_from_u64(seed);
+        info!("autoplay reseeded with {}, shuffling as of {}", seed, self.seed_time);
+    }
+
+    /// Restore saved play counts, used to weight shuffles from here on
+    pub fn load_plays(&mut self, plays: HashMap<String, PlayStats>) {
+        self.plays = Arc::new(plays);
+    }
+
+    /// Count a song as played for the purposes of weighting shuffles
+    pub fn count_play(&mut self, url: &str) {
+        let stats = Arc::make_mut(&mut self.plays).entry(url.to_string()).or_default();
+        stats.count += 1;
+        stats.last_played = unix_now();
+    }
+
+    /// Restore previously saved scores. Users pick these back up when they are next enabled.
+    pub fn load_scores(&mut self, scores: HashMap<MinstrelUserId, i64>) {
+        self.invalidate_upcoming();
+        self.usertimecache.extend(scores);
     }
 
     pub fn debug_enable_all_users(&mut self) {
@@ -331,8 +607,9 @@ impl AutoplayState {
     }
 
     pub fn shuffle_user(&mut self, userid: &MinstrelUserId) -> Result<AutoplayOk, AutoplayError> {
+        self.invalidate_upcoming();
         if let Some(list) = self.userlists.get_mut(userid) {
-            list.shuffle();
+            list.shuffle(&self.plays, self.seed_time, &mut self.rng);
             // TODO: shuffled ok
             Ok(AutoplayOk::EnrolledUser)
         }
@@ -343,22 +620,51 @@ impl AutoplayState {
 
     }
 
+    /// Add a played song to its requester's score, however much the current strategy says it costs
+    pub fn charge_user(&mut self, song: &SongRequest) {
+        self.decay_scores();
+
+        let cost = self.get_strategy().cost(&song.song);
+        self.add_time_to_user(&song.requested_by.id, cost);
+    }
+
+    /// Take back what a song cost its requester, e.g. when it failed to play
+    pub fn refund_user(&mut self, song: &SongRequest) {
+        let cost = self.get_strategy().cost(&song.song);
+        self.add_time_to_user(&song.requested_by.id, -cost);
+    }
+
+    // Decay everyone's score by the time since they were last decayed. Done in steps of at least
+    //  SCORE_DECAY_INTERVAL, so rounding to whole seconds can't cancel out a small decay every time.
+    fn decay_scores(&mut self) {
+        let elapsed = self.last_decay.elapsed();
+        if elapsed < SCORE_DECAY_INTERVAL {
+            return;
+        }
+
+        self.invalidate_upcoming();
+        self.last_decay = Instant::now();
+
+        let secs = elapsed.as_secs();
+        self.usertime = self.usertime.clone()
+            .into_iter()
+            .map(|(userid, Reverse(time))| (userid, Reverse(decay_score(time, secs))))
+            .collect();
+        self.usertimecache.iter_mut().for_each(|(_, time)| *time = decay_score(*time, secs));
+    }
+
     pub fn add_time_to_user(&mut self, userid: &MinstrelUserId, delta: i64) {
+        self.invalidate_upcoming();
         self.usertime.change_priority_by(userid, |Reverse(v)| *v += delta);
         let us = self.usertimecache.entry(*userid).or_insert(0);
         *us += delta;
     }
 
-    pub async fn update_userplaylist(&mut self, requester: &Requester) -> Result<AutoplayOk, AutoplayError> {
-        let sources = self.db.get_sources_from_userid(requester.id, true).await.unwrap();
-
-        self.load_sources_for_requester(requester, &sources)
-    }
-
     pub fn advance_userplaylist(&mut self, userid: &MinstrelUserId, num: u64) -> Result<AutoplayOk, AutoplayError> {
+        self.invalidate_upcoming();
         if let Some(ul) = self.userlists.get_mut(userid) {
             for _ in 0..num {
-                ul.next();
+                ul.next(|_| None, &self.plays, self.seed_time, &mut self.rng);
             }
 
             Ok(AutoplayOk::Ok)
@@ -368,14 +674,11 @@ impl AutoplayState {
     }
 
     /// Remove a song from a user's upcoming songs
-    pub fn bump_userplaylist(&mut self, userid: &MinstrelUserId, index: usize) -> Result<AutoplayOk, AutoplayError> {
-        if index > read_config!(music.autoplay_prefetch_max) as usize {
-            // TODO: replace with a better error
-            return Err(AutoplayError::ExcessiveSize)
-        }
-
-        if let Some(ul) = self.userlists.get_mut(userid) {
-            ul.push_to_end(index).map_err(|_| AutoplayError::ExcessiveSize)?;
+    pub fn bump_userplaylist(&mut self, userid: &MinstrelUserId, id: RequestId) -> Result<AutoplayOk, AutoplayError> {
+        self.invalidate_upcoming();
+        match self.userlists.get_mut(userid) {
+            Some(ul) => ul.push_to_end(id)?,
+            None => return Err(AutoplayError::UserNotRegistered),
         }
 
         Ok(AutoplayOk::Ok)
@@ -383,3 +686,144 @@ impl AutoplayState {
 }
 
 
+
+
+/// Shrink a score based on how much time has passed, so old scores don't haunt a user forever.
+/// Scores are halved every `music.autoplay_score_halflife` hours, 0 disables decay.
+pub fn decay_score(score: i64, elapsed_secs: u64) -> i64 {
+    let halflife = read_config!(music.autoplay_score_halflife) * 60 * 60;
+    if halflife == 0 {
+        return score;
+    }
+
+    let factor = 0.5f64.powf(elapsed_secs as f64 / halflife as f64);
+
+    (score as f64 * factor).round() as i64
+}
+
+// How many tracks back, and how far back in time a song counts as recently played
+fn repeat_window() -> (usize, Duration) {
+    (
+        read_config!(music.autoplay_repeat_tracks),
+        Duration::from_secs(read_config!(music.autoplay_repeat_minutes) * 60),
+    )
+}
+
+// How many tracks ago a song was last played, if that was within the repeat window
+fn played_ago(recent: &VecDeque<(String, Instant)>, url: &str, tracks: usize, window: Duration) -> Option<usize> {
+    recent.iter()
+        .enumerate()
+        .position(|(i, (u, played))| u == url && (i < tracks || played.elapsed() < window))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::test_request;
+
+    fn playlist(userid: MinstrelUserId, titles: &[&str]) -> Vec<SongRequest> {
+        titles.iter().map(|t| test_request(t, userid)).collect()
+    }
+
+    #[test]
+    fn test_no_repeats() {
+        let mut ap = AutoplayState::new();
+        ap.set_userplaylist(&1, playlist(1, &["a", "b", "c"])).unwrap();
+        ap.enable_user(&1).unwrap();
+
+        // With every song recently played, wrapping around to a new shuffle still goes oldest first
+        let played = (0..30)
+            .map(|_| ap.next().unwrap().song.url)
+            .collect::<Vec<String>>();
+        assert!(played.windows(3).all(|w| w[0] != w[1] && w[1] != w[2] && w[0] != w[2]));
+    }
+
+    #[test]
+    fn test_no_back_to_back_users() {
+        let mut ap = AutoplayState::new();
+        ap.set_userplaylist(&1, playlist(1, &["a1", "a2", "a3"])).unwrap();
+        ap.set_userplaylist(&2, playlist(2, &["b1", "b2", "b3"])).unwrap();
+        ap.enable_user(&1).unwrap();
+        ap.enable_user(&2).unwrap();
+
+        // User 1 is way behind, but still has to wait for user 2 in between
+        ap.add_time_to_user(&2, 1000);
+
+        let users = (0..4)
+            .map(|_| ap.next().unwrap().requested_by.id)
+            .collect::<Vec<MinstrelUserId>>();
+        assert_eq!(users, [1, 2, 1, 2]);
+    }
+
+    #[test]
+    fn test_seeded_replay() {
+        // Shuffles are weighted by these with the default autoplay_shuffle_weight
+        let day = 86400;
+        let plays = HashMap::from([
+            ("https://example.com/a1".to_string(), PlayStats { count: 5, last_played: 1_000_000 - day }),
+            ("https://example.com/a3".to_string(), PlayStats { count: 1, last_played: 1_000_000 - 10 * day }),
+            ("https://example.com/b2".to_string(), PlayStats { count: 2, last_played: 1_000_000 - 3 * day }),
+        ]);
+
+        let run = |seed| {
+            let mut ap = AutoplayState::new();
+            ap.load_plays(plays.clone());
+            ap.reseed(seed, Some(1_000_000));
+            ap.set_strategy(AutoplayStrategyKind::Random);
+            ap.set_userplaylist(&1, playlist(1, &["a1", "a2", "a3", "a4"])).unwrap();
+            ap.set_userplaylist(&2, playlist(2, &["b1", "b2", "b3", "b4"])).unwrap();
+            ap.enable_users(&[1, 2]);
+
+            (0..12)
+                .map(|_| ap.next().unwrap().song.url)
+                .collect::<Vec<String>>()
+        };
+
+        assert_eq!(run(1234), run(1234));
+    }
+
+    #[test]
+    fn test_upcoming_matches_next() {
+        let mut ap = AutoplayState::new();
+        ap.set_userplaylist(&1, playlist(1, &["a1", "a2", "a3"])).unwrap();
+        ap.set_userplaylist(&2, playlist(2, &["b1", "b2", "b3"])).unwrap();
+        ap.enable_users(&[1, 2]);
+
+        ap.update_upcoming(5);
+        let predicted = ap.upcoming(5).iter().map(|s| s.id).collect::<Vec<RequestId>>();
+        let played = (0..5).map(|_| ap.next().unwrap().id).collect::<Vec<RequestId>>();
+        assert_eq!(predicted, played);
+
+        // Anything that changes the order throws out the prediction
+        ap.update_upcoming(5);
+        ap.add_time_to_user(&1, 1000);
+        assert!(ap.upcoming(5).is_empty());
+    }
+
+    #[test]
+    fn test_strategies() {
+        let mut ap = AutoplayState::new();
+        let mut long = playlist(1, &["a1", "a2", "a3"]);
+        long.iter_mut().for_each(|s| s.song.duration = 600);
+        ap.set_userplaylist(&1, long).unwrap();
+        ap.set_userplaylist(&2, playlist(2, &["b1", "b2", "b3"])).unwrap();
+        ap.set_userplaylist(&3, playlist(3, &["c1", "c2", "c3"])).unwrap();
+
+        // Song length doesn't matter in round robin
+        ap.set_strategy(AutoplayStrategyKind::RoundRobin);
+        ap.enable_user(&1).unwrap();
+        ap.enable_user(&2).unwrap();
+        ap.next().unwrap();
+        ap.next().unwrap();
+        let scores = ap.get_score_map();
+        assert_eq!((scores[&1], scores[&2]), (1, 1));
+
+        // Catching up re-enters at the average instead of going first
+        ap.set_strategy(AutoplayStrategyKind::CatchUp);
+        ap.add_time_to_user(&1, 100);
+        ap.add_time_to_user(&2, 300);
+        ap.enable_user(&3).unwrap();
+        assert_eq!(ap.get_score_map()[&3], 200);
+    }
+}
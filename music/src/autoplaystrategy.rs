@@ -0,0 +1,135 @@
+use minstrel_config::{
+    read_config,
+    AutoplayStrategyKind,
+};
+
+use model::{
+    MinstrelUserId,
+    Song,
+};
+
+use crate::autoplay::AutoplayRng;
+
+use priority_queue::PriorityQueue;
+use std::cmp::Reverse;
+use rand::Rng;
+
+
+/// Enrolled users and their scores, lowest score on top
+pub type UserScores = PriorityQueue<MinstrelUserId, Reverse<i64>>;
+
+/// Decides whose turn it is in autoplay, what a played song costs its requester, and where someone
+/// enrolling (or re-enrolling) slots in. The default methods are the original time-fairness behavior.
+pub trait AutoplayStrategy: Send + Sync {
+    /// How much a song adds to its requester's score
+    fn cost(&self, song: &Song) -> i64 {
+        song.duration
+    }
+
+    /// Pick whose song plays next. `last` is whoever had the last song played, from autoplay or not
+    fn pick(&self, scores: &UserScores, last: Option<MinstrelUserId>, _rng: &mut AutoplayRng) -> Option<MinstrelUserId> {
+        let (lowest, _) = scores.peek()?;
+
+        // Let the runner-up go instead if the lowest user just had a song played
+        if Some(*lowest) == last && read_config!(music.autoplay_avoid_same_user) {
+            let runnerup = scores.iter()
+                .filter(|(u, _)| *u != lowest)
+                // Scores are Reverse'd, so the max is the lowest remaining
+                .max_by_key(|(_, time)| *time)
+                .map(|(u, _)| *u);
+
+            if runnerup.is_some() {
+                return runnerup;
+            }
+        }
+
+        Some(*lowest)
+    }
+
+    /// Score for a user being enrolled, given the score they had when they were last enrolled.
+    /// Keeps their old score unless it is below everyone else's, then they go just ahead of the lowest.
+    fn entry_score(&self, prev: i64, scores: &UserScores) -> i64 {
+        match scores.peek() {
+            Some((_, Reverse(lowest))) if prev >= *lowest => prev,
+            Some((_, Reverse(lowest))) => lowest - 1,
+            None => 0,
+        }
+    }
+}
+
+/// Lowest total seconds played goes next
+pub struct TimeStrategy;
+
+impl AutoplayStrategy for TimeStrategy {}
+
+/// Everyone gets one song per turn, no matter how long it is
+pub struct RoundRobinStrategy;
+
+impl AutoplayStrategy for RoundRobinStrategy {
+    fn cost(&self, _song: &Song) -> i64 {
+        1
+    }
+}
+
+/// Random pick, weighted against users who have had more than their share of the time played
+pub struct RandomStrategy;
+
+impl AutoplayStrategy for RandomStrategy {
+    fn pick(&self, scores: &UserScores, last: Option<MinstrelUserId>, rng: &mut AutoplayRng) -> Option<MinstrelUserId> {
+        let mut users = scores.iter()
+            .map(|(u, Reverse(time))| (*u, *time))
+            .collect::<Vec<(MinstrelUserId, i64)>>();
+
+        if users.len() > 1 && read_config!(music.autoplay_avoid_same_user) {
+            users.retain(|(u, _)| Some(*u) != last);
+        }
+
+        let lowest = users.iter().map(|(_, t)| *t).min()?;
+        let total = users.iter().map(|(_, t)| t - lowest).sum::<i64>();
+        let floor = 1.0 / users.len() as f64;
+
+        // Share of the time played past the lowest user, everyone keeps at least some chance of going
+        let weights = users.iter()
+            .map(|(_, t)| match total {
+                0 => 1.0,
+                _ => 1.0 + floor - (t - lowest) as f64 / total as f64,
+            })
+            .collect::<Vec<f64>>();
+
+        let mut roll = rng.gen::<f64>() * weights.iter().sum::<f64>();
+        for ((user, _), weight) in users.iter().zip(weights) {
+            if roll < weight {
+                return Some(*user);
+            }
+            roll -= weight;
+        }
+
+        users.last().map(|(u, _)| *u)
+    }
+}
+
+/// Same as time, but someone re-enrolling is brought up to the average score, rather than jumping
+/// to the front of the line
+pub struct CatchUpStrategy;
+
+impl AutoplayStrategy for CatchUpStrategy {
+    fn entry_score(&self, prev: i64, scores: &UserScores) -> i64 {
+        if scores.is_empty() {
+            return 0;
+        }
+
+        let average = scores.iter().map(|(_, Reverse(t))| *t).sum::<i64>() / scores.len() as i64;
+
+        prev.max(average)
+    }
+}
+
+/// Get the strategy implementation for a configured kind
+pub fn get_strategy(kind: AutoplayStrategyKind) -> &'static dyn AutoplayStrategy {
+    match kind {
+        AutoplayStrategyKind::Time => &TimeStrategy,
+        AutoplayStrategyKind::RoundRobin => &RoundRobinStrategy,
+        AutoplayStrategyKind::Random => &RandomStrategy,
+        AutoplayStrategyKind::CatchUp => &CatchUpStrategy,
+    }
+}
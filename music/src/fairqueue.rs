@@ -0,0 +1,145 @@
+use std::collections::{
+    HashMap,
+    VecDeque,
+};
+
+use model::{
+    MinstrelUserId,
+    SongRequest,
+};
+
+
+/// Keeps track of how much each requester has had played from the queue, so that requests
+/// can be slotted in turn by turn. Works the same way as autoplay's usertime: a requester's
+/// time goes up by a song's duration as it is dequeued, and lowest time goes next.
+#[derive(Clone, Debug, Default)]
+pub struct FairQueue {
+    usertime: HashMap<MinstrelUserId, i64>,
+}
+
+impl FairQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Charge a requester for a song that was taken off the queue
+    pub fn charge(&mut self, userid: &MinstrelUserId, secs: i64) {
+        *self.usertime.entry(*userid).or_insert(0) += secs;
+    }
+
+    /// Give back time charged for a song that never actually played
+    pub fn refund(&mut self, userid: &MinstrelUserId, secs: i64) {
+        // Nothing was charged if the turns were reset when the song was taken off
+        if let Some(time) = self.usertime.get_mut(userid) {
+            *time -= secs;
+        }
+    }
+
+    /// Forget all accumulated time, everyone starts even again
+    pub fn clear(&mut self) {
+        self.usertime.clear();
+    }
+
+    /// Get the time a requester starts with, entering them if they have nothing queued.
+    /// Same as autoplay's enable_user, a returning requester keeps their old time unless it is
+    /// below everyone else's, in which case they go just ahead of the lowest.
+    fn enter(&mut self, queue: &VecDeque<SongRequest>, userid: &MinstrelUserId) -> i64 {
+        if queue.iter().any(|r| r.requested_by.id == *userid) {
+            return *self.usertime.entry(*userid).or_insert(0);
+        }
+
+        let lowest = queue.iter()
+            .map(|r| self.usertime.get(&r.requested_by.id).copied().unwrap_or(0))
+            .min();
+        let prev = self.usertime.get(userid).copied();
+
+        let time = match (lowest, prev) {
+            (None, prev) => prev.unwrap_or(0),
+            (Some(lowest), Some(prev)) if prev >= lowest => prev,
+            (Some(lowest), _) => lowest - 1,
+        };
+
+        self.usertime.insert(*userid, time);
+        time
+    }
+
+    /// Find where a new request from `userid` belongs in the queue. Existing requests keep their
+    /// order, the new one goes after the requester's own songs, ahead of the first song whose
+    /// requester will have had more time played by then.
+    pub fn position(&mut self, queue: &VecDeque<SongRequest>, userid: &MinstrelUserId) -> usize {
+        let start = self.enter(queue, userid);
+
+        let mine = start + queue.iter()
+            .filter(|r| r.requested_by.id == *userid)
+            .map(|r| r.song.duration)
+            .sum::<i64>();
+        let after = queue.iter()
+            .rposition(|r| r.requested_by.id == *userid)
+            .map(|p| p + 1)
+            .unwrap_or(0);
+
+        // Walk the queue as it would play out, tracking each requester's time along the way
+        let mut times = HashMap::new();
+        for (i, req) in queue.iter().enumerate() {
+            let uid = req.requested_by.id;
+            let time = times.entry(uid)
+                .or_insert_with(|| self.usertime.get(&uid).copied().unwrap_or(0));
+
+            if i >= after && *time > mine {
+                return i;
+            }
+
+            *time += req.song.duration;
+        }
+
+        queue.len()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::test_request;
+
+    fn enqueue(fq: &mut FairQueue, queue: &mut VecDeque<SongRequest>, title: &str, userid: MinstrelUserId) {
+        let pos = fq.position(queue, &userid);
+        queue.insert(pos, test_request(title, userid));
+    }
+
+    fn titles(queue: &VecDeque<SongRequest>) -> Vec<&str> {
+        queue.iter().map(|r| r.song.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_fair_queue_interleaves() {
+        let mut fq = FairQueue::new();
+        let mut queue = VecDeque::new();
+
+        for t in ["a1", "a2", "a3"] {
+            enqueue(&mut fq, &mut queue, t, 1);
+        }
+        enqueue(&mut fq, &mut queue, "b1", 2);
+        enqueue(&mut fq, &mut queue, "b2", 2);
+        enqueue(&mut fq, &mut queue, "c1", 3);
+
+        assert_eq!(titles(&queue), ["c1", "b1", "a1", "b2", "a2", "a3"]);
+    }
+
+    #[test]
+    fn test_fair_queue_charges() {
+        let mut fq = FairQueue::new();
+        let mut queue = VecDeque::new();
+
+        enqueue(&mut fq, &mut queue, "a1", 1);
+        enqueue(&mut fq, &mut queue, "a2", 1);
+
+        // a1 plays, then b shows up: they go ahead of a2 since a has already had a turn
+        let played = queue.pop_front().unwrap();
+        fq.charge(&played.requested_by.id, played.song.duration);
+        enqueue(&mut fq, &mut queue, "b1", 2);
+        enqueue(&mut fq, &mut queue, "b2", 2);
+
+        assert_eq!(titles(&queue), ["b1", "a2", "b2"]);
+    }
+}
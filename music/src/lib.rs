@@ -1,9 +1,19 @@
 pub mod autoplay;
+pub mod autoplaystrategy;
 pub mod musicstate;
 pub mod song;
+pub mod local;
+pub mod playlist;
+pub mod fairqueue;
+pub mod resolver;
 pub mod player;
+pub mod simulatedplayer;
+pub mod streamplayer;
 pub mod adapters;
 
+#[cfg(any(test, feature = "test-util"))]
+pub mod testutil;
+
 // Re-exports for the sake of making the imports prettier in main.rs
 //  Probably not necessary, can be changed in the next big rework
 pub use musicstate::MusicState as MusicState;
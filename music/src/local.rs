@@ -0,0 +1,115 @@
+use super::MusicError;
+
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use walkdir::WalkDir;
+use lofty::{
+    Accessor,
+    AudioFile,
+};
+
+use minstrel_config::read_config;
+use model::Song;
+
+use log::*;
+
+// Only bother reading tags from files that look like audio
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "ogg", "opus", "m4a", "aac", "wav", "wma"];
+
+/// Resolve a user-supplied path against the configured local music root.
+/// Refuses anything that ends up outside of the root, so users can't go poking around the filesystem.
+pub fn resolve_local_path(path: &str) -> Result<PathBuf, MusicError> {
+    let root = read_config!(music.local_music_root).clone();
+    if root.is_empty() {
+        return Err(MusicError::LocalSourcesDisabled);
+    }
+
+    let root = Path::new(&root).canonicalize().map_err(|e| {
+        error!("could not resolve local music root {}: {:?}", root, e);
+        MusicError::LocalSourcesDisabled
+    })?;
+
+    // Absolute paths replace the root in a join, which is then caught by the starts_with check
+    let resolved = root.join(path).canonicalize().map_err(|e| {
+        debug!("could not resolve local path {}: {:?}", path, e);
+        MusicError::InvalidSource
+    })?;
+
+    if !resolved.starts_with(&root) {
+        warn!("local path {} resolved outside of the music root, refusing", path);
+        return Err(MusicError::InvalidSource);
+    }
+
+    Ok(resolved)
+}
+
+fn is_audio_file(path: &Path) -> bool {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+/// Read the metadata of a single audio file into a Song.
+/// Falls back to the file name if the file has no title tag.
+pub fn song_from_file(path: &Path) -> Result<Song, MusicError> {
+    let tagged = lofty::read_from_path(path, true).map_err(|e| {
+        debug!("failed to read tags from {}: {:?}", path.display(), e);
+        MusicError::Unplayable
+    })?;
+
+    let duration = tagged.properties().duration().as_secs() as i64;
+    let tag = tagged.primary_tag().or_else(|| tagged.first_tag());
+
+    let title = tag.and_then(|t| t.title().map(|t| t.to_string()))
+        .unwrap_or_else(|| path.file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("Unknown")));
+    let artist = tag.and_then(|t| t.artist().map(|a| a.to_string()))
+        .unwrap_or_else(|| String::from("Unknown"));
+
+    Ok(Song {
+        title,
+        artist,
+        url: path.to_string_lossy().into_owned(),
+        thumbnail: String::new(),
+        duration,
+    })
+}
+
+/// Recursively scan a directory (relative to the local music root) for audio files
+pub fn fetch_songs_from_directory(path: &str) -> Result<Vec<Song>, MusicError> {
+    let dir = resolve_local_path(path)?;
+    if !dir.is_dir() {
+        return Err(MusicError::InvalidSource);
+    }
+
+    // Symlinks are not followed, they could point anywhere on the host and skip the root check
+    let songs = WalkDir::new(&dir)
+        .into_iter()
+        .filter_map(|e| match e {
+            Ok(e) => Some(e),
+            Err(e) => {
+                warn!("error walking {}: {:?}", dir.display(), e);
+                None
+            }
+        })
+        .filter(|e| e.file_type().is_file() && is_audio_file(e.path()))
+        .filter_map(|e| match song_from_file(e.path()) {
+            // Songs without a duration would never count against a user's autoplay time
+            Ok(song) if song.duration > 0 => Some(song),
+            Ok(_) => {
+                debug!("skipping {}, could not determine duration", e.path().display());
+                None
+            },
+            Err(_) => None,
+        })
+        .collect::<Vec<Song>>();
+
+    debug!("found {} songs in {}", songs.len(), dir.display());
+
+    Ok(songs)
+}
@@ -1,23 +1,43 @@
 use super::autoplay::{
+    decay_score,
     AutoplayState,
+    PlayStats,
     AutoplayControlCmd,
     AutoplayOk,
     AutoplayError,
 };
 
 use std::{
-    collections::VecDeque,
+    collections::{
+        HashMap,
+        HashSet,
+        VecDeque,
+    },
     fmt,
     fs::OpenOptions,
     io::Write,
+    sync::{
+        Arc,
+        atomic::{
+            AtomicU64,
+            Ordering,
+        },
+    },
+    time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    },
 };
 
 use chrono::offset::Local;
 
+use tokio::time::Instant;
 use tokio::sync::{
     oneshot,
     mpsc,
     broadcast,
+    watch,
 };
 
 use log::*;
@@ -31,11 +51,32 @@ use crate::adapters::{
     MusicAdapter,
     AutoplayAdapter,
 };
+use crate::resolver::{
+    SongResolver,
+    ResolverPool,
+};
+use crate::fairqueue::FairQueue;
 
-use minstrel_config::read_config;
+use minstrel_config::{
+    read_config,
+    PlayerKind,
+    QueueMode,
+    SkipVoteBasis,
+};
+use model::error::{
+    ErrorCode,
+    MinstrelError,
+};
 use model::{
+    MinstrelUserId,
+    RequestId,
+    SeekPosition,
     SongRequest,
+    MAX_VOLUME,
+    AutoplayScore,
     MinstrelBroadcast,
+    MinstrelEvent,
+    MinstrelWebData,
     MusicStateStatus,
 };
 use db::DbAdapter;
@@ -51,6 +92,15 @@ pub enum MusicOk {
     EmptyQueue,
     NothingToPlay,
     SkippingSong,
+    Paused,
+    Resumed,
+    Seeked,
+    RemovedSong,
+    MovedSong,
+    Volume(u8),
+    SkipVoted { votes: usize, needed: usize },
+    LikedSong,
+    UnlikedSong,
     Data(Box<model::MinstrelWebData>),
     AutoplayOk(AutoplayOk),
     Unimplemented
@@ -60,6 +110,8 @@ impl fmt::Display for MusicOk {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         #[allow(unreachable_patterns)]
         let ret = match self {
+            MusicOk::Volume(v)      => return write!(f, "Volume is {}%.", v),
+            MusicOk::SkipVoted { votes, needed } => return write!(f, "Voted to skip ({}/{}).", votes, needed),
             MusicOk::StartedPlaying => "Started playing.",
             MusicOk::StoppedPlaying => "Stopped playing.",
             MusicOk::NotPlaying     => "Not currently playing.",
@@ -67,6 +119,13 @@ impl fmt::Display for MusicOk {
             MusicOk::EmptyQueue     => "Queue is empty.",
             MusicOk::NothingToPlay  => "Nothing to play.",
             MusicOk::SkippingSong   => "Skipping song.",
+            MusicOk::Paused         => "Paused playback.",
+            MusicOk::Resumed        => "Resumed playback.",
+            MusicOk::Seeked         => "Seeked.",
+            MusicOk::RemovedSong    => "Removed song from queue.",
+            MusicOk::MovedSong      => "Moved song in queue.",
+            MusicOk::LikedSong      => "Liked song.",
+            MusicOk::UnlikedSong    => "Unliked song.",
             MusicOk::Unimplemented  => "Unimplemented Ok message",
             _ => "Unknown response, fill me in!",
         };
@@ -78,18 +137,69 @@ impl fmt::Display for MusicOk {
 
 #[allow(dead_code)]
 #[non_exhaustive]
-#[derive(Debug)] // TODO: maybe just implement Display here, so that error messages are automatic?
+#[derive(Debug)]
 pub enum MusicError {
     UnknownError, // TODO: try to replace all UnknownError usages with better errors
     AlreadyPlaying,
     QueueFull,
     InvalidUrl,
+    InvalidSource,
+    LocalSourcesDisabled,
     FailedToRetrieve,
+    Unplayable,
+    ResolverTimeout,
     EmptyHistory,
+    NotPlaying,
+    NotPaused,
+    InvalidVolume,
+    RequestNotFound,
+    NotRequester,
+    QueueJump,
     PlaybackFailed,
+    AlreadyVoted,
+    DbError,
     AutoplayError(AutoplayError),
 }
 
+impl MusicError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::UnknownError => ErrorCode::Unknown,
+            Self::AlreadyPlaying => ErrorCode::AlreadyPlaying,
+            Self::QueueFull => ErrorCode::QueueFull,
+            Self::InvalidUrl => ErrorCode::InvalidUrl,
+            Self::InvalidSource => ErrorCode::InvalidSource,
+            Self::LocalSourcesDisabled => ErrorCode::LocalSourcesDisabled,
+            Self::FailedToRetrieve => ErrorCode::FailedToRetrieve,
+            Self::Unplayable => ErrorCode::Unplayable,
+            Self::ResolverTimeout => ErrorCode::ResolverTimeout,
+            Self::EmptyHistory => ErrorCode::EmptyHistory,
+            Self::NotPlaying => ErrorCode::NotPlaying,
+            Self::NotPaused => ErrorCode::NotPaused,
+            Self::InvalidVolume => ErrorCode::InvalidVolume,
+            Self::RequestNotFound => ErrorCode::RequestNotFound,
+            Self::NotRequester => ErrorCode::NotRequester,
+            Self::QueueJump => ErrorCode::QueueJump,
+            Self::PlaybackFailed => ErrorCode::PlaybackFailed,
+            Self::AlreadyVoted => ErrorCode::AlreadyVoted,
+            Self::DbError => ErrorCode::DbError,
+            Self::AutoplayError(e) => e.code(),
+        }
+    }
+}
+
+impl fmt::Display for MusicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl From<MusicError> for MinstrelError {
+    fn from(e: MusicError) -> Self {
+        e.code().into()
+    }
+}
+
 
 #[derive(Clone, Debug)]
 pub enum MusicControlCmd {
@@ -99,14 +209,41 @@ pub enum MusicControlCmd {
     Start,
     Enqueue(SongRequest),
     EnqueueAndPlay(SongRequest),
+    InsertAt((usize, SongRequest)),
+    PlayNext(SongRequest),
+    Remove((RequestId, MinstrelUserId)),
+    Move((RequestId, RequestId, MinstrelUserId)),
     ClearQueue,
     ClearHistory,
     Previous,
-    SongEnded,
+    Pause,
+    Resume,
+    Seek(SeekPosition),
+    SetVolume(u8),
+    GetVolume,
+    SongEnded(RequestId),
+    VoteSkip(MinstrelUserId),
+    SetListeners(Option<usize>),
     GetData,
     AutoplayCmd(AutoplayControlCmd),
 }
 
+// How often to save the progress of the current track, in seconds
+const PROGRESS_SAVE_INTERVAL: u64 = 10;
+
+// A single change can send several events, e.g. a song ending also changes history, queue and the current track
+const BROADCAST_BUFFER: usize = 64;
+
+// How long after the watchdog stops a stalled song to wait for the player to report it ended
+const WATCHDOG_STOP_WAIT: Duration = Duration::from_secs(5);
+
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Get a new ID for a song request, unique for the life of the process
+pub fn next_request_id() -> RequestId {
+    NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 pub type MusicResult = Result<MusicOk, MusicError>;
 pub type MSCMD = (oneshot::Sender<MusicResult>, MusicControlCmd);
 
@@ -117,15 +254,74 @@ pub struct MusicState {
     player: mpsc::Sender<MPCMD>,
     // TODO: Perhaps put this in a higher level lock, so maybe it's automatic?
     bcast: broadcast::Sender<model::MinstrelBroadcast>,
+    seq: u64, // Number of the last event broadcast
+    last_broadcast: Option<(MinstrelWebData, Instant)>, // What clients were last told, to send only what changed
     cmd_channel: (mpsc::Sender<MSCMD>, mpsc::Receiver<MSCMD>),
 
     current_track: Option<SongRequest>,
-    songstarted: Option<std::time::Instant>,
+    songprogress: Option<SongProgress>,
     status: MusicStateStatus,
+    watchdog_stopped: Option<Instant>, // When the watchdog stopped a stalled song, if it has
+    resume_at: Option<(RequestId, Duration)>, // Where to pick the song interrupted by a restart back up from
+    timescale: f64, // How many seconds of song the player gets through per real second
+    skip_votes: HashSet<MinstrelUserId>, // Who has voted to skip the current track
+    listeners: Option<usize>, // How many are listening to the player, if it can tell
+    volume: u8, // percent
     queue: VecDeque<SongRequest>,
+    fairqueue: FairQueue, // Only used in QueueMode::Fair
     history: VecDeque<SongRequest>,
     pub autoplay: AutoplayState,
     adapter: MusicAdapter, // To work around adapters possibly having unique state due to chained constructors
+    persist: watch::Sender<PlayStateSnapshot>,
+}
+
+/// Tracks how far into the current song we are, not counting time spent paused
+#[derive(Clone, Copy, Debug)]
+struct SongProgress {
+    resumed: Option<Instant>, // None while paused
+    elapsed: Duration,        // Time played up until the last pause
+}
+
+impl SongProgress {
+    fn start() -> Self {
+        Self {
+            resumed: Some(Instant::now()),
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    fn pause(&mut self) {
+        if let Some(resumed) = self.resumed.take() {
+            self.elapsed += resumed.elapsed();
+        }
+    }
+
+    fn resume(&mut self) {
+        if self.resumed.is_none() {
+            self.resumed = Some(Instant::now());
+        }
+    }
+
+    fn seek(&mut self, position: Duration) {
+        self.elapsed = position;
+        if self.resumed.is_some() {
+            self.resumed = Some(Instant::now());
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.elapsed + self.resumed.map(|r| r.elapsed()).unwrap_or_default()
+    }
+}
+
+/// Everything needed to pick up where we left off after a restart
+#[derive(Clone, Debug, Default)]
+struct PlayStateSnapshot {
+    current_track: Option<SongRequest>,
+    songprogress: Option<SongProgress>,
+    queue: VecDeque<SongRequest>,
+    history: VecDeque<SongRequest>,
+    scores: HashMap<MinstrelUserId, i64>,
 }
 
 impl fmt::Debug for MusicState {
@@ -135,38 +331,83 @@ impl fmt::Debug for MusicState {
             status: {:?}, \
             queue: <{} songs>, \
             history: <{} songs>, \
-            autoplay: ..., \
+            autoplay: {{ seed: {} }}, \
         }}",
             "player goes here",
             //&self.player,
             &self.status,
             &self.queue.len(),
             &self.history.len(),
-            // Autoplay
+            self.autoplay.seed(),
         )
     }
 }
 
 impl MusicState {
 
-    pub async fn new(player: mpsc::Sender<MPCMD>, db: DbAdapter) -> MusicState {
-        let bcast = broadcast::channel(10).0;
-        let cmd_channel = mpsc::channel(10);
+    pub async fn new(player: mpsc::Sender<MPCMD>, db: DbAdapter, resolver: Arc<dyn SongResolver>) -> MusicState {
+        Self::with_resolver_pool(player, db, ResolverPool::from_config(resolver)).await
+    }
 
-        MusicState {
-            adapter: MusicAdapter::new(cmd_channel.0.clone(), bcast.clone(), db.clone()),
+    /// Same as new, with a pool that isn't set up from the config (e.g. no retry delays in tests)
+    pub async fn with_resolver_pool(player: mpsc::Sender<MPCMD>, db: DbAdapter, resolver: ResolverPool) -> MusicState {
+        let bcast = broadcast::channel(BROADCAST_BUFFER).0;
+        let cmd_channel = mpsc::channel(10);
+        let adapter = MusicAdapter::new(cmd_channel.0.clone(), bcast.clone(), db.clone(), resolver);
+
+        let (queue, history, resume_at) = load_play_state(&db).await;
+        let scores = load_autoplay_scores(&db).await;
+        let plays = load_song_plays(&db).await;
+        let persist = watch::channel(PlayStateSnapshot {
+            queue: queue.clone(),
+            history: history.clone(),
+            scores: scores.clone(),
+            ..Default::default()
+        });
+        tokio::spawn(persist_play_state(db, persist.1));
+
+        let mut autoplay = AutoplayState::new();
+        autoplay.load_scores(scores);
+        autoplay.load_plays(plays);
+
+        // Sources might still need fetching, so fill in autoplay once the command loop is up
+        let mut ap = adapter.autoplay.clone();
+        tokio::spawn(async move {
+            ap.load_all_userplaylists().await;
+        });
+
+        let mut mstate = MusicState {
+            adapter,
             // TODO: use a proper channel buffer sizes here
             player,
             bcast,
+            seq: 0,
+            last_broadcast: None,
             cmd_channel,
 
             current_track: None,
-            songstarted: None,
-            queue: VecDeque::<SongRequest>::new(),
-            history: VecDeque::<SongRequest>::new(),
+            songprogress: None,
+            queue,
+            fairqueue: FairQueue::new(),
+            history,
             status: MusicStateStatus::Idle,
-            autoplay: AutoplayState::new(db).await,
-        }
+            watchdog_stopped: None,
+            resume_at,
+            timescale: match read_config!(music.player) {
+                PlayerKind::Simulated if read_config!(music.simulated_timescale) > 0.0 => read_config!(music.simulated_timescale),
+                _ => 1.0,
+            },
+            skip_votes: HashSet::new(),
+            listeners: None,
+            volume: read_config!(music.default_volume).min(MAX_VOLUME),
+            autoplay,
+            persist: persist.0,
+        };
+
+        // Starting point for working out what changed in the first broadcast
+        mstate.last_broadcast = Some((mstate.get_webdata(), Instant::now()));
+
+        mstate
     }
 
     async fn player_invoke(&self, cmd: MusicPlayerCommand) -> Result<(), MusicError> {
@@ -185,28 +426,62 @@ impl MusicState {
 
     pub async fn run(&mut self) {
         loop {
-            if let Some((rettx, cmd)) = self.cmd_channel.1.recv().await {
+            // Only wait for as long as the current song should take, in case the player never ends it
+            let next = match self.watchdog_timeout() {
+                Some(timeout) => match tokio::time::timeout(timeout, self.cmd_channel.1.recv()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        self.song_stalled().await;
+                        continue;
+                    },
+                },
+                None => self.cmd_channel.1.recv().await,
+            };
+
+            if let Some((rettx, cmd)) = next {
                 let ret = match cmd {
                     MusicControlCmd::Play(song) => self.play(song).await,
                     MusicControlCmd::Skip => self.skip().await,
+                    MusicControlCmd::VoteSkip(muid) => self.vote_skip(muid).await,
+                    MusicControlCmd::SetListeners(count) => { self.set_listeners(count).await; Ok(MusicOk::Unimplemented) },
                     MusicControlCmd::Stop => self.stop().await,
                     MusicControlCmd::Start => self.start().await,
                     MusicControlCmd::Enqueue(song) => self.enqueue(song), // TODO: probably just make this async...
                     MusicControlCmd::EnqueueAndPlay(song) => self.enqueue_and_play(song).await,
+                    MusicControlCmd::InsertAt((index, song)) => self.insert_at(index, song),
+                    MusicControlCmd::PlayNext(song) => self.play_next(song).await,
+                    MusicControlCmd::Remove((id, muid)) => self.remove(id, muid),
+                    MusicControlCmd::Move((id, target, muid)) => self.move_song(id, target, muid),
                     MusicControlCmd::ClearQueue => self.clear_queue(),
                     MusicControlCmd::ClearHistory => self.clear_history(),
                     MusicControlCmd::Previous => self.previous().await,
-                    MusicControlCmd::SongEnded => { self.song_ended().await; Ok(MusicOk::Unimplemented) },
+                    MusicControlCmd::Pause => self.pause().await,
+                    MusicControlCmd::Resume => self.resume().await,
+                    MusicControlCmd::Seek(pos) => self.seek(pos).await,
+                    MusicControlCmd::SetVolume(vol) => self.set_volume(vol).await,
+                    MusicControlCmd::GetVolume => Ok(MusicOk::Volume(self.volume)),
+                    MusicControlCmd::SongEnded(id) => { self.player_song_ended(id).await; Ok(MusicOk::Unimplemented) },
                     MusicControlCmd::GetData => Ok(MusicOk::Data(Box::new(self.get_webdata()))),
                     MusicControlCmd::AutoplayCmd(cmd) => {
-                        // TODO: this is really excessive, but broadcast after every successful autoplay command
-                        //  Autoplay has no way of broadcasting on its own, so just do it every time for now
-                        //  A change in the broadcasts with the partial broadcast system might be nice, to allow
-                        //  different components to have control over certain aspects.
-                        //  e.g. autoplay sends "Upcoming" broadcasts, MusicState only queue/history/nowplaying, etc
-                        let bcast = cmd != AutoplayControlCmd::Status;
+                        // Autoplay has no way of broadcasting on its own, so check for changes after anything
+                        //  that might have changed something. Only what actually changed gets sent.
+                        let bcast = !matches!(cmd, AutoplayControlCmd::Status | AutoplayControlCmd::GetScores
+                            | AutoplayControlCmd::GetStrategy | AutoplayControlCmd::GetSeed);
+                        let refreshed = match &cmd {
+                            AutoplayControlCmd::SetPlaylist((uid, _)) => Some(*uid),
+                            _ => None,
+                        };
+                        let enrolled = match bcast {
+                            true => self.autoplay.get_scores(),
+                            false => Vec::new(),
+                        };
+
                         let ret = AutoplayAdapter::handle_cmd(cmd, &mut self.autoplay).await;
                         if ret.is_ok() && bcast {
+                            if let Some(uid) = refreshed {
+                                self.broadcast_event(MinstrelEvent::PlaylistRefreshed(uid));
+                            }
+                            self.broadcast_enrollment(&enrolled);
                             self.broadcast_update();
                         }
                         ret
@@ -223,19 +498,24 @@ impl MusicState {
     }
 
     /// Start playing a song
-    async fn play(&mut self, song: SongRequest) -> Result<MusicOk, MusicError> {
+    async fn play(&mut self, mut song: SongRequest) -> Result<MusicOk, MusicError> {
         debug!("play called on song = {}", song);
 
+        // Songs played directly never went through the queue
+        if song.id == 0 {
+            song.id = next_request_id();
+        }
+
         if self.current_track.is_some() {
             return Err(MusicError::AlreadyPlaying);
         }
 
-        let ret = self.player_invoke(MusicPlayerCommand::Play(song.song.clone())).await;
+        let ret = self.player_invoke(MusicPlayerCommand::Play((song.song.clone(), song.id))).await;
 
         if let Err(e) = ret {
             if self.bcast.receiver_count() > 0 {
-                let errmsg = format!("Error playing track: {:?}", e);
-                let ret = self.bcast.send(MinstrelBroadcast::Error(errmsg));
+                let err = MinstrelError::new(e.code(), format!("Error playing {}: {}", song.song.title, e));
+                let ret = self.bcast.send(MinstrelBroadcast::Error(err));
                 if let Err(e) = ret {
                     error!("error broadcasting update: {:?}", e);
                 }
@@ -255,7 +535,8 @@ impl MusicState {
             // TODO: don't charge a user until the song ends. probably will depend on the song-buffer
             //  method, but will clean up a lot of this error handling magic probably maybe.
             // Refund the requester the time from an errored song
-            self.autoplay.add_time_to_user(&song.requested_by.id, -song.song.duration);
+            self.autoplay.refund_user(&song);
+            self.fairqueue.refund(&song.requested_by.id, song.song.duration);
             debug!("Refunding {} seconds to {}", song.song.duration, &song.requested_by.displayname);
 
             // TODO: This is really gross. A song failed to play, so signal SongEnded so that the next song can play.
@@ -263,8 +544,9 @@ impl MusicState {
             //   .song_ended() will lead back here (via .next()).
             // Rather than create a loop, end the call to .play() and let the event loop handle the SongEnd event.
             let mut temp = self.get_adapter();
+            let id = song.id;
             tokio::spawn(async move {
-                temp.song_ended().await;
+                temp.song_ended(id).await;
             });
 
             return Err(e);
@@ -274,9 +556,40 @@ impl MusicState {
             log_song(&song);
         }
 
+        self.autoplay.count_play(&song.song.url);
+        let db = self.adapter.db.clone();
+        let url = song.song.url.clone();
+        tokio::spawn(async move {
+            if db.record_song_play(&url).await.is_err() {
+                error!("failed to record play of {}", url);
+            }
+        });
+
+        let resume = self.resume_at.filter(|(id, _)| *id == song.id);
+
         self.current_track = Some(song);
-        self.songstarted = Some(std::time::Instant::now());
+        self.songprogress = Some(SongProgress::start());
         self.status = MusicStateStatus::Playing;
+        self.watchdog_stopped = None;
+        self.skip_votes.clear();
+
+        // Interrupted by a restart, carry on from where it got to rather than starting over.
+        // Going straight to the player, since a normal seek failing would end the song instead.
+        if let Some((_, position)) = resume {
+            self.resume_at = None;
+            let duration = self.current_track.as_ref().map(|s| s.song.duration.max(0) as u64).unwrap_or_default();
+            let position = Duration::from_secs(position.as_secs().min(duration.saturating_sub(1)));
+
+            debug!("resuming interrupted song {} seconds in", position.as_secs());
+            match self.player_invoke(MusicPlayerCommand::Seek(position)).await {
+                Ok(_) => {
+                    if let Some(p) = self.songprogress.as_mut() {
+                        p.seek(position);
+                    }
+                },
+                Err(e) => error!("failed to resume interrupted song, playing it from the start: {:?}", e),
+            }
+        }
 
         self.broadcast_update();
 
@@ -300,7 +613,16 @@ impl MusicState {
     fn get_next_song(&mut self) -> Option<SongRequest> {
         if let Some(song) = self.queue.pop_front() {
             if self.autoplay.is_enabled() && read_config!(music.queue_adds_usertime) {
-                self.autoplay.add_time_to_user(&song.requested_by.id, song.song.duration);
+                self.autoplay.charge_user(&song);
+            }
+
+            self.autoplay.record_played(&song);
+
+            // Turns only matter while there is a queue to take turns in
+            if self.queue.is_empty() {
+                self.fairqueue.clear();
+            } else {
+                self.fairqueue.charge(&song.requested_by.id, song.song.duration);
             }
 
             return Some(song);
@@ -323,6 +645,65 @@ impl MusicState {
         Ok(MusicOk::SkippingSong)
     }
 
+    /// Vote to skip the current track, which skips once enough others have voted too
+    pub async fn vote_skip(&mut self, muid: MinstrelUserId) -> Result<MusicOk, MusicError> {
+        let requester = match &self.current_track {
+            Some(song) => song.requested_by.id,
+            None => return Err(MusicError::NotPlaying),
+        };
+
+        if requester == muid && read_config!(music.skip_vote_requester_override) {
+            debug!("requester {} skipping their own song", muid);
+            return self.skip().await;
+        }
+
+        if !self.skip_votes.insert(muid) {
+            return Err(MusicError::AlreadyVoted);
+        }
+
+        let votes = self.skip_votes.len();
+        let needed = self.skip_votes_needed();
+        debug!("skip vote from {}, {}/{}", muid, votes, needed);
+
+        if votes >= needed {
+            return self.skip().await;
+        }
+
+        self.broadcast_update();
+
+        Ok(MusicOk::SkipVoted { votes, needed })
+    }
+
+    /// How many votes it takes to skip the current track
+    fn skip_votes_needed(&self) -> usize {
+        let fraction = read_config!(music.skip_vote_fraction);
+        if fraction <= 0.0 {
+            return 1;
+        }
+
+        let voters = match (read_config!(music.skip_vote_basis), self.listeners) {
+            (SkipVoteBasis::Listening, Some(listeners)) => listeners,
+            _ => self.autoplay.enrolled_count(),
+        };
+
+        ((voters as f64 * fraction).ceil() as usize).max(1)
+    }
+
+    /// Update how many are listening, None if the player has no listeners to count
+    async fn set_listeners(&mut self, count: Option<usize>) {
+        self.listeners = count;
+
+        // Someone leaving may mean the votes already in are enough
+        if !self.skip_votes.is_empty() && self.skip_votes.len() >= self.skip_votes_needed() {
+            debug!("enough skip votes after listeners changed, skipping");
+            if let Err(e) = self.skip().await {
+                error!("failed to skip after listeners changed: {:?}", e);
+            }
+        }
+
+        self.broadcast_update();
+    }
+
     /// Stop the current playing track (if any)
     pub async fn stop(&mut self) -> Result<MusicOk, MusicError> {
         self.status = MusicStateStatus::Stopping;
@@ -334,6 +715,7 @@ impl MusicState {
 
         self.status = MusicStateStatus::Stopped;
         self.current_track = None;
+        self.skip_votes.clear();
 
         self.broadcast_update();
 
@@ -342,8 +724,10 @@ impl MusicState {
 
     /// Helper to play music if state has been stopped or enqueued without playing
     pub async fn start(&mut self) -> Result<MusicOk, MusicError> {
-        if let MusicStateStatus::Playing = self.status {
-            return Err(MusicError::AlreadyPlaying);
+        match self.status {
+            MusicStateStatus::Playing => return Err(MusicError::AlreadyPlaying),
+            MusicStateStatus::Paused => return self.resume().await,
+            _ => (),
         };
 
         if let Some(song) = self.get_next_song() {
@@ -354,13 +738,111 @@ impl MusicState {
         }
     }
 
+    /// Pause the current track, keeping our place in it
+    pub async fn pause(&mut self) -> Result<MusicOk, MusicError> {
+        if self.status != MusicStateStatus::Playing {
+            return Err(MusicError::NotPlaying);
+        }
+
+        self.player_invoke(MusicPlayerCommand::Pause).await?;
+
+        self.status = MusicStateStatus::Paused;
+        if let Some(p) = self.songprogress.as_mut() {
+            p.pause();
+        }
+
+        self.broadcast_update();
+
+        Ok(MusicOk::Paused)
+    }
+
+    /// Continue a paused track
+    pub async fn resume(&mut self) -> Result<MusicOk, MusicError> {
+        if self.status != MusicStateStatus::Paused {
+            return Err(MusicError::NotPaused);
+        }
+
+        self.player_invoke(MusicPlayerCommand::Resume).await?;
+
+        self.status = MusicStateStatus::Playing;
+        if let Some(p) = self.songprogress.as_mut() {
+            p.resume();
+        }
+
+        self.broadcast_update();
+
+        Ok(MusicOk::Resumed)
+    }
+
+    /// Jump to a different point in the current track
+    pub async fn seek(&mut self, position: SeekPosition) -> Result<MusicOk, MusicError> {
+        let duration = match (&self.current_track, &self.status) {
+            (Some(song), MusicStateStatus::Playing | MusicStateStatus::Paused) => song.song.duration.max(0) as u64,
+            _ => return Err(MusicError::NotPlaying),
+        };
+
+        let target = match position {
+            SeekPosition::Absolute(secs) => secs,
+            SeekPosition::Relative(delta) => (self.song_progress() as i64 + delta).max(0) as u64,
+        };
+        // Seeking right to the end would just end the song, which is what skip is for
+        let target = target.min(duration.saturating_sub(1));
+
+        let mut ret = self.player_invoke(MusicPlayerCommand::Seek(Duration::from_secs(target))).await;
+
+        // Players may restart the track to seek, so make sure a paused track stays paused
+        if ret.is_ok() && self.status == MusicStateStatus::Paused {
+            ret = self.player_invoke(MusicPlayerCommand::Pause).await;
+        }
+
+        // No telling where (or whether) the player is in the track after a failed seek, so end it
+        // rather than keep counting progress for something that may not be playing
+        if let Err(e) = ret {
+            error!("failed to seek, ending the current track: {:?}", e);
+            // Even if it was paused, the watchdog only looks after playing tracks
+            self.status = MusicStateStatus::Playing;
+            self.stop_stuck_track().await;
+            return Err(e);
+        }
+
+        if let Some(p) = self.songprogress.as_mut() {
+            p.seek(Duration::from_secs(target));
+        }
+
+        self.broadcast_update();
+
+        Ok(MusicOk::Seeked)
+    }
+
+    /// Change the playback volume, applies to the current and all following tracks
+    pub async fn set_volume(&mut self, volume: u8) -> Result<MusicOk, MusicError> {
+        if volume > MAX_VOLUME {
+            return Err(MusicError::InvalidVolume);
+        }
+
+        self.player_invoke(MusicPlayerCommand::SetVolume(volume)).await?;
+        self.volume = volume;
+
+        self.broadcast_update();
+
+        Ok(MusicOk::Volume(volume))
+    }
+
     /// Only enqueue a track to be played, do not start playing
-    pub fn enqueue(&mut self, song: SongRequest) -> Result<MusicOk, MusicError> {
+    pub fn enqueue(&mut self, mut song: SongRequest) -> Result<MusicOk, MusicError> {
         if self.queue.len() > read_config!(music.queue_length) {
             return Err(MusicError::QueueFull)
         }
 
-        self.queue.push_back(song);
+        song.id = next_request_id();
+
+        match read_config!(music.queue_mode) {
+            QueueMode::Fifo => self.queue.push_back(song),
+            QueueMode::Fair => {
+                let index = self.fairqueue.position(&self.queue, &song.requested_by.id);
+                self.queue.insert(index, song);
+            },
+        }
 
         self.broadcast_update();
 
@@ -393,6 +875,107 @@ impl MusicState {
         self.history.clone()
     }
 
+    /// Put a track at a specific position in the queue, or at the end if past it.
+    /// Requests can't go ahead of anyone else's, asking to is a QueueJump error.
+    pub fn insert_at(&mut self, index: usize, mut song: SongRequest) -> Result<MusicOk, MusicError> {
+        if self.queue.len() > read_config!(music.queue_length) {
+            return Err(MusicError::QueueFull)
+        }
+
+        let index = index.min(self.queue.len());
+        let index = match read_config!(music.queue_mode) {
+            // Wherever the requester would have ended up anyway, the fair queue decides the order
+            QueueMode::Fair => {
+                let position = self.fairqueue.position(&self.queue, &song.requested_by.id);
+                if index < position {
+                    return Err(MusicError::QueueJump);
+                }
+                position
+            },
+            QueueMode::Fifo => {
+                if index < self.earliest_position(song.requested_by.id) {
+                    return Err(MusicError::QueueJump);
+                }
+                index
+            },
+        };
+
+        song.id = next_request_id();
+        self.queue.insert(index, song);
+
+        self.broadcast_update();
+
+        Ok(MusicOk::EnqueuedSong)
+    }
+
+    /// Put a track at the front of the queue, and start playing music if not already playing.
+    /// Fails with QueueJump if anyone else has something queued up already.
+    pub async fn play_next(&mut self, song: SongRequest) -> Result<MusicOk, MusicError> {
+        self.insert_at(0, song)?;
+
+        match self.start().await {
+            Ok(m) => Ok(m),
+            Err(MusicError::AlreadyPlaying) => Ok(MusicOk::EnqueuedSong),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn queue_position(&self, id: RequestId) -> Result<usize, MusicError> {
+        self.queue.iter()
+            .position(|r| r.id == id)
+            .ok_or(MusicError::RequestNotFound)
+    }
+
+    // Earliest position `muid` can put a request in without jumping ahead of anyone else's
+    fn earliest_position(&self, muid: MinstrelUserId) -> usize {
+        self.queue.iter()
+            .rposition(|r| r.requested_by.id != muid)
+            .map(|p| p + 1)
+            .unwrap_or(0)
+    }
+
+    // Only the user who requested a queued track gets to rearrange or remove it.
+    // Returns the track's current position in the queue.
+    fn check_queue_owner(&self, id: RequestId, muid: MinstrelUserId) -> Result<usize, MusicError> {
+        let index = self.queue_position(id)?;
+
+        match self.queue[index].requested_by.id == muid {
+            true => Ok(index),
+            false => Err(MusicError::NotRequester),
+        }
+    }
+
+    /// Remove a track from the queue on behalf of `muid`
+    pub fn remove(&mut self, id: RequestId, muid: MinstrelUserId) -> Result<MusicOk, MusicError> {
+        let index = self.check_queue_owner(id, muid)?;
+
+        // The fair queue only charges for requests as they're played, so there's nothing to refund here
+        self.queue.remove(index);
+
+        self.broadcast_update();
+
+        Ok(MusicOk::RemovedSong)
+    }
+
+    /// Move a track on behalf of `muid`, so that it takes the position `target` is currently at
+    pub fn move_song(&mut self, id: RequestId, target: RequestId, muid: MinstrelUserId) -> Result<MusicOk, MusicError> {
+        let from = self.check_queue_owner(id, muid)?;
+        let to = self.queue_position(target)?;
+
+        // Moving back is always fine, but moving forward can only pass the requester's own songs
+        if to < from && self.queue.range(to..from).any(|r| r.requested_by.id != muid) {
+            return Err(MusicError::QueueJump);
+        }
+
+        // Checked above, this can't fail
+        let song = self.queue.remove(from).unwrap();
+        self.queue.insert(to, song);
+
+        self.broadcast_update();
+
+        Ok(MusicOk::MovedSong)
+    }
+
     pub async fn previous(&mut self) -> Result<MusicOk, MusicError> {
 
         if let Some(song) = self.history.pop_front() {
@@ -411,6 +994,7 @@ impl MusicState {
 
     pub fn clear_queue(&mut self) -> Result<MusicOk, MusicError> {
         self.queue.clear();
+        self.fairqueue.clear();
 
         self.broadcast_update();
 
@@ -429,35 +1013,152 @@ impl MusicState {
         self.queue.is_empty()
     }
 
-    // TODO: These broadcasts should really be more robust.
-    //   Probably allow partial updates, as well as intelligently send them whenever
-    //   MusicState is mutated, rather than having to manually call
-    fn broadcast_update(&self) {
-        let out: model::MinstrelWebData = self.into();
+    /// Let clients know about everything that changed since the last broadcast
+    fn broadcast_update(&mut self) {
+        let out = self.get_webdata();
+
+        // Anything worth broadcasting is also worth saving
+        self.save_state();
+
+        let events = match &self.last_broadcast {
+            Some((prev, at)) => diff_webdata(prev, at.elapsed(), &out),
+            None => Vec::new(),
+        };
+
+        for event in events {
+            self.broadcast_event(event);
+        }
+
+        self.last_broadcast = Some((out, Instant::now()));
+    }
 
-        // TODO: keep an eye on how often this appears now that this is called on
-        //  every single autoplay command
-        debug!("sending broadcast");
+    /// Send out a single event, numbered after the last one
+    fn broadcast_event(&mut self, event: MinstrelEvent) {
+        self.seq += 1;
+        trace!("sending broadcast {}: {:?}", self.seq, event);
 
         if self.bcast.receiver_count() > 0 {
-            if let Err(e) = self.bcast.send(MinstrelBroadcast::MusicState(out)) {
+            if let Err(e) = self.bcast.send(MinstrelBroadcast::Event { seq: self.seq, event }) {
                 error!("error broadcasting update: {:?}", e);
             }
         }
     }
 
-    pub fn get_webdata(&self) -> model::MinstrelWebData {
-        self.into()
+    /// Announce anyone who was enrolled or removed from autoplay since `before` was taken
+    fn broadcast_enrollment(&mut self, before: &[AutoplayScore]) {
+        let was_enrolled = |userid| before.iter().any(|b| b.userid == userid && b.enabled);
+
+        let changed = self.autoplay.get_scores().into_iter()
+            .filter(|s| s.enabled != was_enrolled(s.userid))
+            .map(|s| (s.userid, s.enabled))
+            .collect::<Vec<(MinstrelUserId, bool)>>();
+
+        for (userid, enrolled) in changed {
+            self.broadcast_event(MinstrelEvent::UserEnrolled { userid, enrolled });
+        }
+    }
+
+    /// Hand the current queue/history/track off to be written to the db
+    fn save_state(&self) {
+        let snapshot = PlayStateSnapshot {
+            current_track: self.current_track.clone(),
+            songprogress: self.songprogress,
+            queue: self.queue.clone(),
+            history: self.history.clone(),
+            scores: self.autoplay.get_score_map(),
+        };
+
+        if self.persist.send(snapshot).is_err() {
+            error!("play state persistence task has exited, state will not be saved");
+        }
+    }
+
+    pub fn get_webdata(&mut self) -> model::MinstrelWebData {
+        self.autoplay.update_upcoming(read_config!(music.upcoming_count));
+        (&*self).into()
     }
 
     pub fn subscribe(&self) -> broadcast::Receiver<MinstrelBroadcast> {
         self.bcast.subscribe()
     }
 
+    /// How long the command loop should wait before assuming the player lost track of the current song.
+    /// None if there's nothing to watch, e.g. paused, or a song with no known length.
+    fn watchdog_timeout(&self) -> Option<Duration> {
+        let grace = read_config!(music.song_end_grace);
+        if grace == 0 || self.status != MusicStateStatus::Playing {
+            return None;
+        }
+
+        // Already stopped it, give the player a moment to report that before moving on without it
+        if let Some(stopped) = self.watchdog_stopped {
+            return Some(WATCHDOG_STOP_WAIT.saturating_sub(stopped.elapsed()));
+        }
+
+        let song = self.current_track.as_ref()?;
+        let progress = self.songprogress.as_ref()?;
+        if song.song.duration <= 0 || progress.resumed.is_none() {
+            return None;
+        }
+
+        // Measured from the start of the song, so commands coming in don't keep pushing it back.
+        // Simulated songs can play faster or slower than their length, the grace is real time either way.
+        let length = Duration::from_secs(song.song.duration as u64).div_f64(self.timescale);
+        Some((length + Duration::from_secs(grace)).saturating_sub(progress.elapsed()))
+    }
+
+    /// The current song ran well past its length without the player saying it ended
+    async fn song_stalled(&mut self) {
+        let title = self.current_track.as_ref()
+            .map(|s| s.song.title.clone())
+            .unwrap_or_default();
+
+        // Stopping didn't get an end out of the player either, so advance without it
+        if self.watchdog_stopped.is_some() {
+            warn!("player still has not ended {}, moving on anyway", title);
+            self.song_ended().await;
+            return;
+        }
+
+        warn!("player never reported the end of {}, skipping ahead", title);
+
+        if self.bcast.receiver_count() > 0 {
+            let errmsg = format!("The player never reported the end of \"{}\", so skipping ahead to the next song", title);
+            if let Err(e) = self.bcast.send(MinstrelBroadcast::Error(MinstrelError::new(ErrorCode::SongStalled, errmsg))) {
+                error!("error broadcasting update: {:?}", e);
+            }
+        }
+
+        self.stop_stuck_track().await;
+    }
+
+    /// Stop a track the player seems to have lost track of. Stopping should get the player to fire the
+    /// usual song end, which moves the queue along, otherwise the watchdog moves on without it shortly after.
+    async fn stop_stuck_track(&mut self) {
+        self.watchdog_stopped = Some(Instant::now());
+        if let Err(e) = self.player_invoke(MusicPlayerCommand::Stop).await {
+            error!("Player encountered a problem stopping a stalled track: {:?}", e);
+        }
+    }
+
+    /// The player reported request `id` ended. A late report for a song that was already moved on from
+    /// (e.g. by the watchdog) would end whatever is playing now instead, so only the current one counts.
+    async fn player_song_ended(&mut self, id: RequestId) {
+        if let Some(current) = &self.current_track {
+            if current.id != id {
+                debug!("ignoring end of request {}, {} is what's playing", id, current.id);
+                return;
+            }
+        }
+
+        self.song_ended().await;
+    }
+
     /// Handler to be called by the player when a song ends
-    // TODO: perhaps replace this with a message event loop as well, maybe over a select
-    //   with a timeout set to slightly more than the song length
     pub async fn song_ended(&mut self) {
+        self.watchdog_stopped = None;
+        self.skip_votes.clear();
+
         if let Some(song) = &self.current_track.take() {
             self.history.push_front(song.clone());
             self.history.truncate(read_config!(music.history_count) as usize);
@@ -480,7 +1181,9 @@ impl MusicState {
         match ret {
             Ok(MusicOk::EmptyQueue) => {
                 self.status = MusicStateStatus::Stopped;
-                debug!("EmptyQueue returned by next(), stopping.")
+                debug!("EmptyQueue returned by next(), stopping.");
+                // Nothing else will report the finished song moving into history
+                self.broadcast_update();
             },
             Ok(MusicOk::StartedPlaying) => debug!("started the next track"),
             Ok(o) => warn!("unexpect Ok response from next? {o}"),
@@ -490,8 +1193,8 @@ impl MusicState {
     }
 
     pub fn song_progress(&self) -> u64 {
-        match &self.songstarted {
-            Some(d) => d.elapsed().as_secs(),
+        match &self.songprogress {
+            Some(p) => p.elapsed().as_secs(),
             None => 0,
         }
     }
@@ -500,18 +1203,213 @@ impl MusicState {
 
 impl From<&MusicState> for model::MinstrelWebData {
     fn from(other: &MusicState) -> Self {
-        let upcoming = other.autoplay.prefetch(read_config!(music.upcoming_count))
+        // Only as fresh as the last update_upcoming(), get_webdata() takes care of that
         // TODO: Better handle when autoplay is not enabled, or no users are enrolled
-        .unwrap_or_default().to_vec();
+        let upcoming = other.autoplay.upcoming(read_config!(music.upcoming_count));
 
         Self {
             current_track: other.current_track.clone(),
             song_progress: other.song_progress(),
             status: other.status.clone(),
+            volume: other.volume,
             queue: other.queue.clone(),
             upcoming,
             history: other.history.clone(),
             ap_enabled: other.autoplay.is_enabled(),
+            skip_votes: other.skip_votes.len(),
+            skip_votes_needed: other.skip_votes_needed(),
+            seq: other.seq,
+        }
+    }
+}
+
+/// Events for everything that differs between what was last broadcast and now, `since` the last broadcast
+fn diff_webdata(prev: &MinstrelWebData, since: Duration, new: &MinstrelWebData) -> Vec<MinstrelEvent> {
+    let mut events = Vec::new();
+    let prev_id = prev.current_track.as_ref().map(|s| s.id);
+    let new_id = new.current_track.as_ref().map(|s| s.id);
+
+    if prev_id != new_id {
+        if let Some(track) = &prev.current_track {
+            events.push(MinstrelEvent::TrackEnded(track.clone()));
+        }
+    }
+    if prev.history != new.history {
+        events.push(MinstrelEvent::HistoryChanged(new.history.clone()));
+    }
+    if prev_id != new_id {
+        if let Some(track) = &new.current_track {
+            events.push(MinstrelEvent::TrackStarted(track.clone()));
+        }
+    }
+    if prev.queue != new.queue {
+        events.push(MinstrelEvent::QueueChanged(new.queue.clone()));
+    }
+    if prev.upcoming != new.upcoming {
+        events.push(MinstrelEvent::UpcomingChanged(new.upcoming.clone()));
+    }
+    if prev.skip_votes != new.skip_votes || prev.skip_votes_needed != new.skip_votes_needed {
+        events.push(MinstrelEvent::SkipVotesChanged {
+            votes: new.skip_votes,
+            needed: new.skip_votes_needed,
+        });
+    }
+
+    // Clients keep the progress ticking on their own, so only tell them when it jumps, e.g. a seek
+    let expected = match prev.status {
+        MusicStateStatus::Playing if prev_id == new_id => prev.song_progress + since.as_secs(),
+        _ if prev_id == new_id => prev.song_progress,
+        _ => 0,
+    };
+    let jumped = expected.abs_diff(new.song_progress) > 1;
+
+    if jumped || prev.status != new.status || prev.volume != new.volume || prev.ap_enabled != new.ap_enabled {
+        events.push(MinstrelEvent::StatusChanged {
+            status: new.status.clone(),
+            song_progress: new.song_progress,
+            volume: new.volume,
+            ap_enabled: new.ap_enabled,
+        });
+    }
+
+    events
+}
+
+/// Load the queue and history saved by a previous run
+async fn load_play_state(db: &DbAdapter) -> (VecDeque<SongRequest>, VecDeque<SongRequest>, Option<(RequestId, Duration)>) {
+    let (current, queue, history) = match db.get_play_state().await {
+        Ok(s) => s,
+        Err(_) => {
+            error!("failed to load saved play state, starting fresh");
+            return (VecDeque::new(), VecDeque::new(), None);
+        }
+    };
+
+    // IDs are only meaningful for this run, hand out fresh ones
+    let mut queue = queue.into_iter()
+        .map(|r| SongRequest { id: next_request_id(), ..r })
+        .collect::<VecDeque<SongRequest>>();
+    let mut history = history.into_iter()
+        .map(|r| SongRequest { id: next_request_id(), ..r })
+        .collect::<VecDeque<SongRequest>>();
+
+    let mut resume_at = None;
+    if let Some((mut song, progress)) = current {
+        song.id = next_request_id();
+        debug!("{} was interrupted {} seconds in", song, progress);
+
+        if read_config!(music.resume_interrupted) {
+            if progress > 0 {
+                resume_at = Some((song.id, Duration::from_secs(progress)));
+            }
+            queue.push_front(song);
+        } else {
+            history.push_front(song);
+            history.truncate(read_config!(music.history_count) as usize);
+        }
+    }
+
+    debug!("restored {} queued songs and {} songs of history", queue.len(), history.len());
+
+    (queue, history, resume_at)
+}
+
+/// Load the autoplay scores saved by a previous run, decayed by how long ago they were saved
+async fn load_autoplay_scores(db: &DbAdapter) -> HashMap<MinstrelUserId, i64> {
+    let scores = match db.get_autoplay_scores().await {
+        Ok(s) => s,
+        Err(_) => {
+            error!("failed to load saved autoplay scores, everyone starts at 0");
+            return HashMap::new();
+        }
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or(0);
+
+    scores.into_iter()
+        .map(|(userid, score, updated)| (userid, decay_score(score, (now - updated).max(0) as u64)))
+        .collect()
+}
+
+/// Load how often and when each song was last played, for weighting autoplay shuffles
+async fn load_song_plays(db: &DbAdapter) -> HashMap<String, PlayStats> {
+    match db.get_song_plays().await {
+        Ok(plays) => plays.into_iter()
+            .map(|(url, (count, last_played))| (url, PlayStats { count, last_played }))
+            .collect(),
+        Err(_) => {
+            error!("failed to load song play counts, shuffles will be unweighted");
+            HashMap::new()
+        }
+    }
+}
+
+/// Write out play state snapshots as they come in, and periodically save the current track's progress
+async fn persist_play_state(db: DbAdapter, mut rx: watch::Receiver<PlayStateSnapshot>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(PROGRESS_SAVE_INTERVAL));
+    let mut saved_scores = rx.borrow().scores.clone();
+    // What got written last, None to write everything (e.g. the saved current track has moved into the queue)
+    let mut saved: Option<PlayStateSnapshot> = None;
+
+    loop {
+        tokio::select! {
+            ret = rx.changed() => {
+                if ret.is_err() {
+                    debug!("MusicState dropped, play state persistence exiting");
+                    break;
+                }
+
+                // Only the latest snapshot matters, intermediate ones are skipped if writes fall behind
+                let snapshot = rx.borrow().clone();
+                let progress = snapshot.songprogress.map(|p| p.elapsed().as_secs()).unwrap_or(0);
+
+                // Requests never change once made, so the IDs are enough to tell if a list did.
+                // Most snapshots only touch one list (or none, e.g. a volume change), so only write those.
+                let changed = |ids: fn(&PlayStateSnapshot) -> Vec<RequestId>| match &saved {
+                    Some(s) => ids(s) != ids(&snapshot),
+                    None => true,
+                };
+                let mut ret = Ok(());
+
+                if changed(|s| s.current_track.iter().map(|r| r.id).collect()) {
+                    ret = ret.and(db.update_play_state_current(snapshot.current_track.as_ref().map(|s| (s, progress))).await);
+                }
+                if changed(|s| s.queue.iter().map(|r| r.id).collect()) {
+                    ret = ret.and(db.update_play_state_queue(&snapshot.queue).await);
+                }
+                if changed(|s| s.history.iter().map(|r| r.id).collect()) {
+                    ret = ret.and(db.update_play_state_history(&snapshot.history).await);
+                }
+
+                match ret {
+                    Ok(()) => saved = Some(snapshot.clone()),
+                    Err(()) => {
+                        error!("failed to save play state");
+                        saved = None;
+                    },
+                }
+
+                // Most snapshots are for queue changes, don't rewrite the scores for those
+                if snapshot.scores != saved_scores {
+                    if db.update_autoplay_scores(&snapshot.scores).await.is_ok() {
+                        saved_scores = snapshot.scores;
+                    } else {
+                        error!("failed to save autoplay scores");
+                    }
+                }
+            },
+            _ = interval.tick() => {
+                let progress = {
+                    let snapshot = rx.borrow();
+                    snapshot.current_track.as_ref().and(snapshot.songprogress)
+                };
+
+                if let Some(progress) = progress {
+                    if db.update_play_state_progress(progress.elapsed().as_secs()).await.is_err() {
+                        error!("failed to save song progress");
+                    }
+                }
+            },
         }
     }
 }
@@ -548,4 +1446,289 @@ fn log_song(song: &SongRequest) {
     if let Err(e) = ret {
         error!("error writing to songlog file: {:?}", e);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resolver::FakeResolver;
+    use crate::testutil::*;
+    use model::{
+        Requester,
+        Song,
+        SourceType,
+    };
+
+    #[tokio::test]
+    async fn test_request_with_fake_resolver() {
+        let song = Song {
+            title: "Test Song".into(),
+            artist: "Test Artist".into(),
+            url: "https://example.com/song".into(),
+            thumbnail: String::new(),
+            duration: 120,
+        };
+        let resolver = FakeResolver::new().with_song("https://example.com/song", song.clone());
+
+        let mut adapter = test_state_with(spawn_stub_player(), Arc::new(resolver)).await;
+
+        assert!(matches!(adapter.fetch_song("https://example.com/missing".into()).await, Err(MusicError::FailedToRetrieve)));
+
+        let fetched = adapter.fetch_song("https://example.com/song".into()).await.unwrap();
+        let requester = Requester {
+            displayname: "tester".into(),
+            icon: String::new(),
+            id: 1,
+        };
+
+        let ret = adapter.enqueue_and_play(SongRequest::new(fetched, requester)).await;
+        assert!(matches!(ret, Ok(MusicOk::StartedPlaying)));
+
+        let data = adapter.get_webdata().await;
+        assert_eq!(data.current_track.map(|s| s.song), Some(song));
+    }
+
+    #[tokio::test]
+    async fn test_queue_editing() {
+        let mut adapter = test_state().await;
+        let titles = |data: model::MinstrelWebData| data.queue.into_iter().map(|r| r.song.title).collect::<Vec<_>>();
+
+        adapter.enqueue(test_request("a", 1)).await.unwrap();
+        adapter.enqueue(test_request("b", 2)).await.unwrap();
+        // Can go ahead of your own requests, but not anyone else's
+        assert!(matches!(adapter.insert_at(0, test_request("c", 2)).await, Err(MusicError::QueueJump)));
+        assert!(matches!(adapter.play_next(test_request("c", 2)).await, Err(MusicError::QueueJump)));
+        adapter.insert_at(1, test_request("c", 2)).await.unwrap();
+        adapter.insert_at(99, test_request("d", 1)).await.unwrap();
+        assert_eq!(titles(adapter.get_webdata().await), ["a", "c", "b", "d"]);
+
+        let ids = adapter.get_webdata().await.queue.iter().map(|r| r.id).collect::<Vec<_>>();
+        assert!(ids.iter().all(|id| *id != 0));
+
+        // Users can only touch their own requests
+        assert!(matches!(adapter.remove(ids[2], 1).await, Err(MusicError::NotRequester)));
+        assert!(matches!(adapter.move_song(ids[3], ids[0], 2).await, Err(MusicError::NotRequester)));
+        assert!(matches!(adapter.move_song(ids[3], ids[0], 1).await, Err(MusicError::QueueJump)));
+        assert!(matches!(adapter.remove(RequestId::MAX, 1).await, Err(MusicError::RequestNotFound)));
+        assert!(matches!(adapter.move_song(ids[0], RequestId::MAX, 1).await, Err(MusicError::RequestNotFound)));
+
+        adapter.move_song(ids[2], ids[1], 2).await.unwrap();
+        adapter.move_song(ids[0], ids[3], 1).await.unwrap();
+        assert_eq!(titles(adapter.get_webdata().await), ["b", "c", "d", "a"]);
+
+        // Positions changed, but the IDs still point at the same requests
+        adapter.remove(ids[1], 2).await.unwrap();
+        assert_eq!(titles(adapter.get_webdata().await), ["b", "d", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_skip_votes() {
+        let (player, mut seen) = spawn_recording_player(true);
+        let mut adapter = test_state_with(player, Arc::new(FakeResolver::new())).await;
+        let votes = |data: model::MinstrelWebData| (data.skip_votes, data.skip_votes_needed);
+
+        assert!(matches!(adapter.vote_skip(2).await, Err(MusicError::NotPlaying)));
+
+        // Half of the four listeners need to vote
+        adapter.set_listeners(Some(4)).await;
+        adapter.enqueue_and_play(test_request("a", 1)).await.unwrap();
+        adapter.enqueue(test_request("b", 2)).await.unwrap();
+
+        assert!(matches!(adapter.vote_skip(2).await, Ok(MusicOk::SkipVoted { votes: 1, needed: 2 })));
+        assert!(matches!(adapter.vote_skip(2).await, Err(MusicError::AlreadyVoted)));
+        assert_eq!(votes(adapter.get_webdata().await), (1, 2));
+
+        // Votes were for the old track, they don't carry over
+        end_current_song(&mut adapter).await;
+        assert_eq!(votes(adapter.get_webdata().await), (0, 2));
+
+        assert!(matches!(adapter.vote_skip(3).await, Ok(MusicOk::SkipVoted { votes: 1, needed: 2 })));
+        assert!(matches!(adapter.vote_skip(4).await, Ok(MusicOk::SkippingSong)));
+
+        // Whoever requested the song can skip it on their own
+        end_current_song(&mut adapter).await;
+        adapter.enqueue_and_play(test_request("c", 3)).await.unwrap();
+        assert!(matches!(adapter.vote_skip(3).await, Ok(MusicOk::SkippingSong)));
+
+        // Listeners leaving can make the votes already in enough
+        end_current_song(&mut adapter).await;
+        adapter.enqueue_and_play(test_request("d", 1)).await.unwrap();
+        assert!(matches!(adapter.vote_skip(2).await, Ok(MusicOk::SkipVoted { votes: 1, needed: 2 })));
+        while seen.try_recv().is_ok() {}
+        adapter.set_listeners(Some(2)).await;
+        assert_eq!(votes(adapter.get_webdata().await), (1, 1));
+        assert!(matches!(seen.try_recv(), Ok(MusicPlayerCommand::Stop)));
+    }
+
+    #[tokio::test]
+    async fn test_watchdog() {
+        let db = db::init_memory_db().await;
+        let mut mstate = MusicState::with_resolver_pool(spawn_stub_player(), db, test_pool(Arc::new(FakeResolver::new()))).await;
+        // Songs take twice their length, like a simulated player at half speed
+        mstate.timescale = 0.5;
+        let mut adapter = mstate.get_adapter();
+        tokio::spawn(async move { mstate.run().await });
+
+        tokio::time::pause();
+        let playing = |data: model::MinstrelWebData| data.current_track.map(|r| r.song.title);
+
+        adapter.enqueue_and_play(test_request("a", 1)).await.unwrap();
+        adapter.enqueue(test_request("b", 1)).await.unwrap();
+        let stale = adapter.get_webdata().await.current_track.unwrap().id;
+
+        // Sleeping rather than advancing lets the clock jump through the watchdog's own timers in order.
+        // Past the song's length and grace at full speed, but not at half.
+        tokio::time::sleep(Duration::from_secs(100)).await;
+        assert_eq!(playing(adapter.get_webdata().await).as_deref(), Some("a"));
+
+        // The stub player never reports an end, so the watchdog moves on without it
+        tokio::time::sleep(Duration::from_secs(60)).await;
+        assert_eq!(playing(adapter.get_webdata().await).as_deref(), Some("b"));
+
+        // The player catching up late doesn't end the song after it
+        adapter.song_ended(stale).await;
+        assert_eq!(playing(adapter.get_webdata().await).as_deref(), Some("b"));
+    }
+
+    #[tokio::test]
+    async fn test_failed_seek() {
+        let (player, mut seen) = spawn_recording_player(false);
+        let mut adapter = test_state_with(player, Arc::new(FakeResolver::new())).await;
+        adapter.enqueue_and_play(test_request("a", 1)).await.unwrap();
+        assert!(matches!(seen.recv().await, Some(MusicPlayerCommand::Play(_))));
+
+        // Rather than carry on as if it seeked, the track gets stopped so the queue moves along
+        assert!(adapter.seek(SeekPosition::Absolute(30)).await.is_err());
+        assert!(matches!(seen.recv().await, Some(MusicPlayerCommand::Seek(_))));
+        assert!(matches!(seen.recv().await, Some(MusicPlayerCommand::Stop)));
+    }
+
+    #[tokio::test]
+    async fn test_failed_source_refresh() {
+        let good = SourceType::YoutubePlaylist("https://example.com/good".into());
+        let bad = SourceType::YoutubePlaylist("https://example.com/bad".into());
+        let resolver = FakeResolver::new().with_source(good.clone(), vec![test_song("a"), test_song("b")]);
+        let mut adapter = test_state_with(spawn_stub_player(), Arc::new(resolver)).await;
+
+        let uid = adapter.db.create_user("user".into(), None).await.unwrap();
+        adapter.db.create_source(uid, &good, true).await.unwrap();
+        let requester = adapter.db.get_requester(uid).await.unwrap();
+        adapter.autoplay.update_userplaylist(&requester).await.unwrap();
+        adapter.autoplay.enable_user(&uid).await.unwrap();
+
+        async fn remove_sources(db: &DbAdapter, uid: MinstrelUserId) {
+            for src in db.get_sources_from_userid(uid, true).await.unwrap() {
+                db.delete_source(src.id).await.unwrap();
+            }
+        }
+
+        // Every source failing (e.g. the resolver being down) keeps whatever was already loaded
+        remove_sources(&adapter.db, uid).await;
+        adapter.db.create_source(uid, &bad, true).await.unwrap();
+        assert!(matches!(adapter.autoplay.update_userplaylist(&requester).await, Err(AutoplayError::SourcesUnavailable)));
+        assert!(adapter.autoplay.get_scores().await.unwrap().iter().any(|s| s.userid == uid && s.enabled));
+
+        // Actually having no sources left is what takes a user out of autoplay
+        remove_sources(&adapter.db, uid).await;
+        adapter.autoplay.update_userplaylist(&requester).await.unwrap();
+        assert!(!adapter.autoplay.get_scores().await.unwrap().iter().any(|s| s.userid == uid && s.enabled));
+    }
+
+    #[tokio::test]
+    async fn test_resume_interrupted() {
+        let db = db::init_memory_db().await;
+        db.update_play_state_current(Some((&test_request("a", 1), 42))).await.unwrap();
+        db.update_play_state_queue(&[test_request("b", 1)]).await.unwrap();
+
+        let (player, mut seen) = spawn_recording_player(true);
+        let mut mstate = MusicState::with_resolver_pool(player, db, test_pool(Arc::new(FakeResolver::new()))).await;
+        let mut adapter = mstate.get_adapter();
+        tokio::spawn(async move { mstate.run().await });
+
+        let titles = adapter.get_webdata().await.queue.into_iter().map(|r| r.song.title).collect::<Vec<_>>();
+        assert_eq!(titles, ["a", "b"]);
+
+        adapter.start().await.unwrap();
+        assert!(matches!(seen.recv().await, Some(MusicPlayerCommand::Play(_))));
+        assert!(matches!(seen.recv().await, Some(MusicPlayerCommand::Seek(d)) if d == Duration::from_secs(42)));
+
+        // Only the interrupted song picks up partway through
+        end_current_song(&mut adapter).await;
+        assert!(matches!(seen.recv().await, Some(MusicPlayerCommand::Play(_))));
+        assert!(seen.try_recv().is_err());
+
+        // A player that can't seek still plays the interrupted song, just from the start
+        let db = db::init_memory_db().await;
+        db.update_play_state_current(Some((&test_request("a", 1), 42))).await.unwrap();
+
+        let (player, mut seen) = spawn_recording_player(false);
+        let mut mstate = MusicState::with_resolver_pool(player, db, test_pool(Arc::new(FakeResolver::new()))).await;
+        let mut adapter = mstate.get_adapter();
+        tokio::spawn(async move { mstate.run().await });
+
+        adapter.start().await.unwrap();
+        assert!(matches!(seen.recv().await, Some(MusicPlayerCommand::Play(_))));
+        assert!(matches!(seen.recv().await, Some(MusicPlayerCommand::Seek(_))));
+
+        let data = adapter.get_webdata().await;
+        assert_eq!(data.current_track.map(|r| r.song.title).as_deref(), Some("a"));
+        assert!(data.song_progress < 42);
+        assert!(seen.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_events() {
+        let mut adapter = test_state().await;
+        let request = |title| test_request(title, 1);
+
+        let mut rx = adapter.subscribe();
+        let mut data = adapter.get_webdata().await;
+
+        adapter.enqueue(request("a")).await.unwrap();
+        adapter.enqueue_and_play(request("b")).await.unwrap();
+        adapter.set_volume(50).await.unwrap();
+        end_current_song(&mut adapter).await;
+
+        // Applying every event gets a client to the same state as asking for all of it
+        let mut seqs = Vec::new();
+        while let Ok(msg) = rx.try_recv() {
+            if let MinstrelBroadcast::Event { seq, event } = msg {
+                seqs.push(seq);
+                assert!(data.apply(seq, event));
+            }
+        }
+        assert_eq!(data, adapter.get_webdata().await);
+        assert!(seqs.windows(2).all(|w| w[1] == w[0] + 1));
+
+        // Skipping ahead means something was missed
+        let event = MinstrelEvent::QueueChanged(VecDeque::new());
+        assert!(!data.apply(data.seq + 2, event));
+    }
+
+    #[tokio::test]
+    async fn test_song_likes() {
+        let mut adapter = test_state().await;
+        let muid = adapter.db.create_user("tester".into(), None).await.unwrap();
+        let request = |title| test_request(title, muid);
+        let liked = |songs: Vec<Song>| songs.into_iter().map(|s| s.title).collect::<Vec<_>>();
+
+        assert!(matches!(adapter.like_song(muid, None, true).await, Err(MusicError::NotPlaying)));
+
+        adapter.enqueue_and_play(request("a")).await.unwrap();
+        assert!(matches!(adapter.like_song(muid, None, true).await, Ok(MusicOk::LikedSong)));
+        // Liking again is not an error, and doesn't double up
+        adapter.like_song(muid, None, true).await.unwrap();
+        assert_eq!(liked(adapter.get_liked_songs(muid).await.unwrap()), ["a"]);
+
+        // Songs can still be liked and unliked from the history
+        adapter.enqueue(request("b")).await.unwrap();
+        end_current_song(&mut adapter).await;
+        let id = adapter.get_webdata().await.history[0].id;
+        adapter.like_song(muid, None, true).await.unwrap();
+        assert_eq!(liked(adapter.get_liked_songs(muid).await.unwrap()), ["a", "b"]);
+
+        assert!(matches!(adapter.like_song(muid, Some(id), false).await, Ok(MusicOk::UnlikedSong)));
+        assert!(matches!(adapter.like_song(muid, Some(RequestId::MAX), false).await, Err(MusicError::RequestNotFound)));
+        assert_eq!(liked(adapter.get_liked_songs(muid).await.unwrap()), ["b"]);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::*;
 
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
 use async_trait::async_trait;
@@ -10,6 +11,7 @@ use tokio::sync::{
 };
 
 use model::{
+    RequestId,
     Song,
 };
 
@@ -23,17 +25,107 @@ pub trait MusicPlayer {
     // For whatever initialization procedure might be needed
     async fn init(&self) -> Result<(), MusicError>;
 
-    /// Start playing the supplied track
-    async fn play(&mut self, song: &Song) -> Result<(), MusicError>;
+    /// Start playing the supplied track, `id` is the request to report back when it ends
+    async fn play(&mut self, song: &Song, id: RequestId) -> Result<(), MusicError>;
 
     /// Stop playing the current track
     async fn stop(&mut self) -> Result<(), MusicError>;
+
+    /// Pause the current track, keeping its position
+    async fn pause(&mut self) -> Result<(), MusicError>;
+
+    /// Continue a paused track from where it left off
+    async fn resume(&mut self) -> Result<(), MusicError>;
+
+    /// Jump to a position in the current track, measured from the start
+    async fn seek(&mut self, position: Duration) -> Result<(), MusicError>;
+
+    /// Set the volume as a percentage, should also apply to any tracks played afterwards
+    async fn set_volume(&mut self, volume: u8) -> Result<(), MusicError>;
+
+    /// Current volume as a percentage
+    async fn get_volume(&self) -> u8;
+}
+
+/// Plays on two players at once, e.g. Discord and a web stream.
+/// The primary is the one that counts, the secondary just follows along and only has its errors logged.
+pub struct TeePlayer<A: MusicPlayer, B: MusicPlayer> {
+    primary: Arc<Mutex<A>>,
+    secondary: Arc<Mutex<B>>,
+}
+
+impl<A: MusicPlayer, B: MusicPlayer> TeePlayer<A, B> {
+    pub fn new(primary: Arc<Mutex<A>>, secondary: Arc<Mutex<B>>) -> Self {
+        Self {
+            primary,
+            secondary,
+        }
+    }
+}
+
+// Log an error from the secondary player, without letting it affect anything
+fn check_secondary(ret: Result<(), MusicError>) {
+    if let Err(e) = ret {
+        warn!("secondary player failed: {:?}", e);
+    }
+}
+
+#[async_trait]
+impl<A: MusicPlayer + Send, B: MusicPlayer + Send> MusicPlayer for TeePlayer<A, B> {
+    async fn init(&self) -> Result<(), MusicError> {
+        check_secondary(self.secondary.lock().await.init().await);
+        self.primary.lock().await.init().await
+    }
+
+    async fn play(&mut self, song: &Song, id: RequestId) -> Result<(), MusicError> {
+        let ret = self.primary.lock().await.play(song, id).await;
+
+        // No point in the secondary playing a song the primary couldn't
+        if ret.is_ok() {
+            check_secondary(self.secondary.lock().await.play(song, id).await);
+        }
+
+        ret
+    }
+
+    async fn stop(&mut self) -> Result<(), MusicError> {
+        check_secondary(self.secondary.lock().await.stop().await);
+        self.primary.lock().await.stop().await
+    }
+
+    async fn pause(&mut self) -> Result<(), MusicError> {
+        check_secondary(self.secondary.lock().await.pause().await);
+        self.primary.lock().await.pause().await
+    }
+
+    async fn resume(&mut self) -> Result<(), MusicError> {
+        check_secondary(self.secondary.lock().await.resume().await);
+        self.primary.lock().await.resume().await
+    }
+
+    async fn seek(&mut self, position: Duration) -> Result<(), MusicError> {
+        check_secondary(self.secondary.lock().await.seek(position).await);
+        self.primary.lock().await.seek(position).await
+    }
+
+    async fn set_volume(&mut self, volume: u8) -> Result<(), MusicError> {
+        check_secondary(self.secondary.lock().await.set_volume(volume).await);
+        self.primary.lock().await.set_volume(volume).await
+    }
+
+    async fn get_volume(&self) -> u8 {
+        self.primary.lock().await.get_volume().await
+    }
 }
 
 #[derive(Clone, Debug)]
 pub enum MusicPlayerCommand {
-    Play(Song),
+    Play((Song, RequestId)),
     Stop,
+    Pause,
+    Resume,
+    Seek(Duration),
+    SetVolume(u8),
 }
 
 pub struct MusicPlayerTask<T: MusicPlayer> {
@@ -65,8 +157,12 @@ impl<T: MusicPlayer> MusicPlayerTask<T> {
             let ret = {
                 let mut player = self.player.lock().await;
                 match cmd {
-                    MusicPlayerCommand::Play(s) => player.play(&s).await,
+                    MusicPlayerCommand::Play((s, id)) => player.play(&s, id).await,
                     MusicPlayerCommand::Stop => player.stop().await,
+                    MusicPlayerCommand::Pause => player.pause().await,
+                    MusicPlayerCommand::Resume => player.resume().await,
+                    MusicPlayerCommand::Seek(pos) => player.seek(pos).await,
+                    MusicPlayerCommand::SetVolume(vol) => player.set_volume(vol).await,
                 }
             };
 
@@ -0,0 +1,343 @@
+use super::MusicError;
+use super::local::resolve_local_path;
+use super::resolver::SongResolver;
+
+use std::path::Path;
+
+use quick_xml::{
+    Reader,
+    events::Event,
+};
+
+use model::Song;
+
+use log::*;
+
+/// A single entry from a playlist file, with whatever metadata the file happened to provide
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PlaylistEntry {
+    pub location: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub duration: Option<i64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PlaylistFormat {
+    M3u,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    /// Determine the playlist format by file extension
+    pub fn from_path(path: &str) -> Option<PlaylistFormat> {
+        let ext = Path::new(path).extension()?.to_str()?.to_lowercase();
+
+        match ext.as_str() {
+            "m3u" | "m3u8" => Some(PlaylistFormat::M3u),
+            "pls" => Some(PlaylistFormat::Pls),
+            "xspf" => Some(PlaylistFormat::Xspf),
+            _ => None,
+        }
+    }
+}
+
+// Most players write "Artist - Title" as the display name
+fn split_display_name(name: &str) -> (Option<String>, Option<String>) {
+    match name.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim().to_string()), Some(title.trim().to_string())),
+        None if !name.trim().is_empty() => (None, Some(name.trim().to_string())),
+        None => (None, None),
+    }
+}
+
+// Playlists use -1 (or nothing at all) for an unknown length
+fn parse_length(len: &str) -> Option<i64> {
+    match len.trim().parse::<i64>() {
+        Ok(l) if l > 0 => Some(l),
+        _ => None,
+    }
+}
+
+pub fn parse_m3u(data: &str) -> Vec<PlaylistEntry> {
+    let mut ret = Vec::new();
+    let mut pending = PlaylistEntry::default();
+
+    for line in data.lines().map(|l| l.trim()) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<length> [attributes],<display name>
+            let (len, name) = info.split_once(',').unwrap_or((info, ""));
+            let len = len.split_whitespace().next().unwrap_or("");
+
+            let (artist, title) = split_display_name(name);
+            pending.duration = parse_length(len);
+            pending.artist = artist;
+            pending.title = title;
+        }
+        else if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        else {
+            pending.location = line.to_string();
+            ret.push(std::mem::take(&mut pending));
+        }
+    }
+
+    ret
+}
+
+pub fn parse_pls(data: &str) -> Vec<PlaylistEntry> {
+    let mut entries: Vec<(u64, PlaylistEntry)> = Vec::new();
+
+    for line in data.lines().map(|l| l.trim()) {
+        let (key, value) = match line.split_once('=') {
+            Some(kv) => kv,
+            None => continue,
+        };
+
+        // Keys are in the form of File1, Title1, Length1
+        let split = key.find(|c: char| c.is_ascii_digit()).unwrap_or(key.len());
+        let (field, num) = key.split_at(split);
+        let num = match num.parse::<u64>() {
+            Ok(n) => n,
+            Err(_) => continue, // NumberOfEntries, Version, etc.
+        };
+
+        let index = match entries.iter().position(|(n, _)| *n == num) {
+            Some(i) => i,
+            None => {
+                entries.push((num, PlaylistEntry::default()));
+                entries.len() - 1
+            }
+        };
+        let entry = &mut entries[index].1;
+
+        match field.to_lowercase().as_str() {
+            "file" => entry.location = value.to_string(),
+            "title" => {
+                let (artist, title) = split_display_name(value);
+                entry.artist = artist;
+                entry.title = title;
+            },
+            "length" => entry.duration = parse_length(value),
+            _ => (),
+        }
+    }
+
+    entries.sort_by_key(|(n, _)| *n);
+    entries.into_iter()
+        .map(|(_, e)| e)
+        .filter(|e| !e.location.is_empty())
+        .collect()
+}
+
+pub fn parse_xspf(data: &str) -> Result<Vec<PlaylistEntry>, MusicError> {
+    let mut reader = Reader::from_str(data);
+    reader.trim_text(true);
+
+    let mut ret = Vec::new();
+    let mut buf = Vec::new();
+    let mut current: Option<PlaylistEntry> = None;
+    let mut field: Option<Vec<u8>> = None;
+
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(e)) => match e.name() {
+                b"track" => current = Some(PlaylistEntry::default()),
+                name => field = Some(name.to_vec()),
+            },
+            Ok(Event::Text(e)) => {
+                if let (Some(entry), Some(name)) = (current.as_mut(), field.as_deref()) {
+                    let text = e.unescape_and_decode(&reader).map_err(|e| {
+                        debug!("bad text in xspf: {:?}", e);
+                        MusicError::InvalidSource
+                    })?;
+
+                    match name {
+                        b"location" => entry.location = text,
+                        b"title" => entry.title = Some(text),
+                        b"creator" => entry.artist = Some(text),
+                        // xspf durations are in milliseconds
+                        b"duration" => entry.duration = parse_length(&text).map(|ms| ms / 1000),
+                        _ => (),
+                    }
+                }
+            },
+            Ok(Event::End(e)) => match e.name() {
+                b"track" => {
+                    if let Some(entry) = current.take() {
+                        if !entry.location.is_empty() {
+                            ret.push(entry);
+                        }
+                    }
+                },
+                _ => field = None,
+            },
+            Ok(Event::Eof) => break,
+            Err(e) => {
+                debug!("failed to parse xspf: {:?}", e);
+                return Err(MusicError::InvalidSource);
+            },
+            _ => (),
+        }
+
+        buf.clear();
+    }
+
+    Ok(ret)
+}
+
+// Minimal decoding of file:// URIs, since some players write those instead of plain paths
+fn file_uri_to_path(uri: &str) -> String {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i+1..i+3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Turn a single playlist entry into a Song, fetching metadata if the playlist didn't have enough
+fn song_from_entry(entry: PlaylistEntry, basedir: &Path, resolver: &dyn SongResolver) -> Result<Song, MusicError> {
+    if entry.location.starts_with("http") {
+        return match (entry.title, entry.duration) {
+            (Some(title), Some(duration)) => Ok(Song {
+                title,
+                artist: entry.artist.unwrap_or_else(|| String::from("Unknown")),
+                url: entry.location,
+                thumbnail: String::new(),
+                duration,
+            }),
+            _ => resolver.resolve_song(&entry.location),
+        };
+    }
+
+    // Relative entries are relative to the playlist file, and everything still has to live in the music root
+    let path = basedir.join(file_uri_to_path(&entry.location));
+    let path = resolve_local_path(&path.to_string_lossy())?;
+
+    resolver.resolve_song(&path.to_string_lossy())
+}
+
+/// Load all the songs referenced by a playlist file (relative to the local music root).
+/// Entries are resolved through `resolver`, so a playlist can mix local files and urls.
+pub fn fetch_songs_from_playlist_file(path: &str, resolver: &dyn SongResolver) -> Result<Vec<Song>, MusicError> {
+    let format = PlaylistFormat::from_path(path).ok_or(MusicError::InvalidSource)?;
+    let file = resolve_local_path(path)?;
+
+    let data = std::fs::read(&file).map_err(|e| {
+        error!("could not read playlist file {}: {:?}", file.display(), e);
+        MusicError::InvalidSource
+    })?;
+    // Older m3u files are usually latin-1, lossy is good enough to get the paths out
+    let data = String::from_utf8_lossy(&data);
+
+    let entries = match format {
+        PlaylistFormat::M3u => parse_m3u(&data),
+        PlaylistFormat::Pls => parse_pls(&data),
+        PlaylistFormat::Xspf => parse_xspf(&data)?,
+    };
+
+    let basedir = file.parent().unwrap_or_else(|| Path::new("/"));
+
+    let songs = entries.into_iter()
+        .filter_map(|e| {
+            let location = e.location.clone();
+            match song_from_entry(e, basedir, resolver) {
+                Ok(song) if song.duration > 0 => Some(song),
+                Ok(_) => {
+                    debug!("skipping playlist entry {}, could not determine duration", location);
+                    None
+                },
+                Err(err) => {
+                    warn!("skipping playlist entry {}: {:?}", location, err);
+                    None
+                }
+            }
+        })
+        .collect::<Vec<Song>>();
+
+    debug!("loaded {} songs from playlist {}", songs.len(), file.display());
+
+    Ok(songs)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_m3u() {
+        let data = "#EXTM3U\n\
+            #EXTINF:123,Some Artist - Some Title\n\
+            music/song.mp3\n\
+            \n\
+            https://www.youtube.com/watch?v=abc\n";
+
+        let entries = parse_m3u(data);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0], PlaylistEntry {
+            location: "music/song.mp3".into(),
+            title: Some("Some Title".into()),
+            artist: Some("Some Artist".into()),
+            duration: Some(123),
+        });
+        assert_eq!(entries[1].location, "https://www.youtube.com/watch?v=abc");
+        assert_eq!(entries[1].title, None);
+    }
+
+    #[test]
+    fn test_parse_pls() {
+        let data = "[playlist]\n\
+            File2=second.flac\n\
+            File1=first.ogg\n\
+            Title1=First\n\
+            Length1=-1\n\
+            NumberOfEntries=2\n";
+
+        let entries = parse_pls(data);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].location, "first.ogg");
+        assert_eq!(entries[0].title, Some("First".into()));
+        assert_eq!(entries[0].duration, None);
+        assert_eq!(entries[1].location, "second.flac");
+    }
+
+    #[test]
+    fn test_parse_xspf() {
+        let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <playlist version="1" xmlns="http://xspf.org/ns/0/">
+              <title>ignored</title>
+              <trackList>
+                <track>
+                  <location>file:///music/some%20song.mp3</location>
+                  <title>Some Song</title>
+                  <creator>Someone</creator>
+                  <duration>61000</duration>
+                </track>
+              </trackList>
+            </playlist>"#;
+
+        let entries = parse_xspf(data).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].title, Some("Some Song".into()));
+        assert_eq!(entries[0].artist, Some("Someone".into()));
+        assert_eq!(entries[0].duration, Some(61));
+        assert_eq!(file_uri_to_path(&entries[0].location), "/music/some song.mp3");
+    }
+}
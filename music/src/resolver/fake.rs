@@ -0,0 +1,56 @@
+use crate::MusicError;
+use super::SongResolver;
+
+use std::collections::HashMap;
+
+use model::{
+    Song,
+    SourceType,
+};
+
+/// Scripted resolver for tests and offline deployments.
+/// Anything that has not been scripted fails to resolve.
+#[derive(Debug, Default)]
+pub struct FakeResolver {
+    songs: HashMap<String, Song>,
+    sources: Vec<(SourceType, Vec<Song>)>,
+}
+
+impl FakeResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve `url` to the supplied song
+    pub fn with_song(mut self, url: &str, song: Song) -> Self {
+        self.songs.insert(url.to_string(), song);
+        self
+    }
+
+    /// Resolve `source` to the supplied list of songs
+    pub fn with_source(mut self, source: SourceType, songs: Vec<Song>) -> Self {
+        self.sources.push((source, songs));
+        self
+    }
+}
+
+impl SongResolver for FakeResolver {
+    fn supports(&self, _url: &str) -> bool {
+        true
+    }
+
+    fn supports_source(&self, _source: &SourceType) -> bool {
+        true
+    }
+
+    fn resolve_song(&self, url: &str) -> Result<Song, MusicError> {
+        self.songs.get(url).cloned().ok_or(MusicError::FailedToRetrieve)
+    }
+
+    fn resolve_source(&self, source: &SourceType) -> Result<Vec<Song>, MusicError> {
+        self.sources.iter()
+            .find(|(s, _)| s == source)
+            .map(|(_, songs)| songs.clone())
+            .ok_or(MusicError::FailedToRetrieve)
+    }
+}
@@ -0,0 +1,48 @@
+use crate::MusicError;
+use crate::local::{
+    resolve_local_path,
+    song_from_file,
+    fetch_songs_from_directory,
+};
+use crate::playlist::fetch_songs_from_playlist_file;
+use super::SongResolver;
+
+use model::{
+    Song,
+    SourceType,
+};
+
+/// Resolves files and directories under the configured local music root
+#[derive(Debug, Default)]
+pub struct LocalResolver;
+
+impl LocalResolver {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl SongResolver for LocalResolver {
+    fn supports(&self, url: &str) -> bool {
+        !url.starts_with("http")
+    }
+
+    fn supports_source(&self, source: &SourceType) -> bool {
+        matches!(source, SourceType::LocalDirectory(_) | SourceType::PlaylistFile(_))
+    }
+
+    fn resolve_song(&self, url: &str) -> Result<Song, MusicError> {
+        let path = resolve_local_path(url)?;
+
+        song_from_file(&path)
+    }
+
+    fn resolve_source(&self, source: &SourceType) -> Result<Vec<Song>, MusicError> {
+        match source {
+            SourceType::LocalDirectory(path) => fetch_songs_from_directory(path),
+            // Entries that point at urls without enough metadata will be skipped on their own
+            SourceType::PlaylistFile(path) => fetch_songs_from_playlist_file(path, self),
+            _ => Err(MusicError::InvalidSource),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::MusicError;
+use crate::playlist::fetch_songs_from_playlist_file;
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use minstrel_config::read_config;
+
+use model::{
+    Song,
+    SourceType,
+};
+
+mod ytdl;
+mod localfile;
+mod fake;
+mod pool;
+
+pub use ytdl::*;
+pub use localfile::*;
+pub use fake::*;
+pub use pool::*;
+
+/// Interface for anything that can turn a url/path or a Source into Song metadata.
+/// Implementations are allowed to block (e.g. shelling out to yt-dlp), so avoid calling
+/// these directly from async code.
+pub trait SongResolver: Send + Sync + fmt::Debug {
+    /// Whether this resolver knows how to handle the supplied url or path
+    fn supports(&self, url: &str) -> bool;
+
+    /// Whether this resolver knows how to load the supplied source
+    fn supports_source(&self, source: &SourceType) -> bool;
+
+    /// Fetch the metadata for a single song
+    fn resolve_song(&self, url: &str) -> Result<Song, MusicError>;
+
+    /// Fetch all the songs contained in a source
+    fn resolve_source(&self, source: &SourceType) -> Result<Vec<Song>, MusicError>;
+}
+
+/// Tries each resolver in order, using the first one that claims to support the url or source
+#[derive(Debug)]
+pub struct ChainResolver {
+    resolvers: Vec<Arc<dyn SongResolver>>,
+}
+
+impl ChainResolver {
+    pub fn new(resolvers: Vec<Arc<dyn SongResolver>>) -> Self {
+        Self {
+            resolvers,
+        }
+    }
+}
+
+impl SongResolver for ChainResolver {
+    fn supports(&self, url: &str) -> bool {
+        self.resolvers.iter().any(|r| r.supports(url))
+    }
+
+    fn supports_source(&self, source: &SourceType) -> bool {
+        self.resolvers.iter().any(|r| r.supports_source(source))
+    }
+
+    fn resolve_song(&self, url: &str) -> Result<Song, MusicError> {
+        self.resolvers.iter()
+            .find(|r| r.supports(url))
+            .ok_or(MusicError::InvalidUrl)?
+            .resolve_song(url)
+    }
+
+    fn resolve_source(&self, source: &SourceType) -> Result<Vec<Song>, MusicError> {
+        match source {
+            // Playlist files can reference songs of any type, so resolve the entries through the whole chain
+            SourceType::PlaylistFile(path) if self.supports_source(source) =>
+                fetch_songs_from_playlist_file(path, self),
+            _ => self.resolvers.iter()
+                .find(|r| r.supports_source(source))
+                .ok_or(MusicError::InvalidSource)?
+                .resolve_source(source),
+        }
+    }
+}
+
+/// The resolver used for normal operation: yt-dlp for anything remote, and files under the local music root
+pub fn default_resolver() -> Arc<dyn SongResolver> {
+    Arc::new(ChainResolver::new(vec![
+        Arc::new(YtdlResolver::with_timeout(Duration::from_secs(read_config!(music.resolver_timeout)))),
+        Arc::new(LocalResolver::new()),
+    ]))
+}
@@ -0,0 +1,115 @@
+use crate::MusicError;
+use super::SongResolver;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Semaphore;
+
+use minstrel_config::read_config;
+use model::{
+    Song,
+    SourceType,
+};
+
+use log::*;
+
+/// Runs a (blocking) SongResolver on tokio's blocking threads, so async callers never stall.
+/// Caps how many lookups run at once, and gives up on lookups that take too long.
+#[derive(Debug, Clone)]
+pub struct ResolverPool {
+    resolver: Arc<dyn SongResolver>,
+    permits: Arc<Semaphore>,
+    timeout: Duration,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl ResolverPool {
+    pub fn new(resolver: Arc<dyn SongResolver>, workers: usize, timeout: Duration, retries: u32, backoff: Duration) -> Self {
+        Self {
+            resolver,
+            permits: Arc::new(Semaphore::new(workers.max(1))),
+            timeout,
+            retries,
+            backoff,
+        }
+    }
+
+    /// Create a pool using the worker/timeout/retry values from the config
+    pub fn from_config(resolver: Arc<dyn SongResolver>) -> Self {
+        Self::new(
+            resolver,
+            read_config!(music.resolver_workers),
+            Duration::from_secs(read_config!(music.resolver_timeout)),
+            read_config!(music.resolver_retries),
+            Duration::from_secs(read_config!(music.resolver_retry_backoff)),
+        )
+    }
+
+    /// Direct access to the underlying resolver, for checking what it supports
+    pub fn resolver(&self) -> &Arc<dyn SongResolver> {
+        &self.resolver
+    }
+
+    pub async fn resolve_song(&self, url: String) -> Result<Song, MusicError> {
+        let resolver = self.resolver.clone();
+
+        self.run(move || resolver.resolve_song(&url)).await
+    }
+
+    pub async fn resolve_source(&self, source: SourceType) -> Result<Vec<Song>, MusicError> {
+        let resolver = self.resolver.clone();
+
+        self.run(move || resolver.resolve_source(&source)).await
+    }
+
+    async fn run<T, F>(&self, func: F) -> Result<T, MusicError>
+        where
+            T: Send + 'static,
+            F: Fn() -> Result<T, MusicError> + Clone + Send + 'static,
+    {
+        let mut attempt = 0;
+
+        loop {
+            match self.run_once(func.clone()).await {
+                // Only retry things that might go differently the second time around
+                Err(e @ (MusicError::FailedToRetrieve | MusicError::ResolverTimeout)) if attempt < self.retries => {
+                    attempt += 1;
+                    debug!("resolver attempt {} failed with {:?}, retrying", attempt, e);
+                    tokio::time::sleep(self.backoff * attempt).await;
+                },
+                ret => return ret,
+            }
+        }
+    }
+
+    async fn run_once<T, F>(&self, func: F) -> Result<T, MusicError>
+        where
+            T: Send + 'static,
+            F: FnOnce() -> Result<T, MusicError> + Send + 'static,
+    {
+        let permit = self.permits.clone().acquire_owned().await
+            .map_err(|_| MusicError::UnknownError)?;
+
+        // The permit moves into the blocking task, so a lookup that timed out still counts
+        // against the cap until yt-dlp actually gives up. YtdlResolver kills yt-dlp at the same
+        // timeout, so that doesn't take long.
+        let handle = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            func()
+        });
+
+        match tokio::time::timeout(self.timeout, handle).await {
+            Ok(Ok(ret)) => ret,
+            Ok(Err(e)) => {
+                error!("resolver task failed: {:?}", e);
+                Err(MusicError::UnknownError)
+            },
+            Err(_) => {
+                warn!("resolver took longer than {:?}, giving up", self.timeout);
+                Err(MusicError::ResolverTimeout)
+            },
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::MusicError;
+use super::SongResolver;
+
+use std::time::Duration;
+
+use youtube_dl::{
+    YoutubeDl,
+    YoutubeDlOutput,
+    SingleVideo,
+};
+
+use model::{
+    Song,
+    SourceType,
+};
+
+use log::*;
+
+pub fn song_from_video(video: SingleVideo) -> Result<Song, MusicError> {
+    // Deleted or private videos in a playlist tend to be missing a duration
+    let duration = match video.duration.as_ref().and_then(|d| d.as_f64()) {
+        Some(d) => d as i64,
+        None => {
+            debug!("video {} is missing a duration", video.id);
+            return Err(MusicError::Unplayable);
+        }
+    };
+
+    let thumbnail = video.thumbnail
+        .unwrap_or(format!("https://img.youtube.com/vi/{}/maxresdefault.jpg", video.id));
+    let url = format!("https://www.youtube.com/watch?v={}", video.id);
+
+    Ok(Song {
+        title: video.title,
+        artist: video.channel.unwrap_or_else(|| String::from("Unknown")),
+        url,
+        thumbnail,
+        duration,
+    })
+}
+
+// Timeouts get retried, anything else yt-dlp failed at might just be the network
+fn ytdl_error(e: youtube_dl::Error) -> MusicError {
+    match e {
+        youtube_dl::Error::ProcessTimeout => MusicError::ResolverTimeout,
+        _ => MusicError::FailedToRetrieve,
+    }
+}
+
+/// Resolves anything yt-dlp knows how to handle
+#[derive(Debug, Default)]
+pub struct YtdlResolver {
+    // yt-dlp gets killed if it runs longer than this
+    timeout: Option<Duration>,
+}
+
+impl YtdlResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+        }
+    }
+
+    fn ytdl(&self, url: &str) -> YoutubeDl {
+        let mut ytdl = YoutubeDl::new(url);
+        if let Some(timeout) = self.timeout {
+            ytdl.process_timeout(timeout);
+        }
+
+        ytdl
+    }
+}
+
+impl SongResolver for YtdlResolver {
+    fn supports(&self, url: &str) -> bool {
+        url.starts_with("http")
+    }
+
+    fn supports_source(&self, source: &SourceType) -> bool {
+        matches!(source, SourceType::YoutubePlaylist(_))
+    }
+
+    fn resolve_song(&self, url: &str) -> Result<Song, MusicError> {
+        if !self.supports(url) {
+            return Err(MusicError::InvalidUrl);
+        }
+
+        let data = self.ytdl(url)
+            .run()
+            .map_err(|e| {
+                    error!("youtube_dl error: {:?}", e);
+                    ytdl_error(e)
+                }
+            )?;
+
+        match data {
+            YoutubeDlOutput::SingleVideo(v) => song_from_video(*v),
+            YoutubeDlOutput::Playlist(_) => Err(MusicError::InvalidUrl),
+        }
+    }
+
+    fn resolve_source(&self, source: &SourceType) -> Result<Vec<Song>, MusicError> {
+        let url = match source {
+            SourceType::YoutubePlaylist(url) => url,
+            _ => return Err(MusicError::InvalidSource),
+        };
+
+        let data = self.ytdl(url)
+            .flat_playlist(true)
+            .run()
+            .map_err(|e| {
+                error!("youtube_dl error fetching playlist {}: {:?}", url, e);
+                ytdl_error(e)
+            })?;
+
+        let data = match data {
+            YoutubeDlOutput::Playlist(p) => p,
+            YoutubeDlOutput::SingleVideo(_) => return Err(MusicError::InvalidSource),
+        };
+
+        let entries = data.entries.unwrap_or_default();
+        let total = entries.len();
+
+        let songs = entries.into_iter()
+            .filter_map(|e| song_from_video(e).ok())
+            .collect::<Vec<Song>>();
+
+        if songs.len() != total {
+            debug!("skipped {} unavailable videos in playlist {}", total - songs.len(), url);
+        }
+
+        Ok(songs)
+    }
+}
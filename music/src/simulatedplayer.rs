@@ -0,0 +1,227 @@
+use crate::MusicError;
+use crate::adapters::MusicAdapter;
+use crate::player::MusicPlayer;
+
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::Instant;
+
+use minstrel_config::read_config;
+use model::{
+    RequestId,
+    Song,
+    MAX_VOLUME,
+};
+
+use log::*;
+
+
+/// Player that doesn't output any audio, songs just "play" for their duration and then end.
+/// For running the web dashboard or tests without Discord.
+pub struct SimulatedPlayer {
+    adapter: MusicAdapter, // For telling MusicState when a song ends
+    timescale: f64, // How many seconds of song pass per real second
+    current: Option<Song>,
+    current_id: RequestId, // Request the current song is for, to report back when it ends
+    position: Duration, // Song position as of `resumed`, or where it was paused
+    resumed: Option<Instant>, // None while paused
+    volume: u8,
+    // Bumped whenever the current timer should no longer end the song, e.g. on a stop or seek
+    generation: Arc<AtomicU64>,
+}
+
+impl SimulatedPlayer {
+    pub fn new(adapter: MusicAdapter) -> Self {
+        Self::with_timescale(adapter, read_config!(music.simulated_timescale))
+    }
+
+    pub fn with_timescale(adapter: MusicAdapter, timescale: f64) -> Self {
+        Self {
+            adapter,
+            timescale: if timescale > 0.0 { timescale } else { 1.0 },
+            current: None,
+            current_id: 0,
+            position: Duration::ZERO,
+            resumed: None,
+            volume: read_config!(music.default_volume).min(MAX_VOLUME),
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    // Where the current song is at right now, in song time
+    fn elapsed(&self) -> Duration {
+        match self.resumed {
+            Some(resumed) => self.position + resumed.elapsed().mul_f64(self.timescale),
+            None => self.position,
+        }
+    }
+
+    // Throw out the current timer, if there is one
+    fn cancel_timer(&mut self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Play the current song from `position`, and start a timer to end it
+    fn start_timer(&mut self, position: Duration) {
+        self.cancel_timer();
+        self.position = position;
+        self.resumed = Some(Instant::now());
+
+        let song = match &self.current {
+            Some(s) => s,
+            None => return,
+        };
+
+        // Songs without a known length (e.g. livestreams) play until they are stopped
+        if song.duration <= 0 {
+            return;
+        }
+
+        let remaining = Duration::from_secs(song.duration as u64).saturating_sub(position).div_f64(self.timescale);
+        let generation = self.generation.load(Ordering::SeqCst);
+        let current = self.generation.clone();
+        let id = self.current_id;
+        let mut adapter = self.adapter.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep(remaining).await;
+
+            if current.load(Ordering::SeqCst) == generation {
+                debug!("simulated song finished");
+                adapter.song_ended(id).await;
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl MusicPlayer for SimulatedPlayer {
+    async fn init(&self) -> Result<(), MusicError> {
+        Ok(())
+    }
+
+    async fn play(&mut self, song: &Song, id: RequestId) -> Result<(), MusicError> {
+        debug!("simulating {} for {}s", song.title, song.duration);
+        self.current = Some(song.clone());
+        self.current_id = id;
+        self.start_timer(Duration::ZERO);
+
+        Ok(())
+    }
+
+    async fn stop(&mut self) -> Result<(), MusicError> {
+        self.cancel_timer();
+
+        // Stopping ends the song, same as any other player, which is how skipping moves on
+        if self.current.is_some() {
+            let mut adapter = self.adapter.clone();
+            let id = self.current_id;
+            tokio::spawn(async move {
+                adapter.song_ended(id).await;
+            });
+        }
+
+        self.current = None;
+        self.resumed = None;
+        self.position = Duration::ZERO;
+
+        Ok(())
+    }
+
+    async fn pause(&mut self) -> Result<(), MusicError> {
+        if self.current.is_none() {
+            return Err(MusicError::NotPlaying);
+        }
+
+        self.cancel_timer();
+        self.position = self.elapsed();
+        self.resumed = None;
+
+        Ok(())
+    }
+
+    async fn resume(&mut self) -> Result<(), MusicError> {
+        if self.current.is_none() {
+            return Err(MusicError::NotPlaying);
+        }
+
+        if self.resumed.is_none() {
+            self.start_timer(self.position);
+        }
+
+        Ok(())
+    }
+
+    async fn seek(&mut self, position: Duration) -> Result<(), MusicError> {
+        if self.current.is_none() {
+            return Err(MusicError::NotPlaying);
+        }
+
+        // Seeking while paused stays paused
+        match self.resumed {
+            Some(_) => self.start_timer(position),
+            None => self.position = position,
+        }
+
+        Ok(())
+    }
+
+    async fn set_volume(&mut self, volume: u8) -> Result<(), MusicError> {
+        self.volume = volume;
+
+        Ok(())
+    }
+
+    async fn get_volume(&self) -> u8 {
+        self.volume
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MusicOk;
+    use crate::musicstate::{
+        MusicControlCmd,
+        MSCMD,
+    };
+    use crate::testutil::*;
+    use tokio::sync::mpsc;
+
+    // Answer the next command sent to MusicState, and hand it back
+    async fn next_cmd(rx: &mut mpsc::Receiver<MSCMD>) -> MusicControlCmd {
+        let (reply, cmd) = rx.recv().await.unwrap();
+        reply.send(Ok(MusicOk::Unimplemented)).ok();
+        cmd
+    }
+
+    #[tokio::test]
+    async fn test_simulated_song_ends() {
+        let (adapter, mut rx) = test_adapter().await;
+        tokio::time::pause();
+
+        // A minute of song in 30 seconds
+        let mut player = SimulatedPlayer::with_timescale(adapter, 2.0);
+        let song = test_song("song");
+
+        // Stopping ends it right away...
+        player.play(&song, 1).await.unwrap();
+        player.stop().await.unwrap();
+        assert!(matches!(next_cmd(&mut rx).await, MusicControlCmd::SongEnded(1)));
+
+        // ...otherwise it plays out
+        player.play(&song, 2).await.unwrap();
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert!(rx.try_recv().is_err());
+
+        tokio::time::advance(Duration::from_secs(2)).await;
+        assert!(matches!(next_cmd(&mut rx).await, MusicControlCmd::SongEnded(2)));
+    }
+}
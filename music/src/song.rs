@@ -1,98 +1,15 @@
-use super::MusicError;
-
-use youtube_dl::{YoutubeDl, YoutubeDlOutput, SingleVideo};
-
-use model::{
-    Requester,
-    Song,
-    SongRequest,
-    SourceType,
-};
-
-macro_rules! get_duration {
-    ($video:ident) => {
-        $video.duration.as_ref().expect("Duration missing from video")
-            .as_f64()
-            .expect("Could not parse as an f64 for some reason")
-            as i64
-    };
-}
-
-pub fn song_from_video(video: SingleVideo) -> Song {
-    let duration = get_duration!(video);
-    let thumbnail = video.thumbnail
-        .unwrap_or(format!("https://img.youtube.com/vi/{}/maxresdefault.jpg", video.id));
-    let url = format!("https://www.youtube.com/watch?v={}", video.id);
-
-    Song {
-        title: video.title,
-        artist: video.channel.unwrap_or_else(|| String::from("Unknown")),
-        url,
-        thumbnail,
-        duration,
-    }
-}
-
-pub fn fetch_song_from_yt(url: String) -> Result<Song, MusicError> {
-    if !url.starts_with("http") {
-        return Err(MusicError::InvalidUrl);
-    }
-
-    let data = YoutubeDl::new(&url)
-        .run()
-        .map_err(|e| {
-                log::error!("youtube_dl error: {:?}", e);
-                MusicError::FailedToRetrieve
-            }
-        )?;
-
-    let data = match data {
-        YoutubeDlOutput::SingleVideo(v) => v,
-        YoutubeDlOutput::Playlist(_) => return Err(MusicError::UnknownError),
-    };
-
-    Ok(song_from_video(*data))
-}
-
-
-// TODO: deprecate this function when proper loading from sources
-/// Create a new song struct from an existing metadata struct
-/// Mostly needed only for the autoplay playlist feature
-pub fn song_request_from_video(video: SingleVideo, requester: &Requester) -> SongRequest {
-    let song = song_from_video(video);
-
-    SongRequest {
-        song,
-        requested_by: requester.clone(),
-    }
-}
-
-pub fn fetch_songs_from_source(source: &SourceType) -> Vec<Song> {
-    match source {
-        SourceType::YoutubePlaylist(url) => {
-            let data = youtube_dl::YoutubeDl::new(url)
-                .flat_playlist(true)
-                .run();
-
-            let data = match data {
-                Ok(YoutubeDlOutput::Playlist(p)) => p,
-                Ok(YoutubeDlOutput::SingleVideo(_)) => todo!("handle incorrect source mapping somehow"),
-                Err(e) => panic!("something broke: {:?}", e),
-            };
-
-            if data.entries.is_none() {
-                panic!("playlist entries is none, this shouldn't happen");
-            }
-
-            let tmpdata = data.entries.unwrap();
-            tmpdata.iter()
-                // Filter out songs without a duration, since we will panic without that anyway
-                // TODO: figure out if there is a better way to filter out deleted videos in a
-                // playlist
-                .filter(|e| e.duration.is_some())
-                .map(|e| song_from_video(e.clone()))
-                .collect()
-        },
+use super::playlist::PlaylistFormat;
+
+use model::SourceType;
+
+/// Guess what kind of source a user-supplied path refers to
+pub fn source_type_from_path(path: String) -> SourceType {
+    if path.starts_with("http") {
+        SourceType::YoutubePlaylist(path)
+    } else if PlaylistFormat::from_path(&path).is_some() {
+        SourceType::PlaylistFile(path)
+    } else {
+        SourceType::LocalDirectory(path)
     }
 }
 
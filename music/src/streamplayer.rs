@@ -0,0 +1,342 @@
+use crate::MusicError;
+use crate::adapters::MusicAdapter;
+use crate::player::MusicPlayer;
+
+use std::process::Stdio;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::sync::atomic::{
+    AtomicU64,
+    Ordering,
+};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tokio::sync::broadcast;
+use tokio::time::Instant;
+
+use minstrel_config::read_config;
+use model::{
+    RequestId,
+    Song,
+    MAX_VOLUME,
+};
+
+use log::*;
+
+
+const LOUDNORM_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+
+// Enough to ride out a short hiccup on a listener's end, anyone further behind skips ahead
+const STREAM_BUFFER_CHUNKS: usize = 64;
+
+/// Handle for listening in on a StreamPlayer. Every listener gets the same chunks as they are
+/// transcoded, so everyone hears the same thing at the same time.
+#[derive(Clone, Debug)]
+pub struct AudioStream {
+    tx: broadcast::Sender<Bytes>,
+}
+
+impl AudioStream {
+    // MP3 frames stand on their own, so songs can be joined back to back in one endless stream
+    pub const CONTENT_TYPE: &'static str = "audio/mpeg";
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Bytes> {
+        self.tx.subscribe()
+    }
+}
+
+/// Player that transcodes songs with ffmpeg into a live MP3 stream, Icecast-style
+pub struct StreamPlayer {
+    // For telling MusicState when a song ends, None if another player is in charge of that
+    adapter: Option<MusicAdapter>,
+    stream: AudioStream,
+    current: Option<Song>,
+    current_id: RequestId, // Request the current song is for, to report back when it ends
+    // What ffmpeg reads for a request, so seeking doesn't resolve again. Filled in once it's been found.
+    input: Arc<Mutex<Option<(RequestId, String)>>>,
+    position: Duration, // Song position as of `resumed`, or where it was paused
+    resumed: Option<Instant>, // None while paused
+    volume: u8,
+    // Bumped whenever the running transcode should stop, e.g. on a stop or seek
+    generation: Arc<AtomicU64>,
+}
+
+impl StreamPlayer {
+    pub fn new(adapter: Option<MusicAdapter>) -> Self {
+        Self {
+            adapter,
+            stream: AudioStream { tx: broadcast::channel(STREAM_BUFFER_CHUNKS).0 },
+            current: None,
+            current_id: 0,
+            input: Arc::new(Mutex::new(None)),
+            position: Duration::ZERO,
+            resumed: None,
+            volume: read_config!(music.default_volume).min(MAX_VOLUME),
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn get_stream(&self) -> AudioStream {
+        self.stream.clone()
+    }
+
+    // Where the current song is at right now
+    fn elapsed(&self) -> Duration {
+        match self.resumed {
+            Some(resumed) => self.position + resumed.elapsed(),
+            None => self.position,
+        }
+    }
+
+    // Stop whatever transcode is running, if there is one
+    fn cancel(&mut self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Start transcoding the current song into the stream, from `position`. Finding the song's audio
+    /// can take a while, so that and the transcode itself happen in the background.
+    fn start_transcode(&mut self, position: Duration) -> Result<(), MusicError> {
+        self.cancel();
+        self.position = position;
+        self.resumed = Some(Instant::now());
+
+        let song = match &self.current {
+            Some(s) => s.clone(),
+            None => return Err(MusicError::NotPlaying),
+        };
+
+        let filter = format!("{},volume={}", LOUDNORM_FILTER, self.volume as f64 / 100.0);
+        let bitrate = format!("{}k", read_config!(music.stream_bitrate));
+        let tx = self.stream.tx.clone();
+        let generation = self.generation.load(Ordering::SeqCst);
+        let current = self.generation.clone();
+        let id = self.current_id;
+        let input = self.input.clone();
+        let adapter = self.adapter.clone();
+
+        tokio::spawn(async move {
+            // Seeking and volume changes restart the transcode, reuse what was found the first time
+            let cached = input.lock().unwrap().as_ref()
+                .filter(|(rid, _)| *rid == id)
+                .map(|(_, i)| i.clone());
+
+            let source = match cached {
+                Some(i) => i,
+                None => {
+                    let timeout = Duration::from_secs(read_config!(music.resolver_timeout));
+                    match tokio::time::timeout(timeout, resolve_input(&song)).await {
+                        Ok(Ok(i)) => {
+                            *input.lock().unwrap() = Some((id, i.clone()));
+                            i
+                        },
+                        Ok(Err(_)) => {
+                            song_failed(adapter, id, &current, generation).await;
+                            return;
+                        },
+                        Err(_) => {
+                            error!("timed out finding audio for {}", song.url);
+                            song_failed(adapter, id, &current, generation).await;
+                            return;
+                        },
+                    }
+                },
+            };
+
+            // Stopped or moved on while finding the audio
+            if current.load(Ordering::SeqCst) != generation {
+                return;
+            }
+
+            // -re paces the output in real time, which is what keeps every listener in sync
+            let ffmpeg = Command::new("ffmpeg")
+                .args(["-hide_banner", "-loglevel", "error", "-re"])
+                .args(["-ss", &position.as_secs_f64().to_string()])
+                .args(["-i", &source])
+                .args(["-vn", "-af", &filter, "-c:a", "libmp3lame", "-b:a", &bitrate, "-f", "mp3", "pipe:1"])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .kill_on_drop(true)
+                .spawn();
+
+            let mut ffmpeg = match ffmpeg {
+                Ok(f) => f,
+                Err(e) => {
+                    error!("failed to start ffmpeg: {:?}", e);
+                    song_failed(adapter, id, &current, generation).await;
+                    return;
+                },
+            };
+
+            // Unwrap is fine, stdout was piped above
+            let mut stdout = ffmpeg.stdout.take().unwrap();
+            let mut buf = vec![0u8; 4096];
+
+            loop {
+                let read = stdout.read(&mut buf).await;
+
+                // Dropping ffmpeg here kills it
+                if current.load(Ordering::SeqCst) != generation {
+                    return;
+                }
+
+                match read {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        // Only fails when nobody is listening, which is fine
+                        tx.send(Bytes::copy_from_slice(&buf[..n])).ok();
+                    },
+                    Err(e) => {
+                        error!("error reading from ffmpeg: {:?}", e);
+                        break;
+                    },
+                }
+            }
+
+            if let Err(e) = ffmpeg.wait().await {
+                error!("error waiting on ffmpeg: {:?}", e);
+            }
+
+            // Stopped right as it ended, whatever stopped it is taking care of what comes next
+            if current.load(Ordering::SeqCst) != generation {
+                return;
+            }
+
+            if let Some(mut adapter) = adapter {
+                debug!("streamed song finished");
+                adapter.song_ended(id).await;
+            }
+        });
+
+        Ok(())
+    }
+}
+
+// The song couldn't be streamed, move on from it unless something else already has
+async fn song_failed(adapter: Option<MusicAdapter>, id: RequestId, current: &AtomicU64, generation: u64) {
+    if current.load(Ordering::SeqCst) != generation {
+        return;
+    }
+
+    if let Some(mut adapter) = adapter {
+        adapter.song_ended(id).await;
+    }
+}
+
+/// Figure out what ffmpeg should read for a song. Local files are read directly, anything else goes
+/// through yt-dlp for a direct link to the audio, which ffmpeg can seek in.
+async fn resolve_input(song: &Song) -> Result<String, MusicError> {
+    if !song.url.starts_with("http") {
+        return Ok(song.url.clone());
+    }
+
+    // Killed if the lookup takes too long and gets dropped
+    let output = Command::new("yt-dlp")
+        .args(["-q", "-f", "bestaudio/best", "-g", &song.url])
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output().await
+        .map_err(|e| {
+            error!("failed to run yt-dlp: {:?}", e);
+            MusicError::PlaybackFailed
+        })?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.lines().next() {
+        Some(url) if output.status.success() => Ok(url.to_string()),
+        _ => {
+            error!("yt-dlp could not find audio for {}: {}", song.url, String::from_utf8_lossy(&output.stderr));
+            Err(MusicError::PlaybackFailed)
+        },
+    }
+}
+
+#[async_trait]
+impl MusicPlayer for StreamPlayer {
+    async fn init(&self) -> Result<(), MusicError> {
+        Ok(())
+    }
+
+    async fn play(&mut self, song: &Song, id: RequestId) -> Result<(), MusicError> {
+        self.current = Some(song.clone());
+        self.current_id = id;
+        self.start_transcode(Duration::ZERO)
+    }
+
+    async fn stop(&mut self) -> Result<(), MusicError> {
+        self.cancel();
+
+        // Stopping ends the song, same as any other player, which is how skipping moves on
+        if let (Some(_), Some(adapter)) = (&self.current, &self.adapter) {
+            let mut adapter = adapter.clone();
+            let id = self.current_id;
+            tokio::spawn(async move {
+                adapter.song_ended(id).await;
+            });
+        }
+
+        self.current = None;
+        *self.input.lock().unwrap() = None;
+        self.resumed = None;
+        self.position = Duration::ZERO;
+
+        Ok(())
+    }
+
+    async fn pause(&mut self) -> Result<(), MusicError> {
+        if self.current.is_none() {
+            return Err(MusicError::NotPlaying);
+        }
+
+        self.cancel();
+        self.position = self.elapsed();
+        self.resumed = None;
+
+        Ok(())
+    }
+
+    async fn resume(&mut self) -> Result<(), MusicError> {
+        if self.current.is_none() {
+            return Err(MusicError::NotPlaying);
+        }
+
+        match self.resumed {
+            Some(_) => Ok(()),
+            None => self.start_transcode(self.position),
+        }
+    }
+
+    async fn seek(&mut self, position: Duration) -> Result<(), MusicError> {
+        if self.current.is_none() {
+            return Err(MusicError::NotPlaying);
+        }
+
+        // Seeking while paused stays paused
+        match self.resumed {
+            Some(_) => self.start_transcode(position),
+            None => {
+                self.position = position;
+                Ok(())
+            },
+        }
+    }
+
+    async fn set_volume(&mut self, volume: u8) -> Result<(), MusicError> {
+        self.volume = volume;
+
+        // Volume is baked into the transcode, so pick it back up from the same spot
+        match (&self.current, self.resumed) {
+            (Some(_), Some(_)) => self.start_transcode(self.elapsed()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn get_volume(&self) -> u8 {
+        self.volume
+    }
+}
@@ -0,0 +1,120 @@
+/// Shared setup for tests and benches, so each one doesn't have to build its own state and songs
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{
+    broadcast,
+    mpsc,
+};
+
+use model::{
+    MinstrelUserId,
+    Requester,
+    Song,
+    SongRequest,
+};
+
+use crate::{
+    MusicError,
+    MusicState,
+    adapters::MusicAdapter,
+    musicstate::MSCMD,
+    player::{
+        MPCMD,
+        MusicPlayerCommand,
+    },
+    resolver::{
+        FakeResolver,
+        ResolverPool,
+        SongResolver,
+    },
+};
+
+/// A one minute song, with a url made from its title
+pub fn test_song(title: &str) -> Song {
+    Song {
+        title: title.into(),
+        artist: String::new(),
+        url: format!("https://example.com/{}", title),
+        thumbnail: String::new(),
+        duration: 60,
+    }
+}
+
+/// Request for `test_song(title)` by user `userid`
+pub fn test_request(title: &str, userid: MinstrelUserId) -> SongRequest {
+    SongRequest::new(
+        test_song(title),
+        Requester { displayname: format!("user{}", userid), icon: String::new(), id: userid },
+    )
+}
+
+/// Acknowledge every player command without actually playing anything
+pub fn spawn_stub_player() -> mpsc::Sender<MPCMD> {
+    let (tx, mut rx) = mpsc::channel::<MPCMD>(3);
+
+    tokio::spawn(async move {
+        while let Some((rettx, _cmd)) = rx.recv().await {
+            rettx.send(Ok(())).unwrap();
+        }
+    });
+
+    tx
+}
+
+/// Acknowledge every player command, passing each one on to the receiver to check what the player got
+/// told to do. Seeking fails if `can_seek` is false.
+pub fn spawn_recording_player(can_seek: bool) -> (mpsc::Sender<MPCMD>, mpsc::UnboundedReceiver<MusicPlayerCommand>) {
+    let (tx, mut rx) = mpsc::channel::<MPCMD>(10);
+    let (seen_tx, seen) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Some((rettx, cmd)) = rx.recv().await {
+            let ret = match cmd {
+                MusicPlayerCommand::Seek(_) if !can_seek => Err(MusicError::PlaybackFailed),
+                _ => Ok(()),
+            };
+            seen_tx.send(cmd).ok();
+            rettx.send(ret).ok();
+        }
+    });
+
+    (tx, seen)
+}
+
+/// Report the end of whatever is playing, as the player would once it finishes
+pub async fn end_current_song(adapter: &mut MusicAdapter) {
+    let id = adapter.get_webdata().await.current_track.map(|r| r.id).unwrap_or_default();
+    adapter.song_ended(id).await;
+}
+
+/// Pool for tests, lookups still get retried but without waiting in between
+pub fn test_pool(resolver: Arc<dyn SongResolver>) -> ResolverPool {
+    ResolverPool::new(resolver, 4, Duration::from_secs(5), 1, Duration::ZERO)
+}
+
+/// An adapter with no MusicState behind it, anything sent through it shows up on the receiver instead
+pub async fn test_adapter() -> (MusicAdapter, mpsc::Receiver<MSCMD>) {
+    let (tx, rx) = mpsc::channel(10);
+    let (bcast, _) = broadcast::channel(1);
+    let db = db::init_memory_db().await;
+    let resolver = test_pool(Arc::new(FakeResolver::new()));
+
+    (MusicAdapter::new(tx, bcast, db, resolver), rx)
+}
+
+/// A running MusicState with a stub player, an empty in-memory db, and nothing to resolve
+pub async fn test_state() -> MusicAdapter {
+    test_state_with(spawn_stub_player(), Arc::new(FakeResolver::new())).await
+}
+
+/// A running MusicState with an empty in-memory db, using the supplied player and resolver
+pub async fn test_state_with(player: mpsc::Sender<MPCMD>, resolver: Arc<dyn SongResolver>) -> MusicAdapter {
+    let db = db::init_memory_db().await;
+    let mut mstate = MusicState::with_resolver_pool(player, db, test_pool(resolver)).await;
+    let adapter = mstate.get_adapter();
+    tokio::spawn(async move { mstate.run().await });
+
+    adapter
+}
@@ -3,13 +3,17 @@ use bimap::BiHashMap;
 use tokio::sync::Mutex;
 use music::{
     adapters::MusicAdapter,
-    song::fetch_song_from_yt,
     autoplay::AutoplayError,
     MusicError,
 };
+use minstrel_config::AutoplayStrategyKind;
 use model::{
     SongRequest,
-    Requester, MinstrelUserId,
+    MinstrelUserId,
+    error::{
+        ErrorCode,
+        MinstrelError,
+    },
     web::*,
 };
 use std::convert::Infallible;
@@ -48,7 +52,10 @@ pub enum MusicControlCmd {
 use model::web::ReplyStatus;
 
 use crate::user::*;
-use crate::ReplyStatusFuncs;
+use crate::{
+    error_reply,
+    ReplyStatusFuncs,
+};
 
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -71,59 +78,57 @@ pub async fn show_state(
 
 // TODO: Unify these, or implement handlers for each unique endpoint
 async fn handle_body_api(
-    _muid: MinstrelUserId,
+    muid: MinstrelUserId,
     mut mstate: MusicAdapter,
     func: String,
     body: SongBody,
 ) -> Result<impl warp::Reply, Infallible> {
     debug!("body = '{:?}'", &body);
 
-    let requester = Requester {
-        displayname: "webuser".to_string(),
-        icon: "".to_string(),
-        id: 0,
+    let requester = match mstate.db.get_requester(muid).await {
+        Ok(r) => r,
+        Err(_) =>
+            return Ok(error_reply(MinstrelError::new(ErrorCode::DbError, "could not look up requesting user")))
     };
 
-    let song = match fetch_song_from_yt(body.song.clone()) {
+    let song = match mstate.fetch_song(body.song.clone()).await {
         Ok(s) => SongRequest::new(s, requester),
         Err(e) =>
-            return Ok(warp::reply::json(&ReplyStatus::new_nd(StatusCode::BAD_REQUEST, format!("error fetching song: {e:?}"))).into_response())
+            return Ok(error_reply(e))
     };
 
     let ret = match func.as_str() {
         "play" => mstate.play(song).await,
         "enqueue" => mstate.enqueue(song).await,
         "enqueueandplay" => mstate.enqueue_and_play(song).await,
-        _ => return Ok(warp::reply::json(&ReplyStatus::new_nd(StatusCode::BAD_REQUEST, "no such function")).into_response())
+        "playnext" => mstate.play_next(song).await,
+        _ => return Ok(error_reply(MinstrelError::new(ErrorCode::BadRequest, "no such function")))
     };
 
     match ret {
         Ok(o) => Ok(warp::reply::json(&ReplyStatus::new_nd(StatusCode::OK, o.to_string())).into_response()),
         Err(e) => {
             debug!("error from musicstatus: {:?}", e);
-
-            let resp = warp::reply::json(&ReplyStatus::new_nd(StatusCode::BAD_REQUEST, format!("{e:?}")));
-            let mut resp = resp.into_response();
-            *resp.status_mut() = StatusCode::BAD_REQUEST;
-
-            Ok(resp)
+            Ok(error_reply(e))
         }
     }
 }
 
 async fn handle_simple_api(
-    _muid: MinstrelUserId,
+    muid: MinstrelUserId,
     mut mstate: MusicAdapter,
     func: String,
 ) -> Result<impl warp::Reply, Rejection> {
 
     debug!("called simple, func = '{}'", &func);
     let ret = match func.as_str() {
-        "skip" => mstate.skip().await,
+        "skip" => mstate.vote_skip(muid).await,
         "stop" => mstate.stop().await,
         "start" => mstate.start().await,
         "clearqueue" => mstate.clear_queue().await,
         "previous" => mstate.previous().await,
+        "pause" => mstate.pause().await,
+        "resume" => mstate.resume().await,
         _ => return Err(warp::reject::reject())
     };
 
@@ -131,12 +136,7 @@ async fn handle_simple_api(
         Ok(o) => Ok(warp::reply::json(&ReplyStatus::new_nd(StatusCode::OK, o.to_string())).into_response()),
         Err(e) => {
             debug!("error from musicstatus: {:?}", e);
-
-            let resp = warp::reply::json(&ReplyStatus::new_nd(StatusCode::BAD_REQUEST, format!("{e:?}")));
-            let mut resp = resp.into_response();
-            *resp.status_mut() = StatusCode::BAD_REQUEST;
-
-            Ok(resp)
+            Ok(error_reply(e))
         }
     }
 }
@@ -146,9 +146,9 @@ async fn handle_ap_bump(
     mut mstate: MusicAdapter,
     body: ApBumpRequest,
 ) -> Result<impl warp::Reply, Rejection> {
-    match mstate.autoplay.bump_userplaylist(&muid, body.index).await {
-        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok())),
-        Err(e) => Ok(warp::reply::json(&ReplyStatus::new_nd(StatusCode::BAD_REQUEST, format!("Error: {e:?}"))))
+    match mstate.autoplay.bump_userplaylist(&muid, body.id).await {
+        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok()).into_response()),
+        Err(e) => Ok(error_reply(e))
     }
 }
 
@@ -163,7 +163,7 @@ async fn handle_ap_toggle(
         false => mstate.autoplay.disable().await,
     };
     if let Err(e) = ret {
-        return Ok(warp::reply::json(&ReplyStatus::new_nd(StatusCode::INTERNAL_SERVER_ERROR, format!("error toggling autoplay: {e:?}"))))
+        return Ok(error_reply(e))
     }
 
     // TODO: consider reporting errors to the user here
@@ -182,7 +182,137 @@ async fn handle_ap_toggle(
         }
     }
 
-    Ok(warp::reply::json(&ReplyStatus::ok()))
+    Ok(warp::reply::json(&ReplyStatus::ok()).into_response())
+}
+
+async fn handle_queue_remove(
+    muid: MinstrelUserId,
+    mut mstate: MusicAdapter,
+    body: QueueRemoveRequest,
+) -> Result<impl warp::Reply, Rejection> {
+    match mstate.remove(body.id, muid).await {
+        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok()).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
+}
+
+async fn handle_queue_move(
+    muid: MinstrelUserId,
+    mut mstate: MusicAdapter,
+    body: QueueMoveRequest,
+) -> Result<impl warp::Reply, Rejection> {
+    match mstate.move_song(body.id, body.target, muid).await {
+        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok()).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
+}
+
+async fn handle_queue_insert(
+    muid: MinstrelUserId,
+    mut mstate: MusicAdapter,
+    body: QueueInsertRequest,
+) -> Result<impl warp::Reply, Rejection> {
+    let requester = match mstate.db.get_requester(muid).await {
+        Ok(r) => r,
+        Err(_) => return Ok(error_reply(MinstrelError::new(ErrorCode::DbError, "could not look up requesting user")))
+    };
+
+    let song = match mstate.fetch_song(body.song).await {
+        Ok(s) => SongRequest::new(s, requester),
+        Err(e) => return Ok(error_reply(e))
+    };
+
+    match mstate.insert_at(body.index, song).await {
+        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok()).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
+}
+
+async fn handle_seek(
+    _muid: MinstrelUserId,
+    mut mstate: MusicAdapter,
+    body: SeekRequest,
+) -> Result<impl warp::Reply, Rejection> {
+    match mstate.seek(body.position).await {
+        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok()).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
+}
+
+async fn handle_volume(
+    _muid: MinstrelUserId,
+    mut mstate: MusicAdapter,
+    body: VolumeRequest,
+) -> Result<impl warp::Reply, Rejection> {
+    match mstate.set_volume(body.volume).await {
+        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok()).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
+}
+
+async fn handle_ap_scores(
+    _muid: MinstrelUserId,
+    mut mstate: MusicAdapter,
+) -> Result<impl warp::Reply, Rejection> {
+    match mstate.autoplay.get_scores().await {
+        Ok(s) => Ok(warp::reply::json(&ReplyStatus::ok_data(ReplyData::AutoplayScores(s))).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
+}
+
+async fn handle_like(
+    muid: MinstrelUserId,
+    mstate: MusicAdapter,
+    body: LikeRequest,
+) -> Result<impl warp::Reply, Rejection> {
+    match mstate.like_song(muid, body.id, body.liked).await {
+        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok()).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
+}
+
+async fn handle_likes(
+    muid: MinstrelUserId,
+    mstate: MusicAdapter,
+) -> Result<impl warp::Reply, Rejection> {
+    match mstate.get_liked_songs(muid).await {
+        Ok(s) => Ok(warp::reply::json(&ReplyStatus::ok_data(ReplyData::LikedSongs(s))).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
+}
+
+async fn handle_ap_reset_scores(
+    muid: MinstrelUserId,
+    mut mstate: MusicAdapter,
+) -> Result<impl warp::Reply, Rejection> {
+    if !mstate.user.is_admin(muid) {
+        return Ok(error_reply(ErrorCode::NotAdmin))
+    }
+
+    match mstate.autoplay.reset_scores().await {
+        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok()).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
+}
+
+async fn handle_ap_strategy(
+    muid: MinstrelUserId,
+    mut mstate: MusicAdapter,
+    body: ApStrategyRequest,
+) -> Result<impl warp::Reply, Rejection> {
+    if !mstate.user.is_admin(muid) {
+        return Ok(error_reply(ErrorCode::NotAdmin))
+    }
+
+    let kind = match body.strategy.parse::<AutoplayStrategyKind>() {
+        Ok(k) => k,
+        Err(_) => return Ok(error_reply(MinstrelError::new(ErrorCode::UnknownStrategy, format!("Unknown strategy: {}", body.strategy)))),
+    };
+
+    match mstate.autoplay.set_strategy(kind).await {
+        Ok(_) => Ok(warp::reply::json(&ReplyStatus::ok()).into_response()),
+        Err(e) => Ok(error_reply(e))
+    }
 }
 
 
@@ -274,6 +404,69 @@ pub fn get_api_filter(mstate: MusicAdapter) -> impl Filter<Extract = (impl warp:
         .and(warp::body::json())
         .and_then(handle_ap_toggle);
 
+    let queue_remove = api_base.clone()
+        .and(warp::path("queue"))
+        .and(warp::path("remove"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(handle_queue_remove);
+
+    let queue_move = api_base.clone()
+        .and(warp::path("queue"))
+        .and(warp::path("move"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(handle_queue_move);
+
+    let queue_insert = api_base.clone()
+        .and(warp::path("queue"))
+        .and(warp::path("insert"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(handle_queue_insert);
+
+    let seek = api_base.clone()
+        .and(warp::path("seek"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(handle_seek);
+
+    let volume = api_base.clone()
+        .and(warp::path("volume"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(handle_volume);
+
+    let autoplay_scores = api_base.clone()
+        .and(warp::path("autoplay"))
+        .and(warp::path("scores"))
+        .and(warp::path::end())
+        .and_then(handle_ap_scores);
+
+    let autoplay_reset_scores = api_base.clone()
+        .and(warp::path("autoplay"))
+        .and(warp::path("resetscores"))
+        .and(warp::path::end())
+        .and_then(handle_ap_reset_scores);
+
+    let autoplay_strategy = api_base.clone()
+        .and(warp::path("autoplay"))
+        .and(warp::path("strategy"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(handle_ap_strategy);
+
+    let like = api_base.clone()
+        .and(warp::path("like"))
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .and_then(handle_like);
+
+    let likes = api_base.clone()
+        .and(warp::path("likes"))
+        .and(warp::path::end())
+        .and_then(handle_likes);
+
     // TODO: seriously clean up this filter building, this is getting out of hand
     login
         .or(logout)
@@ -282,6 +475,16 @@ pub fn get_api_filter(mstate: MusicAdapter) -> impl Filter<Extract = (impl warp:
         .or(userinfo)
         .or(autoplay_ap_bump)
         .or(autoplay_toggle)
+        .or(autoplay_scores)
+        .or(autoplay_reset_scores)
+        .or(autoplay_strategy)
+        .or(queue_remove)
+        .or(queue_move)
+        .or(queue_insert)
+        .or(seek)
+        .or(volume)
+        .or(like)
+        .or(likes)
         .or(api_no_body)
         .or(api_body)
 }
\ No newline at end of file
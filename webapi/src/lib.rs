@@ -4,22 +4,30 @@ pub mod web;
 pub mod user;
 
 use warp::http::StatusCode;
-use model::web::ReplyData;
+use warp::Reply;
+use model::web::{
+    ReplyData,
+    ReplyStatus,
+};
+use model::error::MinstrelError;
+
 pub trait ReplyStatusFuncs {
     fn new<S: Into<String>>(status: StatusCode, error: S, data: Option<ReplyData>) -> Self;
     fn ok() -> Self;
     fn ok_data(data: ReplyData) -> Self;
     fn new_nd<S: Into<String>>(status: StatusCode, error: S) -> Self;
     fn uerr<S: Into<String>>(error: S) -> Self;
+    fn err<E: Into<MinstrelError>>(error: E) -> Self;
 }
 
-impl ReplyStatusFuncs for model::web::ReplyStatus {
+impl ReplyStatusFuncs for ReplyStatus {
 
 
     fn new<S: Into<String>>(status: StatusCode, error: S, data: Option<ReplyData>) -> Self {
         Self {
             status: status.try_into().unwrap(),
             error: error.into(),
+            code: None,
             data,
         }
     }
@@ -39,4 +47,23 @@ impl ReplyStatusFuncs for model::web::ReplyStatus {
     fn uerr<S: Into<String>>(error: S) -> Self {
         Self::new(StatusCode::INTERNAL_SERVER_ERROR, error, None)
     }
+
+    fn err<E: Into<MinstrelError>>(error: E) -> Self {
+        let error = error.into();
+
+        Self {
+            status: error.code.http_status(),
+            error: error.message,
+            code: Some(error.code),
+            data: None,
+        }
+    }
+}
+
+/// Reply with an error, with the HTTP status matching its code
+pub fn error_reply<E: Into<MinstrelError>>(error: E) -> warp::reply::Response {
+    let reply = ReplyStatus::err(error);
+    let status = StatusCode::from_u16(reply.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    warp::reply::with_status(warp::reply::json(&reply), status).into_response()
 }
\ No newline at end of file
@@ -11,7 +11,12 @@ use model::{
     web::{
         LoginRequest,
         RegisterRequest, ReplyStatus, LinkRequest, ReplyData,
-    }, MinstrelUserId, UserMgmtError,
+    },
+    error::{
+        ErrorCode,
+        MinstrelError,
+    },
+    MinstrelUserId, UserMgmtError,
 };
 use std::convert::Infallible;
 
@@ -34,6 +39,12 @@ fn gen_auth_token() -> String {
 
 use crate::ReplyStatusFuncs;
 
+use log::*;
+
+fn not_logged_in() -> MinstrelError {
+    MinstrelError::new(ErrorCode::NotLoggedIn, "User not logged in, or invalid session ID")
+}
+
 #[cfg(not(debug_assertions))]
 // Require HTTPS for cookie support in-release mode, permit it in debug.
 // TODO: figure out a way to require an https reverse proxy, fail login otherwise
@@ -49,7 +60,7 @@ pub async fn handle_login(
 ) -> Result<impl warp::Reply, Infallible> {
     let auth = mstate.user.user_authenticate(&body.username, body.password).await;
 
-    let (status, error, userinfo, auth_token) = match auth {
+    let (reply, auth_token) = match auth {
         Ok(Some(id)) => {
             let req = mstate.db.get_requester(id).await.unwrap();
             let token = gen_auth_token();
@@ -58,16 +69,12 @@ pub async fn handle_login(
                 tokens.lock().await.insert(id, token.clone());
             }
 
-            (StatusCode::OK, "Login Successful".into(), Some(req), Some(token))
+            (ReplyStatus::new(StatusCode::OK, "Login Successful", Some(ReplyData::UserInfo(req))), Some(token))
         },
-        Ok(None) => (StatusCode::UNAUTHORIZED, "Incorrect username or password".into(), None, None),
-        Err(model::UserMgmtError::DbError) => (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong with the database".into(), None, None),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Something really went wrong internally: {:?}", e), None, None),
+        Ok(None) => (ReplyStatus::err(ErrorCode::BadLogin), None),
+        Err(e) => (ReplyStatus::err(e), None),
     };
 
-    let userinfo = userinfo.map(ReplyData::UserInfo);
-    let reply = ReplyStatus::new(status, error, userinfo);
-
     if let Some(token) = auth_token {
         Ok(warp::http::Response::builder()
             // TODO: probably set an expiry for these
@@ -92,7 +99,7 @@ pub async fn handle_register(
     let auth = usermgmt::AuthType::UserAuth(body.username, body.password);
     let info = usermgmt::UserInfo { displayname: body.displayname, icon: body.icon };
 
-    let (status, error, userinfo , auth_token) = {
+    let (reply, auth_token) = {
         let resp = mstate.user.user_create(auth, info).await;
         match resp {
             Ok(id) => {
@@ -104,17 +111,14 @@ pub async fn handle_register(
                     tokens.lock().await.insert(id, token.clone());
                 }
 
-                (StatusCode::OK, "User successfully created.".into(), Some(req), Some(token))
+                (ReplyStatus::new(StatusCode::OK, "User successfully created.", Some(ReplyData::UserInfo(req))), Some(token))
             },
-            Err(UserMgmtError::UserExists) => (StatusCode::UNAUTHORIZED, "Username has already been taken.".into(), None, None),
-            Err(UserMgmtError::DbError) => (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong with the database".into(), None, None),
-            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Something went really wrong internally: {e:?}"), None, None),
+            Err(UserMgmtError::UserExists) =>
+                (ReplyStatus::err(MinstrelError::new(ErrorCode::UserExists, "Username has already been taken.")), None),
+            Err(e) => (ReplyStatus::err(e), None),
         }
     };
 
-    let userinfo = userinfo.map(ReplyData::UserInfo);
-    let reply = ReplyStatus::new(status, error, userinfo);
-
     if let Some(token) = auth_token {
         Ok(warp::http::Response::builder()
             // TODO: probably set an expiry for these
@@ -139,7 +143,7 @@ pub async fn handle_link(
     let auth = usermgmt::AuthType::UserAuth(body.username, body.password);
 
     let resp = mstate.user.user_link(link, auth).await;
-    let (status, error, userinfo , auth_token) = {
+    let (reply, auth_token) = {
             match resp {
             Ok(id) => {
                 let req = mstate.db.get_requester(id).await.unwrap();
@@ -149,18 +153,14 @@ pub async fn handle_link(
                     tokens.lock().await.insert(id, token.clone());
                 }
 
-                (StatusCode::OK, "User linked successfully.".into(), Some(req), Some(token))
+                (ReplyStatus::new(StatusCode::OK, "User linked successfully.", Some(ReplyData::UserInfo(req))), Some(token))
             },
-            Err(UserMgmtError::InvalidLink) => (StatusCode::UNAUTHORIZED, "Invalid or expired link, please regenerate and try again.".into(), None, None),
-            Err(UserMgmtError::DbError) => (StatusCode::INTERNAL_SERVER_ERROR, "Something went wrong with the database".into(), None, None),
-            Err(UserMgmtError::UserExists) => (StatusCode::UNAUTHORIZED, "You appear to have an account already, please recreate this link and reuse with a different auth method (e.g. discord).".into(), None, None),
-            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Something really went wrong internally: {:?}", e), None, None),
+            Err(UserMgmtError::UserExists) => (ReplyStatus::err(MinstrelError::new(ErrorCode::UserExists,
+                "You appear to have an account already, please recreate this link and reuse with a different auth method (e.g. discord).")), None),
+            Err(e) => (ReplyStatus::err(e), None),
         }
     };
 
-    let userinfo = userinfo.map(ReplyData::UserInfo);
-    let reply = ReplyStatus::new(status, error, userinfo);
-
     if let Some(token) = auth_token {
         Ok(warp::http::Response::builder()
             // TODO: probably set an expiry for these
@@ -191,12 +191,12 @@ pub async fn handle_logout(
             Ok(warp::http::Response::builder()
                 .header("Set-Cookie", format!(r#"auth_token=""; {COOKIEOPTS}"#))
                 .status(StatusCode::UNAUTHORIZED)
-                .body(serde_json::to_string(&ReplyStatus::new_nd(StatusCode::UNAUTHORIZED, "User not logged in, or invalid session ID")).unwrap()).unwrap())
+                .body(serde_json::to_string(&ReplyStatus::err(not_logged_in())).unwrap()).unwrap())
         }
     } else {
         Ok(warp::http::Response::builder()
             .status(StatusCode::UNAUTHORIZED)
-            .body(serde_json::to_string(&ReplyStatus::new_nd(StatusCode::UNAUTHORIZED, "User not logged in, or invalid session ID")).unwrap()).unwrap())
+            .body(serde_json::to_string(&ReplyStatus::err(not_logged_in())).unwrap()).unwrap())
     }
 
 }
@@ -211,7 +211,7 @@ pub async fn handle_create_link(
     } else {
         return Ok(warp::http::Response::builder()
             .status(StatusCode::UNAUTHORIZED)
-            .body(serde_json::to_string(&ReplyStatus::new_nd(StatusCode::UNAUTHORIZED, "User not logged in, or invalid session ID")).unwrap()).unwrap())
+            .body(serde_json::to_string(&ReplyStatus::err(not_logged_in())).unwrap()).unwrap())
     };
 
     let tokens = tokens.lock().await;
@@ -220,19 +220,16 @@ pub async fn handle_create_link(
     } else {
         return Ok(warp::http::Response::builder()
             .status(StatusCode::UNAUTHORIZED)
-            .body(serde_json::to_string(&ReplyStatus::new_nd(StatusCode::UNAUTHORIZED, "User not logged in, or invalid session ID")).unwrap()).unwrap())
+            .body(serde_json::to_string(&ReplyStatus::err(not_logged_in())).unwrap()).unwrap())
     };
     drop(tokens); // No longer need to hold lock here
 
     let resp = mstate.user.create_link(user_id).await;
-    let (status, error, link) = match resp {
-        Ok(link) => (StatusCode::OK, "Link successfully created.".into(), Some(link)),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Something went really wrong internally: {e:?}"), None)
+    let reply = match resp {
+        Ok(link) => ReplyStatus::new(StatusCode::OK, "Link successfully created.", Some(ReplyData::LinkInfo(link))),
+        Err(e) => ReplyStatus::err(e),
     };
 
-    let link = link.map(ReplyData::LinkInfo);
-    let reply = ReplyStatus::new(status, error, link);
-
     Ok(warp::http::Response::builder()
     // TODO: probably set an expiry for these
         .status(reply.status)
@@ -244,22 +241,22 @@ pub async fn handle_userinfo(
     mstate: MusicAdapter,
 ) -> Result<impl warp::Reply, Infallible> {
 
-    let (status, userinfo, error) = {
+    let reply = {
         let req = mstate.db.get_requester(muid).await;
         match req {
-            Ok(req) => (StatusCode::OK, Some(req), "UserInfo Retrieved".into()),
-            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, None, format!("Something went really wrong internally: {e:?}")),
+            Ok(req) => ReplyStatus::new(StatusCode::OK, "UserInfo Retrieved", Some(ReplyData::UserInfo(req))),
+            Err(e) => {
+                error!("failed to look up user {}: {:?}", muid, e);
+                ReplyStatus::err(ErrorCode::DbError)
+            },
         }
     };
 
-    let userinfo = userinfo.map(ReplyData::UserInfo);
-    let reply = ReplyStatus::new(status, error, userinfo);
-
     let resp = warp::http::Response::builder()
         .status(reply.status);
 
     // Clear bogus cookie if it wasn't accepted
-    let resp = if !status.is_success() {
+    let resp = if reply.code.is_some() {
         resp.header("Set-Cookie", format!(r#"auth_token=""; {COOKIEOPTS}"#))
     } else { resp };
 
@@ -1,6 +1,8 @@
 use model::MinstrelBroadcast;
+use model::web::WS_RESYNC;
 use warp::Filter;
 
+use std::convert::Infallible;
 use std::sync::Arc;
 use tokio::sync::{
     Mutex,
@@ -11,13 +13,27 @@ use log::*;
 
 use music::{
     adapters::MusicAdapter,
+    streamplayer::AudioStream,
 };
 
 use futures_util::{
+    stream::SplitSink,
     StreamExt,
     SinkExt
 };
+use warp::ws::{
+    Message,
+    WebSocket,
+};
+use tokio_stream::wrappers::BroadcastStream;
+
 
+/// Send the whole current state, for a client that is just connecting or fell behind on events
+async fn send_state(ws_tx: &mut SplitSink<WebSocket, Message>, mstate: &MusicAdapter) -> Result<(), warp::Error> {
+    let msg = MinstrelBroadcast::MusicState(mstate.get_webdata().await);
+    let msg = serde_json::to_string(&msg).unwrap();
+    ws_tx.send(Message::text(msg)).await
+}
 
 async fn ws_connect(ws: warp::ws::Ws, mstate: Arc<Mutex<MusicAdapter>>) -> impl warp::reply::Reply {
     ws.on_upgrade(|websocket| async move {
@@ -25,22 +41,18 @@ async fn ws_connect(ws: warp::ws::Ws, mstate: Arc<Mutex<MusicAdapter>>) -> impl
 
         let (mut ws_tx, mut ws_rx) = websocket.split();
 
+        // Subscribe first, so nothing is missed between the initial state and the first event.
+        //  Clients skip over any events the state already includes.
         let mut bc_rx = mstate.subscribe();
 
         tokio::task::spawn(async move {
             // TODO: figure out a nicer way to assign these task or thread IDs, would be nice for debug
             debug!("spawning ws thread");
 
-            {
-                debug!("sending initial state");
-                let msg = mstate.get_webdata().await;
-                let msg = MinstrelBroadcast::MusicState(msg);
-                let msg = serde_json::to_string(&msg).unwrap();
-                let msg = warp::ws::Message::text(msg);
-                if let Err(resp) = ws_tx.send(msg).await {
-                    error!("websocket appears to have disconnected before it could even receive the initial state {}", resp);
-                    return;
-                };
+            debug!("sending initial state");
+            if let Err(resp) = send_state(&mut ws_tx, &mstate).await {
+                error!("websocket appears to have disconnected before it could even receive the initial state {}", resp);
+                return;
             }
 
             loop {
@@ -50,18 +62,32 @@ async fn ws_connect(ws: warp::ws::Ws, mstate: Arc<Mutex<MusicAdapter>>) -> impl
                     Ok(msg) => {
                         trace!("broadcast received, sending to websocket");
                         let msg = serde_json::to_string(&msg).unwrap();
-                        if let Err(resp) = ws_tx.send(warp::ws::Message::text(msg)).await {
+                        if let Err(resp) = ws_tx.send(Message::text(msg)).await {
+                            debug!("websocket appears to have disconnected, dropping? {}", resp);
+                            break;
+                        }
+                    },
+                    // Events were dropped, so the client can't keep up from events alone anymore
+                    Err(RecvError::Lagged(c)) => {
+                        warn!("websocket lagged behind by {c:?} broadcasts, resending state");
+                        if let Err(resp) = send_state(&mut ws_tx, &mstate).await {
                             debug!("websocket appears to have disconnected, dropping? {}", resp);
                             break;
                         }
                     },
-                    Err(RecvError::Lagged(c)) => error!("Lagging behind: {c:?}"),
                     Err(RecvError::Closed) => {
                         error!("broadcast appears closed, exiting loop");
                         break;
                     }
                 }},
                 recv = ws_rx.next() => { match recv {
+                    Some(Ok(msg)) if msg.to_str() == Ok(WS_RESYNC) => {
+                        debug!("client missed an event, resending state");
+                        if let Err(resp) = send_state(&mut ws_tx, &mstate).await {
+                            debug!("websocket appears to have disconnected, dropping? {}", resp);
+                            break;
+                        }
+                    },
                     Some(msg) => debug!("message from ws = {msg:?}"),
                     None => { debug!("client appears to have disconnected, closing ws thread"); break; }
                 }}
@@ -71,7 +97,26 @@ async fn ws_connect(ws: warp::ws::Ws, mstate: Arc<Mutex<MusicAdapter>>) -> impl
     })
 }
 
-pub fn get_web_filter(mstate: MusicAdapter) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+/// Hook a listener up to the live audio stream, starting from wherever it currently is
+async fn stream_connect(stream: Option<AudioStream>) -> Result<impl warp::Reply, warp::Rejection> {
+    let stream = match stream {
+        Some(s) => s,
+        None => return Err(warp::reject::not_found()),
+    };
+
+    debug!("new stream listener");
+
+    // A listener that fell behind just skips ahead and carries on
+    let body = BroadcastStream::new(stream.subscribe())
+        .filter_map(|chunk| async move { chunk.ok().map(Ok::<_, Infallible>) });
+
+    Ok(warp::http::Response::builder()
+        .header("Content-Type", AudioStream::CONTENT_TYPE)
+        .header("Cache-Control", "no-cache, no-store")
+        .body(warp::hyper::Body::wrap_stream(body)))
+}
+
+pub fn get_web_filter(mstate: MusicAdapter, stream: Option<AudioStream>) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
     let mstate_mutex = Arc::new(Mutex::new(mstate.clone()));
     let mstate_filter = warp::any().map(move || { mstate_mutex.clone() });
 
@@ -82,6 +127,12 @@ pub fn get_web_filter(mstate: MusicAdapter) -> impl Filter<Extract = (impl warp:
         .and(mstate_filter)
         .then(ws_connect);
 
+    let stream = warp::path("stream")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::any().map(move || stream.clone()))
+        .and_then(stream_connect);
+
     let files = crate::embed::get_embedded_file_filter();
 
     let root_redir = warp::get()
@@ -90,5 +141,5 @@ pub fn get_web_filter(mstate: MusicAdapter) -> impl Filter<Extract = (impl warp:
             warp::redirect::redirect(warp::hyper::Uri::from_static("/index.html"))
         });
 
-    api.or(ws).or(root_redir).or(files)
+    api.or(ws).or(stream).or(root_redir).or(files)
 }
\ No newline at end of file
@@ -1,6 +1,60 @@
+use gloo_net::http::Request;
+use model::{
+    error::ErrorCode,
+    web::ReplyStatus,
+};
+use serde::Serialize;
+use yew::UseReducerDispatcher;
+use yew_toast::{
+    toast_error,
+    toast_warning,
+    ToastAction,
+    ToastList,
+};
+
 pub fn duration_text(dur: i64) -> String {
     let min = dur / 60;
     let secs = dur % 60;
 
     format!("{}:{:02}", min, secs)
-}
\ No newline at end of file
+}
+
+/// Toast for an error from the backend. Anything the user can fix (e.g. nothing playing, a bad link) is
+/// only a warning, anything broken on the backend is an error.
+pub fn error_toast(code: Option<ErrorCode>, message: String) -> ToastAction {
+    match code {
+        Some(ErrorCode::NotLoggedIn) => toast_warning!("Log in first!".into()),
+        Some(code) if code.is_user_error() => toast_warning!(message),
+        _ => toast_error!(message),
+    }
+}
+
+/// POST `body` to `/api/{path}`, toasting whatever went wrong if the backend didn't accept it
+pub async fn post_api<T: Serialize>(path: &str, body: &T, tdis: &UseReducerDispatcher<ToastList>) -> Result<(), ()> {
+    let resp = Request::post(format!("/api/{}", path).as_str())
+        .json(body).unwrap()
+        .send().await;
+
+    let resp = match resp {
+        Ok(resp) => resp,
+        Err(e) => {
+            log::error!("failed to reach backend: {:?}", e);
+            tdis.dispatch(toast_error!("Could not reach the server".into()));
+            return Err(())
+        },
+    };
+
+    if !resp.ok() {
+        let resp = resp.json::<ReplyStatus>().await;
+        if let Ok(msg) = resp {
+            tdis.dispatch(error_toast(msg.code, msg.error));
+        } else {
+            log::error!("bad response from backend: {:?}", resp);
+            tdis.dispatch(toast_error!("Bad data from API, check console".into()));
+        }
+
+        return Err(())
+    }
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use yew::{
+    prelude::*,
+    function_component,
+    html,
+};
+
+use yew_toast::*;
+
+/// Toggle for listening along in the browser, if the backend has the web stream running
+#[function_component(ListenButton)]
+pub fn listenbutton() -> Html {
+    let toast = use_context::<ToastContext>().unwrap();
+    let listening = use_state(|| false);
+
+    let onclick = {
+        let listening = listening.clone();
+        Callback::from(move |_: MouseEvent| listening.set(!*listening))
+    };
+
+    // Most likely the stream isn't enabled, or the connection dropped
+    let onerror = {
+        let listening = listening.clone();
+        let tdis = toast.dispatcher();
+        Callback::from(move |_: Event| {
+            tdis.dispatch(toast_error!("Could not connect to the audio stream".into()));
+            listening.set(false);
+        })
+    };
+
+    html! {
+        <div class="columns is-centered is-mobile is-vcentered">
+            <div class="column is-narrow is-flex is-clickable" {onclick}
+                title={ if *listening { "Stop listening" } else { "Listen in the browser" } }>
+                if *listening {
+                    <yew_feather::Headphones />
+                    <audio src="/stream" autoplay=true {onerror} />
+                } else {
+                    <span class="is-flex" style="opacity: 0.5;">
+                        <yew_feather::Headphones />
+                    </span>
+                }
+            </div>
+        </div>
+    }
+}
@@ -20,6 +20,7 @@ use yew_toast::{
 };
 
 use crate::components::requester::*;
+use crate::components::error_toast;
 
 async fn login_update_usercontext(
     resp: &Response,
@@ -273,7 +274,7 @@ pub fn link_form(props: &LinkFormProps) -> Html {
                 match resp.json::<ReplyStatus>().await {
                     Ok(ui) => {
                         log::error!("Error returned from server: {:?}", ui);
-                        toastcontext.dispatch(toast_error!(format!("Error: {:?}", ui.error)));
+                        toastcontext.dispatch(error_toast(ui.code, format!("Error: {}", ui.error)));
                     },
                     Err(e) => {
                         log::error!("Error {e:?}, server sent back garbage: {resp:?}");
@@ -1,4 +1,5 @@
 mod helpers;
+pub use helpers::error_toast;
 
 mod nowplaying;
 pub use nowplaying::*;
@@ -22,4 +23,9 @@ mod login;
 pub use login::*;
 
 mod isloggedin;
-pub use isloggedin::*;
\ No newline at end of file
+pub use isloggedin::*;
+mod volume;
+pub use volume::*;
+
+mod listen;
+pub use listen::*;
@@ -4,28 +4,37 @@ use yew::{
     html,
 };
 use model::{
-    Song, SongRequest,
+    Song, SongRequest, SeekPosition,
+    web::SeekRequest,
 };
 
 use gloo_timers::callback::Interval;
+use web_sys::HtmlElement;
+use yew_toast::ToastContext;
 
-use crate::components::helpers::duration_text;
+use crate::components::helpers::{
+    duration_text,
+    post_api,
+};
 use crate::components::requester::*;
 
 
 pub enum NpMsg {
     IncrementNowplaying,
+    Seek(i64),
 }
 
 pub struct NowPlayingProgress {
     pub time: i64,
     pub interval: Option<Interval>,
+    toast: ToastContext,
 }
 
 #[derive(Properties, PartialEq)]
 pub struct NowPlayingProgressProps {
     pub song: Song,
     pub initial: u64,
+    pub paused: bool,
 }
 
 impl Component for NowPlayingProgress {
@@ -33,14 +42,10 @@ impl Component for NowPlayingProgress {
     type Properties = NowPlayingProgressProps;
 
     fn create(ctx: &Context<Self>) -> Self {
-        let link = ctx.link().clone();
-        let interval = Interval::new(1000, move || {
-            link.send_message(Self::Message::IncrementNowplaying);
-        });
-
         Self {
             time: ctx.props().initial as i64,
-            interval: Some(interval),
+            interval: Self::start_interval(ctx),
+            toast: ctx.link().context::<ToastContext>(Callback::noop()).unwrap().0,
         }
     }
 
@@ -52,22 +57,30 @@ impl Component for NowPlayingProgress {
                     self.interval.take().unwrap().cancel();
                 }
 
+                true
+            },
+            Self::Message::Seek(secs) => {
+                // Jump ahead locally, the broadcast from the backend will correct this if it was off
+                self.time = secs;
+
+                let tdis = self.toast.dispatcher();
+                wasm_bindgen_futures::spawn_local(async move {
+                    let body = SeekRequest { position: SeekPosition::Absolute(secs as u64) };
+                    let _ = post_api("seek", &body, &tdis).await;
+                });
+
                 true
             },
         }
     }
 
     fn changed(&mut self, ctx: &Context<Self>, _props: &NowPlayingProgressProps) -> bool {
-        let link = ctx.link().clone();
-
         if let Some(interval) = self.interval.take() {
             interval.cancel();
         }
 
         self.time = ctx.props().initial as i64;
-        self.interval = Some(Interval::new(1000, move || {
-            link.send_message(Self::Message::IncrementNowplaying);
-        }));
+        self.interval = Self::start_interval(ctx);
 
         true
     }
@@ -80,20 +93,48 @@ impl Component for NowPlayingProgress {
 
     fn view(&self, ctx: &Context<Self>) -> Html {
         let song = &ctx.props().song;
+
+        // Clicking somewhere on the progress bar seeks to that point in the song
+        let duration = song.duration;
+        let onclick = ctx.link().callback(move |e: MouseEvent| {
+            let bar: HtmlElement = e.target_unchecked_into();
+            let frac = e.offset_x() as f64 / bar.client_width().max(1) as f64;
+            NpMsg::Seek((frac.clamp(0.0, 1.0) * duration as f64) as i64)
+        });
+
         html! {
             <div class="is-flex is-flex-direction-column">
                 <div class="py-0"><span>{ format!("{} / {}", duration_text(self.time), duration_text(song.duration)) }</span></div>
-                <div class="pt-1 pb-0"><progress class="progress is-primary" value={self.time.to_string()} max={song.duration.to_string()}/></div>
+                <div class="pt-1 pb-0"><progress class="progress is-primary is-clickable" {onclick} title="Seek" value={self.time.to_string()} max={song.duration.to_string()}/></div>
             </div>
         }
     }
 }
 
+impl NowPlayingProgress {
+    // Progress only ticks along while the song is actually playing
+    fn start_interval(ctx: &Context<Self>) -> Option<Interval> {
+        if ctx.props().paused {
+            return None;
+        }
+
+        let link = ctx.link().clone();
+        Some(Interval::new(1000, move || {
+            link.send_message(NpMsg::IncrementNowplaying);
+        }))
+    }
+}
+
 
 #[derive(Properties, PartialEq)]
 pub struct NowPlayingProps {
     pub song: SongRequest,
     pub progress: u64,
+    pub paused: bool,
+    #[prop_or_default]
+    pub skip_votes: usize,
+    #[prop_or_default]
+    pub skip_votes_needed: usize,
 }
 
 // TODO: This gets called on every broadcast since song progress in the broadcast will push an update
@@ -133,8 +174,13 @@ pub fn nowplaying(props: &NowPlayingProps) -> Html {
             </div>
             // Progress bar
             <div class="p-3">
-                <NowPlayingProgress song={song.clone()} initial={props.progress}/>
+                <NowPlayingProgress song={song.clone()} initial={props.progress} paused={props.paused}/>
             </div>
+            if props.skip_votes > 0 {
+                <div class="px-3 has-text-centered is-size-7">
+                    {format!("{}/{} votes to skip", props.skip_votes, props.skip_votes_needed)}
+                </div>
+            }
         </div>
         </>
     }
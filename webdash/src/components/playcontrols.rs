@@ -5,12 +5,12 @@ use yew::{
     html,
 };
 
-use gloo_net::http::Request;
 use yew_hooks::prelude::*;
 
 use yew_toast::*;
+use crate::components::helpers::post_api;
 
-use model::{web::{ReplyStatus, ApToggleRequest}, MusicStateStatus};
+use model::{web::ApToggleRequest, MusicStateStatus};
 
 #[derive(Serialize, Clone)]
 struct NoBody {}
@@ -18,20 +18,7 @@ struct NoBody {}
 #[hook]
 fn use_gen_callback<T: Serialize + Clone + 'static>(path: &'static str, body: T, toast_string: Option<&'static str>, tdis: UseReducerDispatcher<ToastList>) -> Callback<MouseEvent> {
     let ahandle = use_async(async move {
-        let resp = Request::post(format!("/api/{}", path).as_str())
-            .json(&body).unwrap()
-            .send().await.unwrap();
-        if !resp.ok() {
-            let resp = resp.json::<ReplyStatus>().await;
-            if let Ok(msg) = resp {
-                tdis.dispatch(toast_error!(msg.error));
-            } else {
-                log::error!("bad response from backend: {:?}", resp);
-                tdis.dispatch(toast_error!("Bad data from API, check console".into()));
-            }
-
-            return Err(())
-        }
+        post_api(path, &body, &tdis).await?;
 
         if let Some(toast) = toast_string {
             tdis.dispatch(toast_info!(toast.into()));
@@ -59,6 +46,8 @@ pub fn playcontrols(props: &PlayControlsProps) -> Html {
     let onskip = use_gen_callback("skip", NoBody{}, None, toast.dispatcher());
     let onstop = use_gen_callback("stop", NoBody{}, None, toast.dispatcher());
     let onplay = use_gen_callback("start", NoBody{}, None, toast.dispatcher());
+    let onpause = use_gen_callback("pause", NoBody{}, None, toast.dispatcher());
+    let onresume = use_gen_callback("resume", NoBody{}, None, toast.dispatcher());
 
     let onenableap = use_gen_callback("autoplay/toggle", ApToggleRequest{ enabled: true }, None, toast.dispatcher());
     let ondisableap = use_gen_callback("autoplay/toggle", ApToggleRequest{ enabled: false }, None, toast.dispatcher());
@@ -89,6 +78,21 @@ pub fn playcontrols(props: &PlayControlsProps) -> Html {
                 {
                     match props.status {
                         MusicStateStatus::Playing => html! {
+                            <div class={iconclass} onclick={onpause} title="Pause Playback">
+                                <yew_feather::Pause />
+                            </div>
+                        },
+                        MusicStateStatus::Paused => html! {
+                            <div class={iconclass} onclick={onresume} title="Resume Playback">
+                                <yew_feather::Play />
+                            </div>
+                        },
+                        _ => html! {}
+                    }
+                }
+                {
+                    match props.status {
+                        MusicStateStatus::Playing | MusicStateStatus::Paused => html! {
                             <div class={iconclass} onclick={onstop} title="Stop Playback">
                                 <yew_feather::Square />
                             </div>
@@ -1,13 +1,89 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
 use yew::{
     prelude::*,
     function_component,
     html,
 };
 use model::{
+    MinstrelUserId,
     MinstrelWebData,
+    RequestId,
+    web::{QueueMoveRequest, ReplyData, ReplyStatus},
 };
 
-use crate::components::{songrow::*, UserContext};
+use gloo_net::http::Request;
+use yew_toast::*;
+
+use crate::components::{songrow::*, UserContext, error_toast};
+
+
+// Fire off a queue move, the resulting broadcast will redraw the list
+fn post_queue_move(id: RequestId, target: RequestId, tdis: UseReducerDispatcher<ToastList>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let resp = Request::post("/api/queue/move")
+            .json(&QueueMoveRequest { id, target }).unwrap()
+            .send().await.unwrap();
+
+        if !resp.ok() {
+            match resp.json::<ReplyStatus>().await {
+                Ok(msg) => tdis.dispatch(error_toast(msg.code, format!("Error moving song: {}", msg.error))),
+                Err(e) => {
+                    log::error!("Server returned garbage: {:?}", e);
+                    tdis.dispatch(toast_error!("Server returned some garbage, check console".into()));
+                },
+            }
+        }
+    });
+}
+
+
+/// Urls of the songs the logged-in user likes
+#[derive(Default, PartialEq)]
+struct LikedSongs(HashSet<String>);
+
+enum LikedAction {
+    Set(HashSet<String>),
+    Update(String, bool),
+}
+
+impl Reducible for LikedSongs {
+    type Action = LikedAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        match action {
+            LikedAction::Set(urls) => Rc::new(Self(urls)),
+            LikedAction::Update(url, liked) => {
+                let mut urls = self.0.clone();
+                match liked {
+                    true => urls.insert(url),
+                    false => urls.remove(&url),
+                };
+                Rc::new(Self(urls))
+            },
+        }
+    }
+}
+
+// Fetch the user's liked songs, or forget them when logged out
+fn fetch_liked(muid: Option<MinstrelUserId>, liked: UseReducerDispatcher<LikedSongs>) {
+    if muid.is_none() {
+        liked.dispatch(LikedAction::Set(HashSet::new()));
+        return;
+    }
+
+    wasm_bindgen_futures::spawn_local(async move {
+        let resp = Request::post("/api/likes").send().await.unwrap();
+
+        match resp.json::<ReplyStatus>().await {
+            Ok(ReplyStatus { data: Some(ReplyData::LikedSongs(songs)), .. }) =>
+                liked.dispatch(LikedAction::Set(songs.into_iter().map(|s| s.url).collect())),
+            Ok(msg) => log::error!("Failed to fetch liked songs: {}", msg.error),
+            Err(e) => log::error!("Server returned garbage: {:?}", e),
+        }
+    });
+}
 
 
 #[derive(Properties, PartialEq)]
@@ -46,20 +122,20 @@ pub fn songlisttabs(props: &SongListTabsProps) -> Html {
     let usercontext = use_context::<UserContext>().unwrap();
     let muid = usercontext.current_user.as_ref().map(|ui| ui.id);
 
-    // Pre-index logged-in user's songs
-    let mut upcoming = Vec::new();
-    let mut i = 0;
-    for up in props.data.upcoming.iter() {
-        upcoming.push(match muid.map(|muid| muid == up.requested_by.id) {
-            Some(true) => {
-                let ret = (Some(i), up.clone());
-                i += 1;
-                ret
-            },
-            _ => (None, up.clone()),
-        });
+    let toast = use_context::<ToastContext>().unwrap();
+    // Request currently being dragged, if any
+    let dragging = use_state(|| None::<RequestId>);
+
+    let liked = use_reducer(LikedSongs::default);
+    {
+        let liked = liked.dispatcher();
+        use_effect_with_deps(move |muid| {
+            fetch_liked(*muid, liked);
+            || ()
+        }, muid);
     }
 
+
     html! {
         <div class="tabview">
         <div class="tabs">
@@ -79,22 +155,55 @@ pub fn songlisttabs(props: &SongListTabsProps) -> Html {
                         <>
                         {
                             for props.data.queue.iter().map(|e| {
+                                // Users can drag their own requests around, and drop them on any spot in the queue
+                                let owned = muid == Some(e.requested_by.id);
+                                let id = e.id;
+
+                                let ondragstart = {
+                                    let dragging = dragging.clone();
+                                    Callback::from(move |_: DragEvent| dragging.set(Some(id)))
+                                };
+                                let ondragend = {
+                                    let dragging = dragging.clone();
+                                    Callback::from(move |_: DragEvent| dragging.set(None))
+                                };
+                                let ondragover = {
+                                    let dragging = dragging.is_some();
+                                    Callback::from(move |e: DragEvent| {
+                                        // Needed to allow dropping here at all
+                                        if dragging {
+                                            e.prevent_default();
+                                        }
+                                    })
+                                };
+                                let ondrop = {
+                                    let dragging = dragging.clone();
+                                    let tdis = toast.dispatcher();
+                                    Callback::from(move |e: DragEvent| {
+                                        e.prevent_default();
+                                        if let Some(dragged) = *dragging {
+                                            if dragged != id {
+                                                post_queue_move(dragged, id, tdis.clone());
+                                            }
+                                        }
+                                        dragging.set(None);
+                                    })
+                                };
+
                                 html! {
-                                <SongRow song={e.clone()} enqueued={true}/>
+                                <div draggable={owned.to_string()} {ondragstart} {ondragend} {ondragover} {ondrop}>
+                                    <SongRow song={e.clone()} enqueued={true} removable={owned}/>
+                                </div>
                                 }
                             })
                         }
                         </>
                         <>
                         {
-                            for upcoming.drain(..).map(|(i,e)| {
-                                match i {
-                                    Some(i) => html! {
-                                        <SongRow song={e} index={i}/>
-                                    },
-                                    _ => html! {
-                                        <SongRow song={e}/>
-                                    }
+                            for props.data.upcoming.iter().map(|e| {
+                                let owned = muid == Some(e.requested_by.id);
+                                html! {
+                                    <SongRow song={e.clone()} bumpable={owned}/>
                                 }
                             })
                         }
@@ -112,8 +221,14 @@ pub fn songlisttabs(props: &SongListTabsProps) -> Html {
                     html! {
                     {
                         for props.data.history.iter().map(|e| {
+                            let onlike = {
+                                let liked = liked.dispatcher();
+                                let url = e.song.url.clone();
+                                Callback::from(move |l| liked.dispatch(LikedAction::Update(url.clone(), l)))
+                            };
+
                             html! {
-                                <SongRow song={e.clone()} />
+                                <SongRow song={e.clone()} liked={muid.map(|_| liked.0.contains(&e.song.url))} {onlike} />
                             }
                         })
                     }
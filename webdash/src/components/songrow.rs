@@ -1,12 +1,12 @@
 use gloo_net::http::Request;
-use model::web::{ApBumpRequest, ReplyStatus};
+use model::web::{ApBumpRequest, LikeRequest, QueueRemoveRequest, ReplyStatus};
 use yew::{
     prelude::*,
     function_component,
     html,
 };
 use model::{
-    Song, SongRequest,
+    Song, SongRequest, RequestId,
 };
 
 use yew_hooks::{
@@ -15,7 +15,10 @@ use yew_hooks::{
 
 use yew_toast::{ToastContext, toast_info, toast_error};
 
-use crate::components::helpers::duration_text;
+use crate::components::helpers::{
+    duration_text,
+    error_toast,
+};
 use crate::components::requester::*;
 
 
@@ -39,17 +42,17 @@ pub fn song_text(props: &SongTextProps) -> Html {
 
 #[derive(Properties, PartialEq)]
 pub struct BumpProps {
-    pub index: usize
+    pub id: RequestId,
 }
 
 #[function_component(BumpSongButton)]
 pub fn bump_song_button(props: &BumpProps) -> Html {
     let toastcontext = use_context::<ToastContext>().unwrap();
-    let index = props.index;
+    let id = props.id;
 
     let bump = use_async(async move {
         let resp = Request::post("/api/autoplay/bump")
-            .json(&ApBumpRequest { index } ).unwrap()
+            .json(&ApBumpRequest { id } ).unwrap()
             .send().await.unwrap();
 
         if resp.ok() {
@@ -58,7 +61,7 @@ pub fn bump_song_button(props: &BumpProps) -> Html {
         } else {
             let resp = resp.json::<ReplyStatus>().await;
             if let Ok(resp) = resp {
-                toastcontext.dispatch(toast_error!(format!("Error bumping song: {} song from upcoming", resp.error)));
+                toastcontext.dispatch(error_toast(resp.code, format!("Error bumping song from upcoming: {}", resp.error)));
             } else {
                 log::error!("Server returned garbage: {:?}", resp);
                 toastcontext.dispatch(toast_error!("Server returned some garbage, check console".into()));
@@ -82,11 +85,111 @@ pub fn bump_song_button(props: &BumpProps) -> Html {
 }
 
 
+#[function_component(RemoveQueuedButton)]
+pub fn remove_queued_button(props: &BumpProps) -> Html {
+    let toastcontext = use_context::<ToastContext>().unwrap();
+    let id = props.id;
+
+    let remove = use_async(async move {
+        let resp = Request::post("/api/queue/remove")
+            .json(&QueueRemoveRequest { id } ).unwrap()
+            .send().await.unwrap();
+
+        if resp.ok() {
+            toastcontext.dispatch(toast_info!("Removed song from queue".into()));
+            Ok(())
+        } else {
+            let resp = resp.json::<ReplyStatus>().await;
+            if let Ok(resp) = resp {
+                toastcontext.dispatch(error_toast(resp.code, format!("Error removing song from queue: {}", resp.error)));
+            } else {
+                log::error!("Server returned garbage: {:?}", resp);
+                toastcontext.dispatch(toast_error!("Server returned some garbage, check console".into()));
+            }
+
+            Err(())
+        }
+    });
+
+    let remove_callback = {
+        Callback::from(move |_| {
+            remove.run();
+        })
+    };
+
+    html! {
+        <div onclick={remove_callback} class="is-flex bumpicon mr-2" title="Remove from queue">
+            <yew_feather::XCircle />
+        </div>
+    }
+}
+
+
+#[derive(Properties, PartialEq)]
+pub struct LikeProps {
+    pub id: RequestId,
+    pub liked: bool,
+    // Called with the new liked state once the server has it
+    pub onchange: Callback<bool>,
+}
+
+#[function_component(LikeSongButton)]
+pub fn like_song_button(props: &LikeProps) -> Html {
+    let toastcontext = use_context::<ToastContext>().unwrap();
+    let id = props.id;
+    let liked = !props.liked;
+
+    let like_callback = {
+        let onchange = props.onchange.clone();
+        Callback::from(move |_| {
+            let tdis = toastcontext.dispatcher();
+            let onchange = onchange.clone();
+
+            wasm_bindgen_futures::spawn_local(async move {
+                let resp = Request::post("/api/like")
+                    .json(&LikeRequest { id: Some(id), liked }).unwrap()
+                    .send().await.unwrap();
+
+                if resp.ok() {
+                    onchange.emit(liked);
+                } else {
+                    match resp.json::<ReplyStatus>().await {
+                        Ok(msg) => tdis.dispatch(error_toast(msg.code, format!("Error liking song: {}", msg.error))),
+                        Err(e) => {
+                            log::error!("Server returned garbage: {:?}", e);
+                            tdis.dispatch(toast_error!("Server returned some garbage, check console".into()));
+                        },
+                    }
+                }
+            });
+        })
+    };
+
+    let (class, title) = match props.liked {
+        true => ("is-flex bumpicon is-liked mr-2", "Unlike"),
+        false => ("is-flex bumpicon mr-2", "Like"),
+    };
+
+    html! {
+        <div onclick={like_callback} {class} {title}>
+            <yew_feather::Heart />
+        </div>
+    }
+}
+
+
 #[derive(Properties, PartialEq)]
 pub struct SongRowProps {
     pub song: SongRequest,
     pub enqueued: Option<bool>,
-    pub index: Option<usize>,
+    // Controls only shown for the logged-in user's own requests
+    pub bumpable: Option<bool>,
+    pub removable: Option<bool>,
+    // Whether the logged-in user likes this song, None to not show the like button
+    #[prop_or_default]
+    pub liked: Option<bool>,
+    #[prop_or_default]
+    pub onlike: Callback<bool>,
 }
 
 #[function_component(SongRow)]
@@ -125,13 +228,23 @@ pub fn song_row(props: &SongRowProps) -> Html {
                 <SongText song={song.clone()} />
             </div>
             {
-                if let Some(index) = props.index {
-                    html! {
-                        // TODO: consider a pop-up menu if more controls are to be added
-                        <BumpSongButton index={index} />
-                    }
-                } else {
-                    html! {}
+                match (props.bumpable, props.removable) {
+                    // TODO: consider a pop-up menu if more controls are to be added
+                    (Some(true), _) => html! {
+                        <BumpSongButton id={props.song.id} />
+                    },
+                    (_, Some(true)) => html! {
+                        <RemoveQueuedButton id={props.song.id} />
+                    },
+                    _ => html! {},
+                }
+            }
+            {
+                match props.liked {
+                    Some(liked) => html! {
+                        <LikeSongButton id={props.song.id} {liked} onchange={props.onlike.clone()} />
+                    },
+                    None => html! {},
                 }
             }
             <div class="column is-narrow is-flex is-flex-direction-column is-justify-content-center mr-2">
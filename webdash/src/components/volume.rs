@@ -0,0 +1,73 @@
+use yew::{
+    prelude::*,
+    function_component,
+    html,
+};
+use web_sys::HtmlInputElement;
+
+use gloo_net::http::Request;
+
+use yew_toast::*;
+use crate::components::error_toast;
+
+use model::{
+    web::{ReplyStatus, VolumeRequest},
+    MAX_VOLUME,
+};
+
+#[derive(Properties, PartialEq)]
+pub struct VolumeSliderProps {
+    pub volume: u8,
+}
+
+#[function_component(VolumeSlider)]
+pub fn volumeslider(props: &VolumeSliderProps) -> Html {
+    let toast = use_context::<ToastContext>().unwrap();
+
+    // Only send the volume once the slider is let go, the broadcast will bring everyone else along
+    let onchange = {
+        let tdis = toast.dispatcher();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let volume = match input.value().parse::<u8>() {
+                Ok(v) => v,
+                Err(_) => return,
+            };
+
+            let tdis = tdis.clone();
+            wasm_bindgen_futures::spawn_local(async move {
+                let resp = Request::post("/api/volume")
+                    .json(&VolumeRequest { volume }).unwrap()
+                    .send().await.unwrap();
+
+                if !resp.ok() {
+                    match resp.json::<ReplyStatus>().await {
+                        Ok(msg) => tdis.dispatch(error_toast(msg.code, msg.error)),
+                        Err(e) => {
+                            log::error!("bad response from backend: {:?}", e);
+                            tdis.dispatch(toast_error!("Bad data from API, check console".into()));
+                        },
+                    }
+                }
+            });
+        })
+    };
+
+    html! {
+        <div class="columns is-centered is-mobile is-vcentered">
+            <div class="column is-narrow is-flex" title="Volume">
+                if props.volume == 0 {
+                    <yew_feather::VolumeX />
+                } else {
+                    <yew_feather::Volume2 />
+                }
+            </div>
+            <div class="column is-6 is-flex">
+                <input type="range" min="0" max={MAX_VOLUME.to_string()} value={props.volume.to_string()} {onchange} style="width: 100%;"/>
+            </div>
+            <div class="column is-narrow">
+                <span>{ format!("{}%", props.volume) }</span>
+            </div>
+        </div>
+    }
+}
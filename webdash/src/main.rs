@@ -3,7 +3,10 @@ use yew::{
     html
 };
 
-use model::{MinstrelWebData, MinstrelBroadcast};
+use std::rc::Rc;
+
+use model::{MinstrelWebData, MinstrelBroadcast, MinstrelEvent, MusicStateStatus};
+use model::web::WS_RESYNC;
 
 mod components;
 use components::*;
@@ -12,16 +15,50 @@ use yew_hooks::use_websocket_with_options;
 use yew_toast::*;
 
 
+/// The dashboard's copy of the music state, kept up to date from the websocket's events
+#[derive(Default, PartialEq)]
+struct DashState {
+    data: Option<MinstrelWebData>,
+    // Missed an event, waiting on the whole state again
+    stale: bool,
+}
+
+enum DashAction {
+    State(MinstrelWebData),
+    Event(u64, MinstrelEvent),
+}
+
+impl Reducible for DashState {
+    type Action = DashAction;
+
+    fn reduce(self: Rc<Self>, action: Self::Action) -> Rc<Self> {
+        match action {
+            DashAction::State(data) => Rc::new(Self { data: Some(data), stale: false }),
+            DashAction::Event(seq, event) => {
+                // Nothing to apply it to, a fresh state is on its way
+                let mut data = match &self.data {
+                    Some(d) if !self.stale => d.clone(),
+                    _ => return self,
+                };
+
+                match data.apply(seq, event) {
+                    true => Rc::new(Self { data: Some(data), stale: false }),
+                    false => Rc::new(Self { data: self.data.clone(), stale: true }),
+                }
+            },
+        }
+    }
+}
+
 #[function_component(FDash)]
 pub fn fdash() -> Html {
-    // TODO: fetch info on page load
-    let data: UseStateHandle<Option<MinstrelWebData>> = use_state(|| None);
+    let state = use_reducer(DashState::default);
 
     let toastlist = use_reducer(|| ToastList::new());
     let userinfo = use_reducer(|| LoginStatus { current_user: None });
 
-    let _ws = {
-        let data = data.clone();
+    let ws = {
+        let state = state.dispatcher();
 
         let window = web_sys::window().unwrap();
         let protocol = window.location().protocol();
@@ -50,10 +87,11 @@ pub fn fdash() -> Html {
             //onopen:(),
             onmessage: Some(Box::new(move |message| {
                 match serde_json::from_str::<MinstrelBroadcast>(&message).unwrap() {
-                    MinstrelBroadcast::MusicState(newdata) => data.set(Some(newdata)),
+                    MinstrelBroadcast::MusicState(newdata) => state.dispatch(DashAction::State(newdata)),
+                    MinstrelBroadcast::Event { seq, event } => state.dispatch(DashAction::Event(seq, event)),
                     MinstrelBroadcast::Error(err) =>{
-                        log::info!("error from backend: {}", err);
-                        tb_mess.dispatch(toast_error!(err));
+                        log::info!("error from backend: {:?}", err);
+                        tb_mess.dispatch(error_toast(Some(err.code), err.message));
                     }
                 };
 
@@ -76,6 +114,18 @@ pub fn fdash() -> Html {
         })
     };
 
+    // Missed an event somewhere, ask for everything again
+    {
+        let ws = ws.clone();
+        use_effect_with_deps(move |stale| {
+            if *stale {
+                log::warn!("missed an event from the backend, resyncing");
+                ws.send(WS_RESYNC.into());
+            }
+            || ()
+        }, state.stale);
+    }
+
     html! {
         <div class="container">
         <div class="background-noise" />
@@ -83,7 +133,7 @@ pub fn fdash() -> Html {
         <ContextProvider<ToastContext> context={toastlist}>
         <ToastTray />
 
-        if let Some(data) = &*data.clone() {
+        if let Some(data) = &state.data {
         // m-0 set to override the negative margins set by columns
         //  no idea why columns is like that, but centers the main div to the container->viewport
             <div class="columns is-vcentered m-0 is-text-shadowed">
@@ -95,7 +145,8 @@ pub fn fdash() -> Html {
                                 <>
                                 <BackgroundImage url={np.song.thumbnail.clone()} />
                                     <div class="column is-full">
-                                        <NowPlaying song={np.clone()} progress={data.song_progress}/>
+                                        <NowPlaying song={np.clone()} progress={data.song_progress} paused={data.status == MusicStateStatus::Paused}
+                                            skip_votes={data.skip_votes} skip_votes_needed={data.skip_votes_needed}/>
                                     </div>
 
                                 </>
@@ -110,7 +161,13 @@ pub fn fdash() -> Html {
                         <div class="column is-full">
                             <PlayControls status={data.status.clone()} ap_enabled={data.ap_enabled}/>
                         </div>
+                        <div class="column is-full">
+                            <VolumeSlider volume={data.volume}/>
+                        </div>
                     </IsLoggedIn>
+                        <div class="column is-full">
+                            <ListenButton />
+                        </div>
                     </div>
                 </div>
                 <div class="column container is-half fullheight">